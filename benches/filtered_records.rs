@@ -0,0 +1,40 @@
+//!
+//! Compares the cost of a record discarded by every writer with the cost of a record 
+//! formatted and written to a null writer. Run with `cargo bench`.
+//!
+use std::{hint::black_box, time::Instant};
+
+use log::{Level, LevelFilter, Log, Record};
+use rslogger::Logger;
+
+const RECORDS: u32 = 1_000_000;
+
+fn bench(name: &str, logger: &Logger) {
+    let start = Instant::now();
+    for i in 0..RECORDS {
+        logger.log(&Record::builder()
+            .level(Level::Trace)
+            .target("bench")
+            .args(format_args!("iteration {}", black_box(i)))
+            .build());
+    }
+    let elapsed = start.elapsed();
+    println!("{name}: {:?} per record", elapsed / RECORDS);
+}
+
+fn main() {
+    // The logger level lets trace through, but the only writer discards it.
+    let filtered = Logger::new()
+        .with_level(LevelFilter::Trace)
+        .with_thread()
+        .with_target()
+        .add_writer_stdout_with_level(false, None, LevelFilter::Info);
+    bench("discarded by every writer", &filtered);
+
+    let written = Logger::new()
+        .with_level(LevelFilter::Trace)
+        .with_thread()
+        .with_target()
+        .add_writer_null(false, None);
+    bench("formatted and written", &written);
+}
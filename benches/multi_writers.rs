@@ -0,0 +1,63 @@
+//!
+//! Counts the allocations of a record written by three separate thread writers,
+//! sharing the formatted line instead of copying it for each writer. Run with `cargo bench`.
+//!
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Instant,
+};
+
+use log::{Level, LevelFilter, Log, Record};
+use rslogger::Logger;
+
+const RECORDS: usize = 100_000;
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+fn bench(name: &str, logger: &Logger) {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    for i in 0..RECORDS {
+        logger.log(&Record::builder()
+            .level(Level::Info)
+            .args(format_args!("iteration {i}"))
+            .build());
+    }
+    logger.flush();
+    let elapsed = start.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+    println!("{name}: {:?} and {} allocations per record", elapsed / RECORDS as u32, allocations / RECORDS);
+}
+
+fn main() {
+    let one_writer = Logger::new()
+        .with_level(LevelFilter::Info)
+        .without_timestamps()
+        .add_writer_null(true, None);
+    bench("one separate thread writer", &one_writer);
+
+    let three_writers = Logger::new()
+        .with_level(LevelFilter::Info)
+        .without_timestamps()
+        .add_writer_null(true, None)
+        .add_writer_null(true, None)
+        .add_writer_null(true, None);
+    bench("three separate thread writers", &three_writers);
+}
@@ -0,0 +1,54 @@
+//!
+//! Measures the throughput of a separate thread writer receiving bursts of records,
+//! including the time to drain the queue at flush. Run with `cargo bench`.
+//!
+use std::time::Instant;
+
+use log::{Level, LevelFilter, Log, Record};
+use rslogger::Logger;
+
+const RECORDS: u32 = 1_000_000;
+
+fn bench(name: &str, logger: &Logger) {
+    let start = Instant::now();
+    for i in 0..RECORDS {
+        logger.log(&Record::builder()
+            .level(Level::Info)
+            .target("bench")
+            .args(format_args!("iteration {i}"))
+            .build());
+    }
+    let sent = start.elapsed();
+    logger.flush();
+    println!(
+        "{name}: {:?} per record sent, {:?} per record written",
+        sent / RECORDS,
+        start.elapsed() / RECORDS
+    );
+}
+
+fn main() {
+    let logger = Logger::new()
+        .with_level(LevelFilter::Info)
+        .without_timestamps()
+        .add_writer_null(true, Some(64 * 1024));
+    bench("burst on a separate thread writer", &logger);
+
+    // The layout work (timestamps, target, thread) is moved to the writer thread.
+    let inline = Logger::new()
+        .with_level(LevelFilter::Info)
+        .with_utc_timestamps()
+        .with_thread()
+        .with_target()
+        .add_writer_null(true, Some(64 * 1024));
+    bench("laid out inline", &inline);
+
+    let deferred = Logger::new()
+        .with_level(LevelFilter::Info)
+        .with_utc_timestamps()
+        .with_thread()
+        .with_target()
+        .with_deferred_formatting()
+        .add_writer_null(true, Some(64 * 1024));
+    bench("laid out on the writer thread", &deferred);
+}
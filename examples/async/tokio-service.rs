@@ -0,0 +1,36 @@
+use std::path::PathBuf;
+
+use rslogger::{Logger, OverflowPolicy, WriterOptions};
+use log::{info, warn};
+
+fn main() {
+    let runtime = tokio::runtime::Builder::new_multi_thread().build().unwrap();
+
+    runtime.block_on(async {
+        // The file writer is a task of this runtime, writing through a tokio file: no dedicated thread.
+        // With a bounded queue dropping the newest records, logging never waits for the disk.
+        Logger::new()
+            .with_level(log::LevelFilter::Info)
+            .with_utc_timestamps()
+            .with_overflow_policy(OverflowPolicy::DropNewest)
+            .add_writer_file_async_opts(PathBuf::from("./LOGS/tokio_service.log"), WriterOptions {
+                queue_capacity: Some(10_000),
+                ..WriterOptions::default()
+            })
+            .init().unwrap();
+
+        let requests: Vec<_> = (0..10).map(|id| tokio::spawn(async move {
+            info!("Handling request {id}");
+            tokio::task::yield_now().await;
+            if id % 4 == 0 {
+                warn!("Request {id} is slow");
+            }
+        })).collect();
+        for request in requests {
+            request.await.unwrap();
+        }
+
+        // Flushed from the runtime, before it's dropped.
+        log::logger().flush();
+    });
+}
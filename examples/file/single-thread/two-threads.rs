@@ -1,7 +1,7 @@
 use std::{path::PathBuf, thread};
 
 use rslogger::Logger;
-use log::{info, warn, error};
+use log::{info, warn};
 
 /// With this configuration, each thread is writing on the file separately.
 /// The logging is happening respectively on Thread 1 and Thread 2 they will contend the resource 
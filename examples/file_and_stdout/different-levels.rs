@@ -1,7 +1,7 @@
 use std::{path::PathBuf, thread};
 
 use rslogger::Logger;
-use log::{info, warn, error, trace};
+use log::{info, warn, error, trace, Level};
 
 fn main() {
     Logger::new()
@@ -9,12 +9,12 @@ fn main() {
         .with_local_timestamps()
         .with_thread()
         .add_writer_stdout(true, Some(1000))
-        // This will trace only error or less
-        .add_writer_file_with_level(PathBuf::from("./LOGS/genercs_1.log"), true, Some(10), log::LevelFilter::Error)
-        // This will trace only info or less
-        .add_writer_file_with_level(PathBuf::from("./LOGS/genercs_2.log"), true, Some(10000), log::LevelFilter::Info)
-        // This will trace only trace or less (default)
-        .add_writer_file(PathBuf::from("./LOGS/genercs_3.log"), true, None)
+        // genercs_1 traces only error or less, genercs_2 info or less and genercs_3 everything
+        .split_by_level(PathBuf::from("./LOGS"), &[
+            (Level::Error, "genercs_1.log"),
+            (Level::Info, "genercs_2.log"),
+            (Level::Trace, "genercs_3.log"),
+        ], true, Some(10000))
         .add_writer_stdout(false, None)
         .init().unwrap();
 
@@ -0,0 +1,25 @@
+use std::path::PathBuf;
+
+use rslogger::Logger;
+
+fn main() {
+    Logger::new()
+        .with_level(log::LevelFilter::Trace)
+        .with_utc_timestamps()
+        .with_target()
+        .add_writer_file(PathBuf::from("./LOGS/tracing_mixed.log"), true, None)
+        .init().unwrap();
+
+    // Forward the tracing events to the logger.
+    rslogger::tracing_bridge::init().unwrap();
+
+    log::info!("This is a log info");
+    tracing::info!("This is a tracing info");
+
+    let span = tracing::info_span!("request", id = 42);
+    let _entered = span.enter();
+    tracing::warn!(user = "bob", "This is a tracing warn inside a span");
+    log::warn!("This is a log warn inside a span (log records don't carry spans)");
+
+    log::logger().flush();
+}
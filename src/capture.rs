@@ -0,0 +1,318 @@
+use std::{io::{self, Write}, sync::{Arc, Mutex, PoisonError}};
+
+use crate::LOGGER;
+
+///
+/// Cloneable handle over the lines captured by [`capture`] or by a capture writer
+/// (see [`Logger::add_writer_capture`](crate::Logger::add_writer_capture)).
+/// All the clones share the same lines and records.
+///
+#[derive(Clone, Default)]
+pub struct CaptureHandle {
+    lines: Arc<Mutex<Vec<String>>>,
+    /// The records behind the lines, whatever their layout.
+    records: Arc<Mutex<Vec<CapturedRecord>>>,
+    /// Bytes written after the last newline, waiting for the rest of the line.
+    partial: Arc<Mutex<Vec<u8>>>,
+}
+
+///
+/// A record received by a capture handle: its level, target and message, as logged.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CapturedRecord {
+    pub level: log::Level,
+    pub target: String,
+    pub message: String,
+}
+
+impl CaptureHandle {
+
+    ///
+    /// Returns a copy of the lines captured so far.
+    ///
+    pub fn lines(&self) -> Vec<String> {
+        self.lines.lock().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    ///
+    /// Returns the text captured so far, with the line not terminated yet if any
+    /// (e.g. the last record of a writer without final suffix).
+    ///
+    pub fn snapshot(&self) -> String {
+        let mut snapshot = self.lines().join("\n");
+        let partial = self.partial.lock().unwrap_or_else(PoisonError::into_inner);
+        if !partial.is_empty() {
+            if !snapshot.is_empty() {
+                snapshot.push('\n');
+            }
+            snapshot.push_str(&String::from_utf8_lossy(&partial));
+        }
+        snapshot
+    }
+
+    ///
+    /// Returns a copy of the records captured so far, in the order they were logged.
+    /// Unlike the lines, they don't depend on the layout of the logger.
+    ///
+    pub fn records(&self) -> Vec<CapturedRecord> {
+        self.records.lock().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    ///
+    /// Removes all the lines and records captured so far.
+    ///
+    pub fn clear(&self) {
+        self.lines.lock().unwrap_or_else(PoisonError::into_inner).clear();
+        self.records.lock().unwrap_or_else(PoisonError::into_inner).clear();
+    }
+
+    ///
+    /// Returns true if any captured line contains `substr`.
+    ///
+    pub fn contains(&self, substr: &str) -> bool {
+        self.lines.lock().unwrap_or_else(PoisonError::into_inner).iter().any(|line| line.contains(substr))
+    }
+
+    pub(crate) fn push(&self, line: &str) {
+        self.lines.lock().unwrap_or_else(PoisonError::into_inner).push(line.to_string());
+    }
+
+    pub(crate) fn push_record(&self, level: log::Level, target: &str, message: std::fmt::Arguments) {
+        self.records.lock().unwrap_or_else(PoisonError::into_inner).push(CapturedRecord {
+            level,
+            target: target.to_string(),
+            message: message.to_string(),
+        });
+    }
+}
+
+/// Maximum number of captured lines listed in the message of a failed assertion.
+#[cfg(feature = "test-util")]
+const ASSERT_CONTEXT_LINES: usize = 20;
+
+#[cfg(feature = "test-util")]
+impl CaptureHandle {
+
+    ///
+    /// Implementation of [`assert_logged!`](crate::assert_logged) and [`assert_not_logged!`](crate::assert_not_logged)
+    /// with `contains:`. A record matches if it has the level, the target and its message contains `contains`.
+    /// # Panics
+    /// If the presence of a matching record differs from `expected`, listing the last captured lines.
+    ///
+    #[doc(hidden)]
+    pub fn assert_logged(&self, level: log::Level, target: Option<&str>, contains: &str, expected: bool) {
+        self.assert_record(level, target, &format!("containing \"{contains}\""), |message| message.contains(contains), expected);
+    }
+
+    ///
+    /// Implementation of [`assert_logged!`](crate::assert_logged) and [`assert_not_logged!`](crate::assert_not_logged)
+    /// with `matches:`. A record matches if it has the level, the target and the regex matches its message.
+    /// # Panics
+    /// If the regex is invalid, or if the presence of a matching record differs from `expected`.
+    ///
+    #[doc(hidden)]
+    pub fn assert_logged_matching(&self, level: log::Level, target: Option<&str>, pattern: &str, expected: bool) {
+        let regex = regex::Regex::new(pattern).unwrap_or_else(|error| panic!("invalid regex \"{pattern}\": {error}"));
+        self.assert_record(level, target, &format!("matching \"{pattern}\""), |message| regex.is_match(message), expected);
+    }
+
+    fn assert_record(&self, level: log::Level, target: Option<&str>, description: &str, matches: impl Fn(&str) -> bool, 
+        expected: bool) {
+        let found = self.records.lock().unwrap_or_else(PoisonError::into_inner).iter().any(|record| {
+            record.level == level
+                && target.is_none_or(|target| record.target == target)
+                && matches(&record.message)
+        });
+
+        if found != expected {
+            let lines = self.lines();
+            let target_description = target.map(|target| format!(" with target \"{target}\"")).unwrap_or_default();
+            let mut failure = format!(
+                "expected {} {level} record{target_description} {description}\nlast captured lines ({} total):",
+                if expected { "a" } else { "no" },
+                lines.len(),
+            );
+            for line in lines.iter().skip(lines.len().saturating_sub(ASSERT_CONTEXT_LINES)) {
+                failure.push_str("\n    ");
+                failure.push_str(line);
+            }
+            panic!("{failure}");
+        }
+    }
+}
+
+///
+/// Asserts that a capture handle received a record of the given level whose message contains a string,
+/// or matches a regex with `matches:`, optionally checking the target too.
+/// The records are matched as logged, whatever the layout of the lines (levels, CSV, timestamps).
+/// On failure, the last captured lines are listed. Requires the `test-util` feature.
+/// # Example
+/// ```should_panic
+/// use log::Level;
+/// use rslogger::{assert_logged, Logger};
+/// let (logger, captured) = Logger::new().without_timestamps().add_writer_capture();
+/// log::set_max_level(log::LevelFilter::Trace);
+/// log::info!(logger: logger, "disk at 95%");
+///
+/// // Panics with:
+/// // expected a WARN record containing "disk almost full"
+/// // last captured lines (1 total):
+/// //     -[][] -> {INFO} disk at 95%
+/// assert_logged!(captured, Level::Info, matches: r"disk at \d+%");
+/// assert_logged!(captured, Level::Warn, contains: "disk almost full");
+/// ```
+///
+#[cfg(feature = "test-util")]
+#[macro_export]
+macro_rules! assert_logged {
+    ($handle:expr, $level:expr, target: $target:expr, contains: $contains:expr $(,)?) => {
+        $handle.assert_logged($level, Some($target), $contains, true)
+    };
+    ($handle:expr, $level:expr, contains: $contains:expr $(,)?) => {
+        $handle.assert_logged($level, None, $contains, true)
+    };
+    ($handle:expr, $level:expr, target: $target:expr, matches: $pattern:expr $(,)?) => {
+        $handle.assert_logged_matching($level, Some($target), $pattern, true)
+    };
+    ($handle:expr, $level:expr, matches: $pattern:expr $(,)?) => {
+        $handle.assert_logged_matching($level, None, $pattern, true)
+    };
+}
+
+///
+/// Asserts that a capture handle did not receive a record of the given level containing a string,
+/// or matching a regex with `matches:`.
+/// Takes the same arguments as [`assert_logged!`]. Requires the `test-util` feature.
+///
+#[cfg(feature = "test-util")]
+#[macro_export]
+macro_rules! assert_not_logged {
+    ($handle:expr, $level:expr, target: $target:expr, contains: $contains:expr $(,)?) => {
+        $handle.assert_logged($level, Some($target), $contains, false)
+    };
+    ($handle:expr, $level:expr, contains: $contains:expr $(,)?) => {
+        $handle.assert_logged($level, None, $contains, false)
+    };
+    ($handle:expr, $level:expr, target: $target:expr, matches: $pattern:expr $(,)?) => {
+        $handle.assert_logged_matching($level, Some($target), $pattern, false)
+    };
+    ($handle:expr, $level:expr, matches: $pattern:expr $(,)?) => {
+        $handle.assert_logged_matching($level, None, $pattern, false)
+    };
+}
+
+impl Write for CaptureHandle {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut partial = self.partial.lock().unwrap_or_else(PoisonError::into_inner);
+        partial.extend_from_slice(buf);
+
+        while let Some(newline) = partial.iter().position(|byte| *byte == b'\n') {
+            let line: Vec<u8> = partial.drain(..=newline).collect();
+            self.push(&String::from_utf8_lossy(&line[..newline]));
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+///
+/// Runs `f` with the writers of the global logger replaced by an in-memory capture and returns
+/// the captured lines. The writers are restored afterwards, even if `f` panics.
+/// Captures are serialized, so tests running in parallel don't see each other's lines
+/// (logs coming from other threads while a capture is active are captured as well).
+/// # Example
+/// ```
+/// use rslogger::Logger;
+/// Logger::new().without_timestamps().add_writer_stdout(false, None).init().unwrap();
+/// let lines = rslogger::capture(|_| log::info!("captured"));
+/// assert_eq!(lines, vec!["-[][] -> {INFO} captured"]);
+/// ```
+/// # Panics
+/// If the global logger was not initialized with init().
+///
+pub fn capture<F: FnOnce(&CaptureHandle)>(f: F) -> Vec<String> {
+    static CAPTURE_LOCK: Mutex<()> = Mutex::new(());
+    let _capture_guard = CAPTURE_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+
+    let logger = LOGGER.get().expect("capture() requires the logger to be initialized with init()");
+    let handle = CaptureHandle::default();
+    let previous = logger.capture.write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .replace(handle.clone());
+    // The writers may accept less than the logger level, the capture accepts all of it.
+    let max_level = logger.max_level();
+    if max_level > log::max_level() {
+        log::set_max_level(max_level);
+    }
+
+    // Restores the previous state on drop, so a panicking closure doesn't leave the capture installed.
+    struct Restore(Option<CaptureHandle>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            if let Some(logger) = LOGGER.get() {
+                *logger.capture.write().unwrap_or_else(PoisonError::into_inner) = self.0.take();
+            }
+            crate::level_override::restore_max_level();
+        }
+    }
+
+    let restore = Restore(previous);
+    f(&handle);
+    drop(restore);
+
+    handle.lines()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_splits_lines() {
+        let mut handle = CaptureHandle::default();
+        handle.write_all(b"first\nsec").unwrap();
+        assert_eq!(handle.lines(), vec!["first"]);
+
+        handle.write_all(b"ond\n").unwrap();
+        assert_eq!(handle.lines(), vec!["first", "second"]);
+        assert!(handle.contains("seco"));
+
+        handle.write_all(b"thi").unwrap();
+        assert_eq!(handle.snapshot(), "first\nsecond\nthi");
+    }
+
+    #[cfg(feature = "test-util")]
+    #[test]
+    fn test_assert_logged() {
+        use log::Level;
+
+        let handle = CaptureHandle::default();
+        handle.push_record(Level::Warn, "app::db", format_args!("disk at 95%, almost full"));
+
+        crate::assert_logged!(handle, Level::Warn, contains: "disk at");
+        crate::assert_logged!(handle, Level::Warn, target: "app::db", contains: "full");
+        crate::assert_logged!(handle, Level::Warn, matches: r"^disk at \d+%");
+        crate::assert_logged!(handle, Level::Warn, target: "app::db", matches: "almost (full|empty)$");
+        crate::assert_not_logged!(handle, Level::Error, contains: "disk at");
+        crate::assert_not_logged!(handle, Level::Warn, target: "app::net", contains: "full");
+        crate::assert_not_logged!(handle, Level::Warn, matches: r"^\d+%");
+    }
+
+    #[cfg(feature = "test-util")]
+    #[test]
+    #[should_panic(expected = "expected a WARN record matching \"disk\"")]
+    fn test_assert_logged_ignores_the_layout() {
+        use log::Level;
+
+        // The line looks like a Warn record about the disk, the record is not.
+        let handle = CaptureHandle::default();
+        handle.push("-[][] -> {WARN} disk almost full");
+        handle.push_record(Level::Info, "", format_args!("{{WARN}} disk almost full"));
+
+        crate::assert_logged!(handle, Level::Warn, matches: "disk");
+    }
+}
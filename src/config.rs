@@ -0,0 +1,314 @@
+//!
+//! Parsing of a logger from a compact configuration string, e.g. the value of a `--log` flag,
+//! or from the environment variables.
+//!
+use std::{error::Error, fmt, path::PathBuf, str::FromStr};
+
+use log::LevelFilter;
+
+use crate::Logger;
+
+///
+/// Error returned when parsing a [`Logger`] from a configuration string,
+/// naming the item that could not be parsed.
+///
+#[derive(PartialEq, Clone, Debug)]
+pub struct ParseError {
+    item: String,
+    reason: String,
+}
+
+impl ParseError {
+    fn new(item: &str, reason: impl Into<String>) -> ParseError {
+        ParseError { item: item.to_string(), reason: reason.into() }
+    }
+
+    ///
+    /// Returns the item of the configuration string that could not be parsed.
+    ///
+    pub fn item(&self) -> &str {
+        &self.item
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid logger configuration item `{}`: {}", self.item, self.reason)
+    }
+}
+
+impl Error for ParseError {}
+
+///
+/// Parses a logger from a comma separated list of items, e.g. `level=info,stdout,file=/var/log/app.log:error`.
+/// The items are:
+/// * `level=<level>` - The level of the logger (`off`, `error`, `warn`, `info`, `debug` or `trace`). Info by default.
+/// * `stdout` or `stdout:<level>` - Adds a stdout writer, with its own level if given.
+/// * `file=<path>` or `file=<path>:<level>` - Adds a file writer, with its own level if given.
+///   The text after the last `:` is a level only if it's a level name, so Windows paths are kept whole.
+/// * `null` - Adds a writer discarding the records after formatting them, e.g. as a placeholder.
+/// * `thread` - Displays the thread of the records.
+/// * `target` - Displays the target of the records.
+/// * `timestamps=<kind>` - The timestamps: `local` (default), `utc`, `uptime` or `none`.
+/// * `<module>=<level>` - The level of a module and its submodules, e.g. `my_crate::db=trace`
+///   (see [`Logger::with_target_level`]). The modules named like an item above can't be set.
+/// * `<module>=<level>/<depth>` - The same, down to `depth` levels below the module only: `my_crate=debug/1` 
+///   sets the crate and its direct children (see [`Logger::with_target_level_to_depth`]).
+///
+/// The spaces around the items are ignored and the items can come in any order, except the module levels:
+/// if several match a target, the first one wins, e.g. `my_crate=debug/1,my_crate=warn` for Warn below the children.
+/// The writers write on the logging thread, with the default buffer capacity.
+/// ```no_run
+/// use rslogger::Logger;
+/// let logger: Logger = "level=info,stdout,file=/var/log/app.log:error".parse().unwrap();
+/// logger.init().unwrap();
+/// ```
+/// # Errors
+/// If an item is unknown, empty or repeated (for `level` and `timestamps`), or has an invalid value.
+///
+impl FromStr for Logger {
+    type Err = ParseError;
+
+    fn from_str(config: &str) -> Result<Logger, ParseError> {
+        let mut logger = Logger::new().with_level(LevelFilter::Info);
+        let (mut level_set, mut timestamps_set) = (false, false);
+
+        for item in config.split(',').map(str::trim) {
+            let (key, value) = match item.split_once('=') {
+                Some((key, value)) => (key.trim(), Some(value.trim())),
+                None => (item, None),
+            };
+
+            logger = match (key, value) {
+                ("", _) => return Err(ParseError::new(item, "empty item")),
+                ("level", Some(level)) => {
+                    if std::mem::replace(&mut level_set, true) {
+                        return Err(ParseError::new(item, "the level is set twice"));
+                    }
+                    logger.with_level(parse_level(item, level)?)
+                }
+                ("timestamps", Some(timestamps)) => {
+                    if std::mem::replace(&mut timestamps_set, true) {
+                        return Err(ParseError::new(item, "the timestamps are set twice"));
+                    }
+                    match timestamps {
+                        "local" => logger.with_local_timestamps(),
+                        "utc" => logger.with_utc_timestamps(),
+                        "uptime" => logger.with_uptime_timestamps(),
+                        "none" => logger.without_timestamps(),
+                        _ => return Err(ParseError::new(item, "expected local, utc, uptime or none")),
+                    }
+                }
+                ("file", Some(file)) => match split_level(file) {
+                    ("", _) => return Err(ParseError::new(item, "the file path is empty")),
+                    (path, Some(level)) => logger.add_writer_file_with_level(PathBuf::from(path), false, None, level),
+                    (path, None) => logger.add_writer_file(PathBuf::from(path), false, None),
+                },
+                ("thread", None) => logger.with_thread(),
+                ("target", None) => logger.with_target(),
+                ("stdout", None) => logger.add_writer_stdout(false, None),
+                ("null", None) => logger.add_writer_null(false, None),
+                (stdout, None) if stdout.starts_with("stdout:") =>
+                    logger.add_writer_stdout_with_level(false, None, parse_level(item, &stdout["stdout:".len()..])?),
+                ("level" | "timestamps" | "file", None) => return Err(ParseError::new(item, format!("expected {key}=<value>"))),
+                ("thread" | "target" | "stdout" | "null", Some(_)) => return Err(ParseError::new(item, format!("{key} takes no value"))),
+                (module, Some(directive)) if is_module_path(module) => with_module_level(logger, item, module, directive)?,
+                _ => return Err(ParseError::new(item, "unknown item, expected level, stdout, file, null, thread, target, timestamps or <module>=<level>")),
+            };
+        }
+        Ok(logger)
+    }
+}
+
+impl Logger {
+
+    ///
+    /// Builds a logger from the environment variables only, so the logging can be changed without code or flags
+    /// (e.g. in a twelve-factor app):
+    /// * `RUST_LOG` - Comma separated `<level>` for the logger level (Info by default) and `<module>=<level>` 
+    ///   for the level of a module and its submodules, as in the configuration string (see [`Logger::from_str`]).
+    /// * `LOG_TARGET` - `stdout` or `file`. Stdout by default, unless `LOG_FILE` is set.
+    /// * `LOG_FILE` - The path of the file writer, required by `LOG_TARGET=file`.
+    /// * `LOG_FORMAT` - `text` (default), `csv` (see [`Logger::with_csv`]) or `json` (see [`Logger::with_json`]).
+    ///
+    /// The empty variables are ignored. The writer writes on the logging thread, with the default buffer capacity.
+    /// ```no_run
+    /// // RUST_LOG=warn,my_app=debug LOG_FILE=/var/log/my_app.log
+    /// rslogger::Logger::from_env_full().unwrap().init().unwrap();
+    /// ```
+    /// # Errors
+    /// If a variable has an invalid value, the error item being `<variable>=<value>`.
+    ///
+    pub fn from_env_full() -> Result<Logger, ParseError> {
+        from_vars(|name| std::env::var(name).ok())
+    }
+}
+
+///
+/// Implementation of [`Logger::from_env_full`], reading the variables with `var`.
+///
+fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Logger, ParseError> {
+    let var = |name: &str| var(name).filter(|value| !value.trim().is_empty());
+    let mut logger = Logger::new().with_level(LevelFilter::Info);
+
+    if let Some(directives) = var("RUST_LOG") {
+        let item = format!("RUST_LOG={directives}");
+        for directive in directives.split(',').map(str::trim).filter(|directive| !directive.is_empty()) {
+            logger = match directive.split_once('=') {
+                Some((module, level)) if is_module_path(module.trim()) => with_module_level(logger, &item, module.trim(), level.trim())?,
+                Some(_) => return Err(ParseError::new(&item, format!("invalid module in `{directive}`"))),
+                None => logger.with_level(parse_level(&item, directive)?),
+            };
+        }
+    }
+
+    match var("LOG_FORMAT").as_deref().map(str::trim) {
+        None | Some("text") => {}
+        Some("csv") => logger = logger.with_csv(),
+        Some("json") => logger = logger.with_json(),
+        Some(format) => return Err(ParseError::new(&format!("LOG_FORMAT={format}"), "expected text, csv or json")),
+    }
+
+    let file = var("LOG_FILE");
+    let target = var("LOG_TARGET");
+    Ok(match (target.as_deref().map(str::trim), file) {
+        (None, None) | (Some("stdout"), _) => logger.add_writer_stdout(false, None),
+        (None | Some("file"), Some(file)) => logger.add_writer_file(PathBuf::from(file.trim()), false, None),
+        (Some("file"), None) => return Err(ParseError::new("LOG_TARGET=file", "LOG_FILE is not set")),
+        (Some(target), _) => return Err(ParseError::new(&format!("LOG_TARGET={target}"), "expected stdout or file")),
+    })
+}
+
+///
+/// Adds the level of `module`, parsed from the `<level>` or `<level>/<depth>` directive of `item`.
+///
+fn with_module_level(logger: Logger, item: &str, module: &str, directive: &str) -> Result<Logger, ParseError> {
+    Ok(match directive.split_once('/') {
+        Some((level, depth)) => {
+            let depth = depth.trim().parse()
+                .map_err(|_| ParseError::new(item, format!("invalid depth `{}`, expected a number", depth.trim())))?;
+            logger.with_target_level_to_depth(module, parse_level(item, level.trim())?, depth)
+        }
+        None => logger.with_target_level(module, parse_level(item, directive)?),
+    })
+}
+
+fn parse_level(item: &str, level: &str) -> Result<LevelFilter, ParseError> {
+    level.parse().map_err(|_| ParseError::new(item, format!("unknown level `{level}`, expected off, error, warn, info, debug or trace")))
+}
+
+///
+/// Returns true if `module` looks like a module path, e.g. `my_crate::db`.
+///
+fn is_module_path(module: &str) -> bool {
+    module.split("::").all(|segment| !segment.is_empty() && segment.chars().all(|c| c.is_alphanumeric() || c == '_'))
+}
+
+///
+/// Splits `path:level` into the path and the level, if the text after the last `:` is a level name.
+///
+fn split_level(file: &str) -> (&str, Option<LevelFilter>) {
+    file.rsplit_once(':')
+        .and_then(|(path, level)| Some((path, Some(level.parse().ok()?))))
+        .unwrap_or((file, None))
+}
+
+#[cfg(test)]
+mod tests {
+    use log::{Level, Log, Record};
+
+    use super::*;
+
+    #[test]
+    fn test_parse_logger() {
+        let path = std::env::temp_dir().join(format!("rslogger-config-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let logger: Logger = format!(" level=debug , timestamps=none,target,file={}:warn", path.display()).parse().unwrap();
+        assert_eq!(logger.log_level(), LevelFilter::Debug);
+        for level in [Level::Info, Level::Warn] {
+            logger.log(&Record::builder().level(level).target("app").args(format_args!("{level}")).build());
+        }
+        logger.flush();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(content, "-[app][] -> {WARN} WARN\n");
+    }
+
+    #[test]
+    fn test_parse_null_writer() {
+        let logger: Logger = "level=warn,null".parse().unwrap();
+        assert_eq!(logger.log_level(), LevelFilter::Warn);
+        assert_eq!(logger.max_level(), LevelFilter::Warn);
+    }
+
+    #[test]
+    fn test_parse_module_levels() {
+        let logger: Logger = "level=info,null,app=debug/1,app=warn,app::db::pool = trace".parse().unwrap();
+        for (target, level) in [
+            ("app::db", LevelFilter::Debug),
+            ("app::db::pool", LevelFilter::Warn),
+            ("app::net::tls", LevelFilter::Warn),
+            ("other", LevelFilter::Info),
+        ] {
+            assert_eq!(logger.target_level(target), level, "{target}");
+        }
+        assert_eq!(logger.max_level(), LevelFilter::Trace);
+    }
+
+    #[test]
+    fn test_from_vars() {
+        fn env<'a>(vars: &'a [(&str, &str)]) -> impl Fn(&str) -> Option<String> + 'a {
+            move |name| vars.iter().find(|(var, _)| *var == name).map(|(_, value)| value.to_string())
+        }
+
+        let logger = from_vars(env(&[("RUST_LOG", "warn, app::db=trace"), ("LOG_TARGET", "")])).unwrap();
+        assert_eq!(logger.log_level(), LevelFilter::Warn);
+        assert_eq!(logger.target_level("app::db::pool"), LevelFilter::Trace);
+        assert_eq!(logger.writers.len(), 1);
+        assert_eq!(from_vars(env(&[])).unwrap().log_level(), LevelFilter::Info);
+
+        let path = std::env::temp_dir().join(format!("rslogger-env-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let logger = from_vars(env(&[("LOG_FILE", path.to_str().unwrap()), ("LOG_FORMAT", "csv")])).unwrap();
+        logger.log(&Record::builder().level(Level::Info).args(format_args!("from env")).build());
+        logger.flush();
+        let content = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert!(content.starts_with("timestamp,level,target,thread,message\n"), "{content}");
+        assert!(content.ends_with(",INFO,,,from env\n"), "{content}");
+
+        let error = |vars: &[(&str, &str)]| from_vars(env(vars)).err().unwrap();
+        assert_eq!(error(&[("LOG_FORMAT", "xml")]).to_string(), "invalid logger configuration item `LOG_FORMAT=xml`: expected text, csv or json");
+        assert!(from_vars(env(&[("LOG_FORMAT", "json")])).unwrap().layout.json);
+        assert_eq!(error(&[("LOG_TARGET", "file")]).to_string(), "invalid logger configuration item `LOG_TARGET=file`: LOG_FILE is not set");
+        assert_eq!(error(&[("LOG_TARGET", "syslog")]).item(), "LOG_TARGET=syslog");
+        assert_eq!(error(&[("RUST_LOG", "info,app=loud")]).item(), "RUST_LOG=info,app=loud");
+    }
+
+    #[test]
+    fn test_split_level() {
+        assert_eq!(split_level("app.log:error"), ("app.log", Some(LevelFilter::Error)));
+        assert_eq!(split_level(r"C:\logs\app.log"), (r"C:\logs\app.log", None));
+        assert_eq!(split_level("app.log"), ("app.log", None));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |config: &str| config.parse::<Logger>().err().unwrap();
+
+        assert_eq!(error("level=loud").to_string(),
+            "invalid logger configuration item `level=loud`: unknown level `loud`, expected off, error, warn, info, debug or trace");
+        assert_eq!(error("level=info,level=warn").item(), "level=warn");
+        assert_eq!(error("stdout,,").item(), "");
+        assert_eq!(error("file=").to_string(), "invalid logger configuration item `file=`: the file path is empty");
+        assert_eq!(error("file").to_string(), "invalid logger configuration item `file`: expected file=<value>");
+        assert_eq!(error("thread=yes").to_string(), "invalid logger configuration item `thread=yes`: thread takes no value");
+        assert_eq!(error("null=1").to_string(), "invalid logger configuration item `null=1`: null takes no value");
+        assert_eq!(error("syslog").item(), "syslog");
+        assert_eq!(error("app=loud").item(), "app=loud");
+        assert_eq!(error("app=debug/one").to_string(), "invalid logger configuration item `app=debug/one`: invalid depth `one`, expected a number");
+        assert_eq!(error("app:::db=debug").item(), "app:::db=debug");
+    }
+}
@@ -0,0 +1,131 @@
+use std::{
+    cell::Cell,
+    collections::VecDeque,
+    fs,
+    io::Write,
+    panic::{self, PanicHookInfo},
+    path::PathBuf,
+    sync::{Arc, Mutex, TryLockError},
+};
+
+/// Number of formatted lines kept in memory for the crash dump.
+const RING_CAPACITY: usize = 256;
+
+thread_local! {
+    ///
+    /// Set while this thread is writing a crash dump.
+    /// If the dump itself panics, the nested hook invocation must not try to dump again.
+    ///
+    static DUMPING: Cell<bool> = const { Cell::new(false) };
+}
+
+///
+/// Keeps the last `RING_CAPACITY` formatted lines so they can be written to a file
+/// when the process panics.
+///
+pub struct CrashDump {
+    path: PathBuf,
+    lines: Mutex<VecDeque<String>>,
+}
+
+impl CrashDump {
+
+    pub fn new(path: PathBuf) -> CrashDump {
+        CrashDump { path, lines: Mutex::new(VecDeque::with_capacity(RING_CAPACITY)) }
+    }
+
+    ///
+    /// Stores a formatted line, evicting the oldest one if the ring is full.
+    ///
+    pub fn push(&self, line: &str) {
+        let mut lines = match self.lines.lock() {
+            Ok(lines) => lines,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        if lines.len() == RING_CAPACITY {
+            lines.pop_front();
+        }
+        lines.push_back(line.to_string());
+    }
+
+    ///
+    /// Installs a panic hook that writes the ring buffer to the dump file and then
+    /// chains to the previously installed hook (the default one if none was set).
+    ///
+    pub fn install_hook(self: &Arc<Self>) {
+        let crash_dump = Arc::clone(self);
+        let previous_hook = panic::take_hook();
+
+        panic::set_hook(Box::new(move |info| {
+            if !DUMPING.with(|dumping| dumping.replace(true)) {
+                crash_dump.dump(info);
+                DUMPING.with(|dumping| dumping.set(false));
+            }
+            previous_hook(info);
+        }));
+    }
+
+    fn dump(&self, info: &PanicHookInfo) {
+        let payload = if let Some(message) = info.payload().downcast_ref::<&str>() {
+            message.to_string()
+        } else if let Some(message) = info.payload().downcast_ref::<String>() {
+            message.clone()
+        } else {
+            "Box<dyn Any>".to_string()
+        };
+
+        let location = info.location()
+            .map(|location| location.to_string())
+            .unwrap_or_else(|| "unknown location".to_string());
+
+        // Never wait on the ring here: the panic may come from a thread holding the lock.
+        let lines = match self.lines.try_lock() {
+            Ok(lines) => lines.iter().cloned().collect(),
+            Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner().iter().cloned().collect(),
+            Err(TryLockError::WouldBlock) => Vec::new(),
+        };
+
+        // Nothing sensible can be done if the dump cannot be written, the default hook still runs.
+        let _ = self.write_dump(&lines, &payload, &location);
+    }
+
+    fn write_dump(&self, lines: &[String], payload: &str, location: &str) -> std::io::Result<()> {
+        let mut file = fs::File::create(&self.path)?;
+        for line in lines {
+            writeln!(file, "{line}")?;
+        }
+        writeln!(file, "Panicked at {location}: {payload}")?;
+        file.sync_all()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ring_evicts_oldest() {
+        let crash_dump = CrashDump::new(PathBuf::new());
+        for i in 0..RING_CAPACITY + 2 {
+            crash_dump.push(&format!("line {i}"));
+        }
+
+        let lines = crash_dump.lines.lock().unwrap();
+        assert_eq!(lines.len(), RING_CAPACITY);
+        assert_eq!(lines.front().unwrap(), "line 2");
+        assert_eq!(lines.back().unwrap(), &format!("line {}", RING_CAPACITY + 1));
+    }
+
+    #[test]
+    fn test_write_dump() {
+        let path = std::env::temp_dir().join(format!("rslogger-crash-dump-{}.log", std::process::id()));
+        let crash_dump = CrashDump::new(path.clone());
+
+        crash_dump.write_dump(&["first".to_string(), "second".to_string()], "boom", "src/main.rs:1:1").unwrap();
+
+        let content = fs::read_to_string(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        assert_eq!(content, "first\nsecond\nPanicked at src/main.rs:1:1: boom\n");
+    }
+}
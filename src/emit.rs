@@ -0,0 +1,88 @@
+use std::fmt::{self, Write};
+
+use log::{Level, Log, Record};
+
+///
+/// A fully owned log event, built from data rather than at a call site (e.g. to replay recorded events)
+/// and logged with [`emit`], without the lifetimes of `log::Record`:
+/// ```no_run
+/// use log::Level;
+/// use rslogger::{Event, Logger};
+/// Logger::new().add_writer_stdout(false, None).init().unwrap();
+///
+/// rslogger::emit(&Event::new(Level::Warn, "replay::orders", "order rejected")
+///     .with_field("id", 42)
+///     .with_field("reason", "out of stock"));
+/// // 10:00:00:000000-[][] -> {WARN} order rejected id=42 reason="out of stock"
+/// ```
+///
+#[derive(Clone, PartialEq, Debug)]
+pub struct Event {
+    pub level: Level,
+    /// The target of the record, as the `target:` of the log macros.
+    pub target: String,
+    pub message: String,
+    /// The key-value pairs appended to the message as ` key=value`, the values quoted if they contain spaces.
+    pub fields: Vec<(String, String)>,
+}
+
+impl Event {
+
+    pub fn new(level: Level, target: &str, message: impl Into<String>) -> Event {
+        Event { level, target: target.to_string(), message: message.into(), fields: Vec::new() }
+    }
+
+    ///
+    /// Appends a key-value pair to the fields.
+    ///
+    pub fn with_field(mut self, key: &str, value: impl fmt::Display) -> Event {
+        self.fields.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    fn log(&self, logger: &dyn Log) {
+        let mut message = self.message.clone();
+        for (key, value) in &self.fields {
+            if value.is_empty() || value.contains(|c: char| c.is_whitespace() || c == '"' || c == '=') {
+                let _ = write!(message, " {key}={value:?}");
+            } else {
+                let _ = write!(message, " {key}={value}");
+            }
+        }
+
+        logger.log(&Record::builder()
+            .level(self.level)
+            .target(&self.target)
+            .args(format_args!("{message}"))
+            .build());
+    }
+}
+
+///
+/// Logs `event` through the global logger, with the same filtering and writers as the records of the log macros.
+/// The time of the record is the time of the call, not of the original event.
+///
+pub fn emit(event: &Event) {
+    if event.level <= log::max_level() {
+        event.log(log::logger());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_fields() {
+        let (logger, captured) = crate::Logger::new().with_level(log::LevelFilter::Info).without_timestamps().with_target().add_writer_capture();
+
+        Event::new(Level::Warn, "replay::orders", "order rejected")
+            .with_field("id", 42)
+            .with_field("reason", "out of stock")
+            .with_field("note", "")
+            .log(&logger);
+        Event::new(Level::Debug, "replay::orders", "filtered by the logger level").log(&logger);
+
+        assert_eq!(captured.lines(), vec![r#"-[replay::orders][] -> {WARN} order rejected id=42 reason="out of stock" note="""#]);
+    }
+}
@@ -0,0 +1,55 @@
+use std::{fmt, io, path::PathBuf};
+
+use log::SetLoggerError;
+
+///
+/// Error of the logger, returned when a writer can't be initialized or the logger can't be installed.
+///
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// A log file or directory could not be created, opened or locked.
+    Io {
+        /// The path of the file or directory.
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// The separate thread of a writer could not be started.
+    ThreadSpawn(io::Error),
+    /// A logger was already installed.
+    SetLogger(SetLoggerError),
+    /// The configuration of a writer is invalid, e.g. an unknown placeholder in the path of its file.
+    InvalidConfig(String),
+}
+
+///
+/// The error of the writer init before it was merged into [`Error`], kept for the existing callers.
+///
+pub type WriterInitError = Error;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io { path, source } => write!(f, "IO error on {}: {source}", path.display()),
+            Error::ThreadSpawn(source) => write!(f, "Unable to start Writer thread: {source}"),
+            Error::SetLogger(error) => write!(f, "{error}"),
+            Error::InvalidConfig(reason) => write!(f, "Invalid writer configuration: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } | Error::ThreadSpawn(source) => Some(source),
+            Error::SetLogger(error) => Some(error),
+            Error::InvalidConfig(_) => None,
+        }
+    }
+}
+
+impl From<SetLoggerError> for Error {
+    fn from(error: SetLoggerError) -> Self {
+        Error::SetLogger(error)
+    }
+}
@@ -0,0 +1,323 @@
+//!
+//! Minimal gzip encoder for the rotated log files: DEFLATE with the fixed Huffman codes and greedy LZ77 matching.
+//! Logs are repetitive enough for it to get most of the gain of a full encoder, without a dependency.
+//!
+use std::io::{self, BufWriter, Read, Write};
+
+/// The input is compressed in independent blocks of this size, bounding the memory used.
+const CHUNK_SIZE: usize = 1 << 20;
+/// The max distance of a match.
+const WINDOW_SIZE: usize = 32 * 1024;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+/// The max number of earlier positions compared for a match.
+const MAX_CHAIN: usize = 32;
+const HASH_BITS: u32 = 15;
+/// The end of a position chain.
+const NO_POSITION: u32 = u32::MAX;
+
+const LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASE: [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+const DISTANCE_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut index = 0;
+    while index < 256 {
+        let mut crc = index as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { 0xEDB8_8320 ^ (crc >> 1) } else { crc >> 1 };
+            bit += 1;
+        }
+        table[index] = crc;
+        index += 1;
+    }
+    table
+}
+
+fn update_crc(crc: u32, data: &[u8]) -> u32 {
+    !data.iter().fold(!crc, |crc, byte| CRC_TABLE[((crc ^ *byte as u32) & 0xFF) as usize] ^ (crc >> 8))
+}
+
+///
+/// Writes the bits of the DEFLATE stream, the first ones in the low bits of each byte.
+///
+struct BitWriter<W: Write> {
+    out: W,
+    bits: u64,
+    count: u32,
+}
+
+impl<W: Write> BitWriter<W> {
+    fn write_bits(&mut self, value: u32, len: u32) -> io::Result<()> {
+        self.bits |= (value as u64) << self.count;
+        self.count += len;
+        while self.count >= 8 {
+            self.out.write_all(&[self.bits as u8])?;
+            self.bits >>= 8;
+            self.count -= 8;
+        }
+        Ok(())
+    }
+
+    /// Writes a Huffman code, sent from its most significant bit.
+    fn write_code(&mut self, code: u32, len: u32) -> io::Result<()> {
+        self.write_bits(code.reverse_bits() >> (32 - len), len)
+    }
+
+    /// Writes a literal, a length or the end of block with the fixed Huffman codes.
+    fn write_symbol(&mut self, symbol: u32) -> io::Result<()> {
+        match symbol {
+            0..=143 => self.write_code(0x30 + symbol, 8),
+            144..=255 => self.write_code(0x190 + symbol - 144, 9),
+            256..=279 => self.write_code(symbol - 256, 7),
+            _ => self.write_code(0xC0 + symbol - 280, 8),
+        }
+    }
+
+    fn write_match(&mut self, length: usize, distance: usize) -> io::Result<()> {
+        let index = LENGTH_BASE.partition_point(|base| *base as usize <= length) - 1;
+        self.write_symbol(257 + index as u32)?;
+        self.write_bits((length - LENGTH_BASE[index] as usize) as u32, LENGTH_EXTRA[index] as u32)?;
+
+        let index = DISTANCE_BASE.partition_point(|base| *base as usize <= distance) - 1;
+        self.write_code(index as u32, 5)?;
+        self.write_bits((distance - DISTANCE_BASE[index] as usize) as u32, DISTANCE_EXTRA[index] as u32)
+    }
+
+    /// Writes the last bits, padded to a whole byte.
+    fn finish(mut self) -> io::Result<W> {
+        if self.count > 0 {
+            self.out.write_all(&[self.bits as u8])?;
+        }
+        Ok(self.out)
+    }
+}
+
+fn hash(bytes: &[u8]) -> usize {
+    let value = u32::from(bytes[0]) << 16 | u32::from(bytes[1]) << 8 | u32::from(bytes[2]);
+    (value.wrapping_mul(0x9E37_79B1) >> (32 - HASH_BITS)) as usize
+}
+
+///
+/// Finds the longest earlier match of each position of a block, through the chains of the positions with the same hash.
+///
+struct MatchFinder<'a> {
+    data: &'a [u8],
+    /// The last position of each hash.
+    head: Vec<u32>,
+    /// The previous position with the same hash of each position.
+    previous: Vec<u32>,
+}
+
+impl<'a> MatchFinder<'a> {
+    fn new(data: &'a [u8]) -> MatchFinder<'a> {
+        MatchFinder { data, head: vec![NO_POSITION; 1 << HASH_BITS], previous: vec![NO_POSITION; data.len()] }
+    }
+
+    fn insert(&mut self, position: usize) {
+        if position + MIN_MATCH <= self.data.len() {
+            let hash = hash(&self.data[position..]);
+            self.previous[position] = self.head[hash];
+            self.head[hash] = position as u32;
+        }
+    }
+
+    /// Returns the length and the distance of the longest match of `position`, a length of 0 if there's none.
+    fn longest_match(&self, position: usize) -> (usize, usize) {
+        let (mut best_length, mut best_distance) = (0, 0);
+        if position + MIN_MATCH > self.data.len() {
+            return (best_length, best_distance);
+        }
+
+        let max_length = MAX_MATCH.min(self.data.len() - position);
+        let mut candidate = self.head[hash(&self.data[position..])];
+        for _ in 0..MAX_CHAIN {
+            if candidate == NO_POSITION || position - candidate as usize > WINDOW_SIZE {
+                break;
+            }
+            let candidate_position = candidate as usize;
+            let length = self.data[candidate_position..].iter().zip(&self.data[position..position + max_length])
+                .take_while(|(a, b)| a == b)
+                .count();
+            if length > best_length {
+                (best_length, best_distance) = (length, position - candidate_position);
+                if length == max_length {
+                    break;
+                }
+            }
+            candidate = self.previous[candidate_position];
+        }
+        (best_length, best_distance)
+    }
+}
+
+///
+/// Writes `data` as a fixed Huffman block, the last of the stream if `last`.
+///
+fn write_block<W: Write>(bits: &mut BitWriter<W>, data: &[u8], last: bool) -> io::Result<()> {
+    bits.write_bits(u32::from(last), 1)?;
+    bits.write_bits(1, 2)?;
+
+    let mut finder = MatchFinder::new(data);
+    let mut position = 0;
+    while position < data.len() {
+        let (length, distance) = finder.longest_match(position);
+        if length >= MIN_MATCH {
+            bits.write_match(length, distance)?;
+            for matched in position..position + length {
+                finder.insert(matched);
+            }
+            position += length;
+        } else {
+            bits.write_symbol(u32::from(data[position]))?;
+            finder.insert(position);
+            position += 1;
+        }
+    }
+    bits.write_symbol(256)
+}
+
+///
+/// Compresses `input` in the gzip format on `output`.
+///
+pub(crate) fn compress(mut input: impl Read, output: impl Write) -> io::Result<()> {
+    let mut output = BufWriter::new(output);
+    // Magic, deflate, no flags, no modification time, no extra flags, unknown OS.
+    output.write_all(&[0x1F, 0x8B, 8, 0, 0, 0, 0, 0, 0, 0xFF])?;
+
+    let mut bits = BitWriter { out: output, bits: 0, count: 0 };
+    let (mut crc, mut size) = (0, 0u32);
+    let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+    loop {
+        chunk.clear();
+        (&mut input).take(CHUNK_SIZE as u64).read_to_end(&mut chunk)?;
+        if chunk.is_empty() {
+            break;
+        }
+        crc = update_crc(crc, &chunk);
+        size = size.wrapping_add(chunk.len() as u32);
+        write_block(&mut bits, &chunk, false)?;
+    }
+    // An empty block closing the stream, as the end of the input is only known after the last chunk.
+    write_block(&mut bits, &[], true)?;
+
+    let mut output = bits.finish()?;
+    output.write_all(&crc.to_le_bytes())?;
+    output.write_all(&size.to_le_bytes())?;
+    output.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reads the bits of a DEFLATE stream.
+    struct BitReader<'a> {
+        data: &'a [u8],
+        position: usize,
+    }
+
+    impl BitReader<'_> {
+        fn bits(&mut self, len: u32) -> u32 {
+            (0..len).fold(0, |value, bit| {
+                let byte = self.data[self.position / 8];
+                let value = value | u32::from(byte >> (self.position % 8) & 1) << bit;
+                self.position += 1;
+                value
+            })
+        }
+
+        fn code(&mut self, len: u32) -> u32 {
+            (0..len).fold(0, |code, _| code << 1 | self.bits(1))
+        }
+
+        fn symbol(&mut self) -> u32 {
+            let code = self.code(7);
+            if code <= 0b0010111 {
+                return 256 + code;
+            }
+            let code = code << 1 | self.bits(1);
+            match code {
+                0x30..=0xBF => code - 0x30,
+                0xC0..=0xC7 => 280 + code - 0xC0,
+                _ => 144 + (code << 1 | self.bits(1)) - 0x190,
+            }
+        }
+    }
+
+    /// Decompresses the fixed Huffman blocks written by compress().
+    fn decompress(gzip: &[u8]) -> Vec<u8> {
+        assert_eq!(gzip[..3], [0x1F, 0x8B, 8]);
+        let mut reader = BitReader { data: &gzip[10..], position: 0 };
+        let mut output = Vec::new();
+        loop {
+            let last = reader.bits(1) == 1;
+            assert_eq!(reader.bits(2), 1);
+            loop {
+                let symbol = reader.symbol() as usize;
+                match symbol {
+                    0..=255 => output.push(symbol as u8),
+                    256 => break,
+                    _ => {
+                        let index = symbol - 257;
+                        let length = LENGTH_BASE[index] as usize + reader.bits(LENGTH_EXTRA[index] as u32) as usize;
+                        let index = reader.code(5) as usize;
+                        let distance = DISTANCE_BASE[index] as usize + reader.bits(DISTANCE_EXTRA[index] as u32) as usize;
+                        for _ in 0..length {
+                            output.push(output[output.len() - distance]);
+                        }
+                    }
+                }
+            }
+            if last {
+                break;
+            }
+        }
+
+        let trailer = &gzip[gzip.len() - 8..];
+        assert_eq!(trailer[..4], update_crc(0, &output).to_le_bytes());
+        assert_eq!(trailer[4..], (output.len() as u32).to_le_bytes());
+        output
+    }
+
+    #[test]
+    fn test_crc() {
+        assert_eq!(update_crc(0, b"123456789"), 0xCBF4_3926);
+        assert_eq!(update_crc(update_crc(0, b"1234"), b"56789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_compress() {
+        let mut logs = String::new();
+        for index in 0..20_000 {
+            logs.push_str(&format!("2024-06-03 10:00:{:02}-[app::db][worker-{}] -> {{INFO}} query {index} done\n", index % 60, index % 7));
+        }
+        let binary: Vec<u8> = (0..70_000u32).map(|index| (index.wrapping_mul(2_654_435_761) >> 13) as u8).collect();
+
+        for input in [logs.as_bytes(), &binary, b"", b"a"] {
+            let mut gzip = Vec::new();
+            compress(input, &mut gzip).unwrap();
+            assert_eq!(decompress(&gzip), input);
+        }
+
+        let mut gzip = Vec::new();
+        compress(logs.as_bytes(), &mut gzip).unwrap();
+        assert!(gzip.len() * 5 < logs.len(), "{} bytes compressed to {}", logs.len(), gzip.len());
+    }
+
+    #[test]
+    fn test_compress_known_answer() {
+        // Checked with `gzip -d`: the decoder of the tests shares the tables of the encoder.
+        let mut gzip = Vec::new();
+        compress(&b"{INFO} query done\n{INFO} query done\n"[..], &mut gzip).unwrap();
+        assert_eq!(gzip, [
+            0x1F, 0x8B, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xAA, 0xF6, 0xF4, 0x73, 0xF3, 0xAF, 0x55, 0x28, 0x2C, 0x4D, 
+            0x2D, 0xAA, 0x54, 0x48, 0xC9, 0xCF, 0x4B, 0xE5, 0xC2, 0x14, 0x01, 0x0C, 0x00, 0xAB, 0x15, 0x54, 0x79, 0x24, 0x00, 0x00, 0x00,
+        ]);
+    }
+}
@@ -0,0 +1,87 @@
+use std::{cell::RefCell, sync::{Mutex, PoisonError}};
+
+use log::LevelFilter;
+
+use crate::{Logger, LOGGER};
+
+thread_local! {
+    /// The levels overriding the level of a logger on this thread, by address of the logger, innermost last.
+    static LEVEL_OVERRIDES: RefCell<Vec<(usize, LevelFilter)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Number of overrides of the global logger currently active on any thread.
+/// While it's not zero, the max level of the log crate is raised to let the overridden records through.
+static ACTIVE_OVERRIDES: Mutex<usize> = Mutex::new(0);
+
+///
+/// Returns the level overriding the level of `logger` on the current thread, if any.
+///
+pub(crate) fn current(logger: &Logger) -> Option<LevelFilter> {
+    let address = logger as *const Logger as usize;
+    LEVEL_OVERRIDES.with_borrow(|overrides| {
+        overrides.iter().rev().find(|(logger, _)| *logger == address).map(|(_, level)| *level)
+    })
+}
+
+///
+/// Sets the max level of the log crate back to the one derived from the logger configuration,
+/// unless an override is still active.
+///
+pub(crate) fn restore_max_level() {
+    let active = ACTIVE_OVERRIDES.lock().unwrap_or_else(PoisonError::into_inner);
+    if *active == 0 && let Some(logger) = LOGGER.get() {
+        log::set_max_level(logger.max_level());
+    }
+}
+
+///
+/// Runs `f` with the level of the global logger replaced by `level` on the current thread only, e.g. to trace
+/// a single request path in production without changing the level for the whole program.
+/// See [`Logger::with_level_override`], `f` is just called if the logger is not initialized.
+/// ```no_run
+/// use rslogger::Logger;
+/// Logger::new().with_level(log::LevelFilter::Info).add_writer_stdout(false, None).init().unwrap();
+/// rslogger::with_level_override(log::LevelFilter::Trace, || {
+///     log::trace!("This is logged");
+/// });
+/// log::trace!("This is not");
+/// ```
+///
+pub fn with_level_override<R, F: FnOnce() -> R>(level: LevelFilter, f: F) -> R {
+    match LOGGER.get() {
+        Some(logger) => run(logger, level, f),
+        None => f(),
+    }
+}
+
+///
+/// Implementation of [`Logger::with_level_override`].
+///
+pub(crate) fn run<R, F: FnOnce() -> R>(logger: &Logger, level: LevelFilter, f: F) -> R {
+    // Only the global logger is reached through the macros filtered by the max level of the log crate.
+    let global = LOGGER.get().is_some_and(|global| std::ptr::eq(*global, logger));
+    if global {
+        let mut active = ACTIVE_OVERRIDES.lock().unwrap_or_else(PoisonError::into_inner);
+        *active += 1;
+        if level > log::max_level() {
+            log::set_max_level(level);
+        }
+    }
+
+    // Restores the previous state on drop, so a panicking closure doesn't leave the override installed.
+    struct Restore(bool);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            LEVEL_OVERRIDES.with_borrow_mut(|overrides| overrides.pop());
+
+            if self.0 {
+                *ACTIVE_OVERRIDES.lock().unwrap_or_else(PoisonError::into_inner) -= 1;
+                restore_max_level();
+            }
+        }
+    }
+
+    LEVEL_OVERRIDES.with_borrow_mut(|overrides| overrides.push((logger as *const Logger as usize, level)));
+    let _restore = Restore(global);
+    f()
+}
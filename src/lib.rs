@@ -1,29 +1,559 @@
+mod capture;
+mod config;
+mod crash_dump;
+mod emit;
+mod error;
+mod gzip;
+mod level_override;
+mod line_logger;
+mod pipeline;
+mod priority;
+mod sanitize;
+mod subscribe;
+mod summary;
+mod suspend;
+mod terminal;
+#[cfg(feature = "tracing")]
+pub mod tracing_bridge;
 mod writer;
-use std::{path::PathBuf, sync::RwLock};
+use std::{borrow::Cow, cell::{Cell, RefCell}, fmt::{self, Write as _}, fs::File, io::{IsTerminal, Write},  path::PathBuf, sync::{atomic::{AtomicBool, AtomicU64, Ordering}, Arc, Mutex, OnceLock, PoisonError, RwLock, RwLockWriteGuard, TryLockError}, time::{Duration, Instant}};
 
-use crate::writer::BufferedWriter;
+use crate::{crash_dump::CrashDump, pipeline::PipelineGuard, sanitize::Sanitized, summary::Summary, writer::{BufferedWriter, SharedThread, ANSI_RESET}};
+pub use crate::capture::{capture, CaptureHandle, CapturedRecord};
+pub use crate::config::ParseError;
+pub use crate::emit::{emit, Event};
+pub use crate::error::{Error, WriterInitError};
+pub use crate::level_override::with_level_override;
+pub use crate::line_logger::{writer_for, LineLogger};
+pub use crate::sanitize::{escape_bytes, EscapedBytes};
+pub use crate::subscribe::{subscribe, LogEvent, Subscription};
+pub use crate::suspend::{resume, suspend};
+pub use crate::writer::{BufferCapacity, FlushPolicy, FlushStats, LevelFlush, OpenMode, OverflowPolicy, RotatedNaming, RotationPolicy, SyncPolicy};
 
-use log::{LevelFilter, Log, SetLoggerError};
-use time::{format_description::FormatItem, OffsetDateTime, UtcDateTime};
+use log::{Level, LevelFilter, Log};
+use time::{format_description::FormatItem, UtcDateTime, UtcOffset};
+
+/// The logger installed by init(), reachable by the free functions of this crate.
+static LOGGER: OnceLock<&'static Logger> = OnceLock::new();
+
+thread_local! {
+    /// Address of the logger forwarding a record to its secondary logger on this thread, to break loops.
+    static FORWARDING: Cell<usize> = const { Cell::new(0) };
+}
+
+/// The target of the warnings of the logger about its own writers.
+const NOTICE_TARGET: &str = "rslogger";
+
+/// Attempts to take the lock of a writer held by another thread before dropping the record, see with_nonblocking_writers().
+const NONBLOCKING_ATTEMPTS: u32 = 64;
+
+/// Queue capacity of the writers of WriterOptions::non_blocking().
+const NON_BLOCKING_QUEUE_CAPACITY: usize = 8192;
+
+/// The header of the CSV files, see with_csv().
+const CSV_HEADER: &str = "timestamp,level,target,thread,message";
 
 const TIMESTMAMP_FORMAT: &[FormatItem] = time::macros::format_description!(
     "[hour]:[minute]:[second]:[subsecond digits:6]"
 );
 
+/// The local part of the dual timestamps, see with_dual_timestamps().
+const DUAL_LOCAL_FORMAT: &[FormatItem] = time::macros::format_description!("[hour]:[minute]");
+
+
+/// The records accepted by a writer, on top of the logger level.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum WriterLevel {
+    /// All the records passing the logger level.
+    Logger,
+    /// The records up to this level, even above the logger level.
+    Max(LevelFilter),
+    /// Only the records with these levels, as a bitset indexed by level.
+    Only(u8),
+}
+
+impl WriterLevel {
+    fn only(levels: &[Level]) -> WriterLevel {
+        WriterLevel::Only(levels.iter().fold(0, |set, level| set | 1 << *level as usize))
+    }
+
+    fn accepts(self, record_level: Level, level: LevelFilter) -> bool {
+        match self {
+            WriterLevel::Logger => record_level <= level,
+            WriterLevel::Max(max) => record_level <= max,
+            WriterLevel::Only(set) => record_level <= level && set & 1 << record_level as usize != 0,
+        }
+    }
+
+    ///
+    /// Returns the most permissive level accepted, `level` being the logger level.
+    /// 
+    fn max(self, level: LevelFilter) -> LevelFilter {
+        match self {
+            WriterLevel::Logger => level,
+            WriterLevel::Max(max) => max,
+            WriterLevel::Only(set) => Level::iter()
+                .filter(|only| set & 1 << *only as usize != 0)
+                .max()
+                .map_or(LevelFilter::Off, |only| only.to_level_filter())
+                .min(level),
+        }
+    }
+}
+
+/// The records accepted by a writer: their level and their target.
+struct WriterFilter {
+    level: WriterLevel,
+    /// The target prefixes accepted, all the targets if empty.
+    include: Vec<String>,
+    /// The target prefixes refused, even if included.
+    exclude: Vec<String>,
+}
+
+impl From<WriterLevel> for WriterFilter {
+    fn from(level: WriterLevel) -> WriterFilter {
+        WriterFilter { level, include: Vec::new(), exclude: Vec::new() }
+    }
+}
+
+impl WriterFilter {
+    fn accepts(&self, metadata: &log::Metadata, level: LevelFilter) -> bool {
+        let target = metadata.target();
+        self.level.accepts(metadata.level(), level)
+            && (self.include.is_empty() || self.include.iter().any(|prefix| target_matches(target, prefix)))
+            && !self.exclude.iter().any(|prefix| target_matches(target, prefix))
+    }
+}
+
+///
+/// The options of a writer added with [`Logger::add_writer_stdout_opts`] or [`Logger::add_writer_file_opts`],
+/// sized and filtered independently of the other writers, named at the call site instead of positional arguments:
+/// ```no_run
+/// use rslogger::{BufferCapacity, Logger, WriterOptions};
+/// Logger::new()
+///     .add_writer_stdout_opts(WriterOptions::default())
+///     .add_writer_file_opts("app.log".into(), WriterOptions { 
+///         threaded: true, 
+///         buffer: BufferCapacity::Bytes(64 * 1024),
+///         queue_capacity: Some(10_000),
+///         exclude: vec!["hyper".to_string()],
+///         ..WriterOptions::default()
+///     })
+///     .init().unwrap();
+/// ```
+///
+#[derive(Clone, Debug, Default)]
+pub struct WriterOptions {
+    /// Writes on a separate thread, as `multi_thread` in the other add_writer_*() functions.
+    pub threaded: bool,
+    /// The buffer capacity, the default one if not set.
+    pub buffer: BufferCapacity,
+    /// The queue capacity of the separate thread, replacing the one of with_queue_capacity() if set.
+    pub queue_capacity: Option<usize>,
+    /// The level of the writer, replacing the logger level as in add_writer_*_with_level() if set.
+    pub level: Option<LevelFilter>,
+    /// The flush policy, replacing the one of with_flush_policy() (and of a buffer capacity in records) if set.
+    pub flush_policy: Option<FlushPolicy>,
+    /// Takes an advisory lock on the file (`flock` on Unix, `LockFileEx` on Windows), also after each rotation. 
+    /// If another process holds it, init() returns the error `<path> is in use by another process` and the logger is not installed, 
+    /// instead of mixing the lines of two processes on the same file. The lock is released when the logger is dropped.
+    /// File writers only.
+    pub exclusive: bool,
+    /// Syncs the file and its metadata on the disk after each flush, as [`Logger::with_fsync`]. File writers only.
+    pub fsync: bool,
+    /// The target modules accepted (with their submodules), all if empty, as in add_writer_file_with_targets().
+    pub include: Vec<String>,
+    /// The target modules refused (with their submodules), even if included.
+    pub exclude: Vec<String>,
+    /// What to do when the queue of the separate thread is full, replacing the one of with_overflow_policy() if set.
+    pub overflow_policy: Option<OverflowPolicy>,
+}
+
+impl WriterOptions {
+
+    ///
+    /// A writer that never blocks the logging threads, for async servers: writing on stdout blocks when the
+    /// terminal or the pipe is full (e.g. its reader is paused), stalling the executor of a runtime worker.
+    /// The writer runs on a separate thread with a bounded queue of 8192 records,
+    /// and the records logged while the queue is full are dropped, counted by [`dropped_records`]:
+    /// ```no_run
+    /// use rslogger::{Logger, WriterOptions};
+    /// Logger::new()
+    ///     .add_writer_stdout_opts(WriterOptions::non_blocking())
+    ///     .init().unwrap();
+    /// // ...
+    /// if rslogger::dropped_records() > 0 {
+    ///     eprintln!("{} records dropped", rslogger::dropped_records());
+    /// }
+    /// ```
+    /// 
+    pub fn non_blocking() -> WriterOptions {
+        WriterOptions {
+            threaded: true,
+            queue_capacity: Some(NON_BLOCKING_QUEUE_CAPACITY),
+            overflow_policy: Some(OverflowPolicy::DropNewest),
+            ..WriterOptions::default()
+        }
+    }
+
+    fn filter(&self) -> WriterFilter {
+        WriterFilter { 
+            level: self.level.map_or(WriterLevel::Logger, WriterLevel::Max),
+            include: self.include.clone(),
+            exclude: self.exclude.clone(),
+        }
+    }
+
+    ///
+    /// Applies the options not covered by add_configured_writer().
+    /// 
+    fn apply(&self, mut writer: BufferedWriter) -> BufferedWriter {
+        if let Some(queue_capacity) = self.queue_capacity { writer = writer.with_queue_capacity(queue_capacity); }
+        if let Some(policy) = self.flush_policy { writer = writer.with_flush_policy(policy); }
+        if self.exclusive { writer = writer.with_exclusive_lock(true); }
+        if self.fsync { writer = writer.with_fsync(); }
+        if let Some(policy) = self.overflow_policy { writer = writer.with_overflow_policy(policy); }
+        writer
+    }
+}
+
+///
+/// A writer added with [`Logger::add_writer`], composing its options freely:
+/// ```no_run
+/// use log::LevelFilter;
+/// use rslogger::{FlushPolicy, Logger, WriterBuilder};
+/// Logger::new()
+///     .add_writer(WriterBuilder::new().on_stdout().with_level(LevelFilter::Warn))
+///     .add_writer(WriterBuilder::new()
+///         .on_file("app.log".into())
+///         .with_separate_thread()
+///         .with_buffer_capacity(Some(64 * 1024))
+///         .with_queue_capacity(10_000)
+///         .with_flush_policy(FlushPolicy::Buffered))
+///     .init().unwrap();
+/// ```
+/// The options of the logger for the writers (e.g. with_rotation() for the files) apply as well.
+///
+#[derive(Clone, Debug, Default)]
+pub struct WriterBuilder {
+    target: BuilderTarget,
+    options: WriterOptions,
+}
+
+///
+/// The target of a WriterBuilder, see [`WriterBuilder::target`].
+///
+#[derive(PartialEq, Eq, Clone, Debug, Default)]
+pub enum BuilderTarget {
+    /// Stdout (default).
+    #[default]
+    StdOut,
+    /// The file at this path, with its placeholders not expanded yet.
+    File(PathBuf),
+    /// Nothing, the records are discarded.
+    Null,
+}
+
+impl WriterBuilder {
+
+    ///
+    /// Creates a single thread stdout writer with the default buffer capacity.
+    /// 
+    pub fn new() -> WriterBuilder {
+        WriterBuilder::default()
+    }
+
+    ///
+    /// Writes on stdout.
+    /// 
+    pub fn on_stdout(mut self) -> WriterBuilder {
+        self.target = BuilderTarget::StdOut;
+        self
+    }
+
+    ///
+    /// Writes on the file at `file_path`, with the placeholders of [`Logger::add_writer_file`].
+    /// 
+    pub fn on_file(mut self, file_path: PathBuf) -> WriterBuilder {
+        self.target = BuilderTarget::File(file_path);
+        self
+    }
+
+    ///
+    /// Discards the records, see [`Logger::add_writer_null`].
+    /// 
+    pub fn on_null(mut self) -> WriterBuilder {
+        self.target = BuilderTarget::Null;
+        self
+    }
+
+    ///
+    /// Writes on a separate thread, as `multi_thread` in the add_writer_*() functions.
+    /// 
+    pub fn with_separate_thread(mut self) -> WriterBuilder {
+        self.options.threaded = true;
+        self
+    }
+
+    ///
+    /// Sets the buffer capacity, in bytes (`Some(bytes)`) or in records (see [`BufferCapacity`]).
+    /// 
+    pub fn with_buffer_capacity(mut self, capacity: impl Into<BufferCapacity>) -> WriterBuilder {
+        self.options.buffer = capacity.into();
+        self
+    }
+
+    ///
+    /// Sets the queue capacity of the separate thread, replacing the one of with_queue_capacity().
+    /// 
+    pub fn with_queue_capacity(mut self, capacity: usize) -> WriterBuilder {
+        self.options.queue_capacity = Some(capacity);
+        self
+    }
+
+    ///
+    /// Sets the level of the writer, replacing the logger level: it may be more verbose.
+    /// 
+    pub fn with_level(mut self, level: LevelFilter) -> WriterBuilder {
+        self.options.level = Some(level);
+        self
+    }
+
+    ///
+    /// Sets the flush policy, replacing the one of with_flush_policy().
+    /// 
+    pub fn with_flush_policy(mut self, policy: FlushPolicy) -> WriterBuilder {
+        self.options.flush_policy = Some(policy);
+        self
+    }
+
+    ///
+    /// Takes an advisory lock on the file, see [`WriterOptions::exclusive`].
+    /// 
+    pub fn with_exclusive_lock(mut self) -> WriterBuilder {
+        self.options.exclusive = true;
+        self
+    }
+
+    ///
+    /// Syncs the file on the disk after each flush, see [`WriterOptions::fsync`].
+    /// 
+    pub fn with_fsync(mut self) -> WriterBuilder {
+        self.options.fsync = true;
+        self
+    }
+
+    ///
+    /// Accepts the records by target, see [`Logger::add_writer_file_with_targets`].
+    /// 
+    pub fn with_targets(mut self, include: Vec<String>, exclude: Vec<String>) -> WriterBuilder {
+        self.options.include = include;
+        self.options.exclude = exclude;
+        self
+    }
+
+    ///
+    /// Never blocks the logging threads, dropping the records when the queue is full: see [`WriterOptions::non_blocking`].
+    /// The queue capacity may still be changed with with_queue_capacity().
+    /// 
+    pub fn non_blocking(mut self) -> WriterBuilder {
+        let preset = WriterOptions::non_blocking();
+        self.options.threaded = preset.threaded;
+        self.options.queue_capacity = preset.queue_capacity;
+        self.options.overflow_policy = preset.overflow_policy;
+        self
+    }
+
+    ///
+    /// Returns the buffer capacity, as set by with_buffer_capacity().
+    /// 
+    pub fn capacity(&self) -> BufferCapacity {
+        self.options.buffer
+    }
+
+    ///
+    /// Returns true if the writer writes on a separate thread, see with_separate_thread().
+    /// 
+    pub fn is_separate_thread(&self) -> bool {
+        self.options.threaded
+    }
+
+    ///
+    /// Returns the target the writer writes on.
+    /// 
+    pub fn target(&self) -> &BuilderTarget {
+        &self.target
+    }
+}
+
+///
+/// Returns true if `target` is the module `prefix` or one of its submodules: 
+/// `my_crate` matches `my_crate::db` but not `my_crate_utils`.
+///
+fn target_matches(target: &str, prefix: &str) -> bool {
+    target.strip_prefix(prefix).is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+}
+
+///
+/// The targets a level applies to, see with_target_level() and with_target_regex().
+///
+enum TargetMatcher {
+    /// The module and its submodules, down to `depth` levels below it if set.
+    Module { prefix: String, depth: Option<usize> },
+    #[cfg(feature = "regex")]
+    Regex(regex::Regex),
+}
+
+impl TargetMatcher {
+    fn matches(&self, target: &str) -> bool {
+        match self {
+            TargetMatcher::Module { prefix, depth } => target_matches(target, prefix) && depth.is_none_or(|depth| {
+                // The `::` separated segments after the prefix.
+                target[prefix.len()..].matches("::").count() <= depth
+            }),
+            #[cfg(feature = "regex")]
+            TargetMatcher::Regex(regex) => regex.is_match(target),
+        }
+    }
+}
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone, Copy)]
 enum Timestamps {
     None, 
     Local,
     Utc,
+    Offset(UtcOffset),
+    Dual(UtcOffset),
+    /// The time elapsed since this instant, reset at init().
+    Uptime(Instant),
+}
+
+/// The time of a record, read on the calling thread when it's logged and rendered according to the Timestamps,
+/// possibly later on a writer thread.
+#[derive(Clone, Copy)]
+enum RecordTime {
+    None,
+    Wall(UtcDateTime),
+    Elapsed(Duration),
+}
+
+impl Timestamps {
+    fn now(self) -> RecordTime {
+        match self {
+            Timestamps::None => RecordTime::None,
+            Timestamps::Uptime(start) => RecordTime::Elapsed(start.elapsed()),
+            _ => RecordTime::Wall(UtcDateTime::now()),
+        }
+    }
+
+    fn render(self, time: RecordTime) -> String {
+        match (self, time) {
+            (Timestamps::Local, RecordTime::Wall(now)) => {
+                // Rendered on the writer thread with with_deferred_formatting(), where a panic would kill the writer.
+                let offset = UtcOffset::local_offset_at(now.to_offset(UtcOffset::UTC)).unwrap_or(UtcOffset::UTC);
+                now.to_offset(offset).format(TIMESTMAMP_FORMAT).unwrap()
+            }
+            (Timestamps::Utc, RecordTime::Wall(now)) => now.format(TIMESTMAMP_FORMAT).unwrap(),
+            (Timestamps::Offset(offset), RecordTime::Wall(now)) => now.to_offset(offset).format(TIMESTMAMP_FORMAT).unwrap(),
+            (Timestamps::Dual(offset), RecordTime::Wall(now)) => dual_timestamp(now, offset),
+            (_, RecordTime::Elapsed(elapsed)) => uptime_timestamp(elapsed),
+            _ => "".to_string(),
+        }
+    }
+}
+
+///
+/// The pieces of a record sent to the separate thread writers with with_deferred_formatting(),
+/// laid out as a line on the writer thread.
+/// 
+pub(crate) struct DeferredRecord {
+    level: Level,
+    target: String,
+    thread: String,
+    time: RecordTime,
+    args: String,
+    /// The `file:line` of the record, None if the locations are not displayed.
+    location: Option<(String, u32)>,
+    timestamps: Timestamps,
+    layout: LineLayout,
 }
 
+impl DeferredRecord {
+    ///
+    /// Lays out the line of the record, colored by level if `colored`.
+    /// 
+    pub(crate) fn line(&self, colored: bool) -> String {
+        let location = self.location.as_ref()
+            .map(|(file, line)| format_location(file, *line, colored))
+            .unwrap_or_default();
+        let line = format_line(self.layout, &self.timestamps.render(self.time), self.level, &self.target, &self.thread, &location, &self.args);
+        if colored {
+            color_line(self.level, &line, terminal::width())
+        } else {
+            line
+        }
+    }
+}
+
+///
+/// Logger implementing the `log` crate interface, writing the records on the configured writers.
+/// 
+/// The time of a record is always read in `log()` on the calling thread, before the record is handed
+/// to any writer queue: with separate thread writers, deferred formatting or a lagging queue, 
+/// the timestamps are the event times, not the write times. Their order in a file is the order
+/// of the records in the queue, so a later time can't be written before an earlier one of the same thread.
+/// 
 pub struct Logger {
     /// The default log level for all the logs.
     log_level: LevelFilter,
     timestamps: Timestamps,
     thread: bool,
     target: bool,
+    /// How the lines are laid out: CSV rows or text lines, short levels.
+    layout: LineLayout,
+    /// Lay out the lines of the separate thread writers on their thread.
+    deferred_formatting: bool,
+    /// Display the `file:line` of the records, linked on the colored writers.
+    locations: bool,
+    /// Held while a record is handed to the writers, so they all receive the records in the same order.
+    strict_ordering: Option<Mutex<()>>,
+    /// Drop the records instead of waiting for a writer locked by another thread.
+    nonblocking_writers: bool,
+    /// The records dropped by the logger itself: their writer was locked by another thread (see with_nonblocking_writers()),
+    /// or they were logged from within the logging pipeline (e.g. by the target of a writer).
+    discarded_records: AtomicU64,
+    /// Color the lines by level on the stdout writers added from now on.
+    colors: bool,
+    /// Draw the Error records in a box on the stdout writers added from now on.
+    boxed_errors: bool,
+    /// Write each record whole under the stdout lock on the stdout writers added from now on.
+    stdout_lock_per_line: bool,
+    /// Create the parent directories of the file writers added from now on.
+    create_dirs: bool,
+    /// The permissions of the directories created by the file writers added from now on, None for the umask.
+    dir_mode: Option<u32>,
+    /// The symlink kept pointing at the file by the file writers added from now on, if any.
+    latest_link: Option<PathBuf>,
+    /// The block size of the file writers added from now on, None for no alignment.
+    block_size: Option<usize>,
+    /// When the file writers added from now on sync the file on the disk.
+    sync: SyncPolicy,
+    /// How many times the file writers added from now on retry opening their file, and the first delay.
+    open_retries: (u32, Duration),
+    /// Whether the file writers added from now on keep the content of their file.
+    open_mode: OpenMode,
+    /// The permissions of the files created by the file writers added from now on, None for the default ones.
+    file_mode: Option<u32>,
+    /// When the file writers added from now on start a new file.
+    rotation: RotationPolicy,
+    /// How the file writers added from now on name their rotated files.
+    rotated_naming: RotatedNaming,
+    /// The max number of rotated files kept by the file writers added from now on, None for all.
+    max_backups: Option<usize>,
+    /// The max age of the rotated files kept by the file writers added from now on, None for all.
+    max_age: Option<Duration>,
+    /// Gzip the rotated files of the file writers added from now on.
+    compress_backups: bool,
     ///
     /// The RwLock is needed to provide interior mutability. 
     /// That bitch of the Log crate decided to declare flush method as flush(&self) and not 
@@ -33,7 +563,53 @@ pub struct Logger {
     /// we need a mutable reference to it inside the flush method.
     /// Also, it is an RwLock and not an Rc because this structure must be Sync + Send.
     writers: Vec<RwLock<BufferedWriter>>,
-    writer_levels: Vec<LevelFilter>,
+    /// The records accepted by each writer.
+    writer_filters: Vec<WriterFilter>,
+    /// The name of the threads of the separate thread writers added from now on, None to derive it from the target.
+    writer_thread_name: Option<String>,
+    /// The build written first on the writers added from now on, see with_build_info().
+    build_info: Option<String>,
+    /// Whether the threads of the separate thread writers added from now on lower their scheduling priority.
+    background_priority: bool,
+    /// Whether the separate thread writers added from now on share a single thread.
+    share_writer_thread: bool,
+    /// Whether the writers added from now on are dropped if a writer already writes on their destination.
+    dedup_destinations: bool,
+    /// The thread shared by the writers, started with the first of them.
+    /// Declared after the writers: it's dropped once they are cleaned up, when its thread can end.
+    shared_thread: Option<Arc<SharedThread>>,
+    /// The bytes written around each record by the writers added from now on. None for the default `\n` terminator.
+    record_framing: Option<(Vec<u8>, Vec<u8>)>,
+    /// Whether the writers added from now on end the output with the suffix of the last record.
+    final_suffix: bool,
+    /// The flush policy per level applied to the writers added from now on.
+    level_flush: Vec<(Level, LevelFlush)>,
+    /// The idle time after which the separate thread writers added from now on flush their buffer.
+    idle_flush: Option<Duration>,
+    /// When the writers added from now on flush their buffer, None for the default of their target.
+    flush_policy: Option<FlushPolicy>,
+    /// The queue capacity of the separate thread writers added from now on, None for unbounded.
+    queue_capacity: Option<usize>,
+    /// What the separate thread writers added from now on do with the records when their queue is full.
+    overflow_policy: OverflowPolicy,
+    /// The high and low watermarks of the queue of the separate thread writers added from now on.
+    queue_watermarks: Option<(usize, usize)>,
+    /// Ring buffer of the last lines, written to a file if the process panics.
+    crash_dump: Option<Arc<CrashDump>>,
+    /// The error of the first writer that failed to initialize, returned by init().
+    init_error: Option<Error>,
+    /// The records counted for the summary written at shutdown, see with_shutdown_summary().
+    summary: Option<Arc<Summary>>,
+    /// When set by capture(), the lines go to the capture instead of the writers.
+    capture: RwLock<Option<CaptureHandle>>,
+    /// Another logger receiving every record alongside the writers.
+    secondary: Option<Box<dyn Log>>,
+    /// The level at or above which the process exits after logging, with the exit code.
+    exit_on: Option<(Level, i32)>,
+    /// Set by suspend(), drops all the records until resume().
+    suspended: AtomicBool,
+    /// The levels replacing the logger level for the matching targets, first match wins.
+    target_levels: Vec<(TargetMatcher, LevelFilter)>,
 }
 
 impl Logger {
@@ -55,8 +631,52 @@ impl Logger {
             timestamps: Timestamps::Local, 
             target: false,
             thread: false, 
+            layout: LineLayout::default(),
+            colors: false,
+            boxed_errors: false,
+            stdout_lock_per_line: false,
+            deferred_formatting: false,
+            locations: false,
+            strict_ordering: None,
+            nonblocking_writers: false,
+            discarded_records: AtomicU64::new(0),
+            create_dirs: true,
+            dir_mode: None,
+            latest_link: None,
+            block_size: None,
+            sync: SyncPolicy::Never,
+            open_retries: (0, Duration::ZERO),
+            open_mode: OpenMode::Append,
+            file_mode: None,
+            rotation: RotationPolicy::Never,
+            rotated_naming: RotatedNaming::Period,
+            max_backups: None,
+            max_age: None,
+            compress_backups: false,
             writers: Vec::new(),
-            writer_levels: Vec::new()
+            writer_thread_name: None,
+            build_info: None,
+            background_priority: false,
+            share_writer_thread: false,
+            dedup_destinations: false,
+            shared_thread: None,
+            writer_filters: Vec::new(),
+            record_framing: None,
+            final_suffix: true,
+            level_flush: Vec::new(),
+            idle_flush: None,
+            flush_policy: None,
+            queue_capacity: None,
+            overflow_policy: OverflowPolicy::Block,
+            queue_watermarks: None,
+            crash_dump: None,
+            init_error: None,
+            summary: None,
+            capture: RwLock::new(None),
+            secondary: None,
+            exit_on: None,
+            suspended: AtomicBool::new(false),
+            target_levels: Vec::new(),
         }
     }
 
@@ -67,6 +687,58 @@ impl Logger {
         self
     }
 
+    ///
+    /// Uses `level` instead of the logger level for the records of the module `target` and of its submodules:
+    /// `app::db` covers `app::db::pool` but not `app::dbx`. If several target levels match, the first added wins,
+    /// so the narrower ones come first.
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn with_target_level(mut self, target: &str, level: LevelFilter) -> Logger {
+        self.target_levels.push((TargetMatcher::Module { prefix: target.to_string(), depth: None }, level));
+        self
+    }
+
+    ///
+    /// Same as [`with_target_level`](Logger::with_target_level), for the submodules at most `depth` levels below `target`,
+    /// the deeper ones keeping the logger level (or matching the next target levels).
+    /// E.g. Debug for `app` and its direct children, Warn below them:
+    /// ```
+    /// use log::LevelFilter;
+    /// use rslogger::Logger;
+    /// let logger = Logger::new()
+    ///     .with_level(LevelFilter::Info)
+    ///     .with_target_level_to_depth("app", LevelFilter::Debug, 1)
+    ///     .with_target_level("app", LevelFilter::Warn);
+    /// ```
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn with_target_level_to_depth(mut self, target: &str, level: LevelFilter, depth: usize) -> Logger {
+        self.target_levels.push((TargetMatcher::Module { prefix: target.to_string(), depth: Some(depth) }, level));
+        self
+    }
+
+    ///
+    /// Uses `level` instead of the logger level for the records whose target matches the regex `pattern`,
+    /// e.g. `::internal$` for any module ending in `::internal`. If several target levels match, the first added wins.
+    /// The pattern is compiled here, once.
+    /// # Panics
+    /// If the pattern is not a valid regex, see [`try_with_target_regex`](Logger::try_with_target_regex).
+    /// 
+    #[cfg(feature = "regex")]
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn with_target_regex(self, pattern: &str, level: LevelFilter) -> Logger {
+        self.try_with_target_regex(pattern, level).expect("Invalid target regex")
+    }
+
+    ///
+    /// Same as [`with_target_regex`](Logger::with_target_regex), returning the error if the pattern is not a valid regex.
+    /// 
+    #[cfg(feature = "regex")]
+    pub fn try_with_target_regex(mut self, pattern: &str, level: LevelFilter) -> Result<Logger, regex::Error> {
+        self.target_levels.push((TargetMatcher::Regex(regex::Regex::new(pattern)?), level));
+        Ok(self)
+    }
+
     /// Display timestamps in UTC time
     #[must_use = "You must call init() to initialize the logger"]
     pub fn with_utc_timestamps(mut self) -> Logger {
@@ -74,13 +746,49 @@ impl Logger {
         self
     }
 
-    /// Display timestamps in Local time
+    /// Display timestamps in Local time.
+    /// 
+    /// The local offset is determined for each record through the time crate, which refuses to 
+    /// do it in multi-threaded programs on most Unix systems, as it's unsound: the timestamps are then in UTC.
+    /// Prefer with_offset() with an offset determined at startup in threaded programs.
     #[must_use = "You must call init() to initialize the logger"]
     pub fn with_local_timestamps(mut self) -> Logger {
         self.timestamps = Timestamps::Local;
         self
     }
 
+    /// Display timestamps with a fixed UTC offset. 
+    /// The offset is applied to the UTC time of each record, without querying the system local offset,
+    /// so it's the safe way to get local-looking timestamps in multi-threaded programs.
+    /// ```no_run
+    /// use rslogger::Logger;
+    /// // Determine the offset while the program is still single-threaded.
+    /// let offset = time::UtcOffset::current_local_offset().unwrap_or(time::UtcOffset::UTC);
+    /// Logger::new().with_offset(offset).add_writer_stdout(true, None).init().unwrap();
+    /// ```
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn with_offset(mut self, offset: UtcOffset) -> Logger {
+        self.timestamps = Timestamps::Offset(offset);
+        self
+    }
+
+    /// Display timestamps in UTC time followed by the hour and minute with a fixed UTC offset,
+    /// e.g. `10:00:00:000000Z (12:00 local)`, to correlate with remote systems while keeping local readability.
+    /// Both are rendered from the same clock read. As in with_offset(), the offset is not queried for each record.
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn with_dual_timestamps(mut self, offset: UtcOffset) -> Logger {
+        self.timestamps = Timestamps::Dual(offset);
+        self
+    }
+
+    /// Display the time elapsed since init() instead of the wall clock, as `D+HH:MM:SS.nnnnnnnnn`.
+    /// The elapsed time is monotonic, so it's not affected by clock adjustments: useful for latency analysis.
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn with_uptime_timestamps(mut self) -> Logger {
+        self.timestamps = Timestamps::Uptime(Instant::now());
+        self
+    }
+
     /// Don't display timestamps
     #[must_use = "You must call init() to initialize the logger"]
     pub fn without_timestamps(mut self) -> Logger {
@@ -110,253 +818,3091 @@ impl Logger {
     }
 
     ///
-    /// Adds a stdout writer. 
-    /// # Param
-    /// * `multi_thread` - If set to true, the writer will be multi thread, otherwise single thread
-    /// * `capacity` - If Some(capacity), specified the buffer capacity of the writer. If None, initializes it with the default capacity.
+    /// Writes each record as a CSV row `timestamp,level,target,thread,message` instead of a text line,
+    /// quoting the fields containing commas, quotes or newlines as in RFC 4180 (e.g. to open the logs in a spreadsheet).
+    /// The file writers added after this call write the header row when they open an empty file.
+    /// The target and thread columns are empty unless enabled with with_target() and with_thread().
     /// 
     #[must_use = "You must call init() to initialize the logger"]
-    pub fn add_writer_stdout(mut self, multi_thread: bool, capacity: Option<usize>) -> Logger {
-        let default_level = self.log_level.clone();
-        self = self.add_writer_stdout_level(multi_thread, capacity, default_level);
+    pub fn with_csv(mut self) -> Logger {
+        self.layout.csv = true;
+        self.layout.json = false;
         self
     }
 
     ///
-    /// Adds a stdout writer. 
-    /// # Param
-    /// * `multi_thread` - If set to true, the writer will be multi thread, otherwise single thread
-    /// * `capacity` - If Some(capacity), specified the buffer capacity of the writer. If None, initializes it with the default capacity.
-    /// * `level` - Max level for this tracer.
+    /// Writes each record as a JSON object on a single line (JSON Lines), e.g. for a log collector:
+    /// `{"timestamp":"10:00:00:000000","level":"WARN","target":"app","message":"disk almost full"}`.
+    /// The timestamp, target and thread fields are left out when they are empty (see with_target() and with_thread()).
+    /// Replaces the CSV rows of with_csv().
     /// 
     #[must_use = "You must call init() to initialize the logger"]
-    pub fn add_writer_stdout_with_level(mut self, multi_thread: bool, capacity: Option<usize>, level: LevelFilter) -> Logger {
-        self = self.add_writer_stdout_level(multi_thread, capacity, level);
+    pub fn with_json(mut self) -> Logger {
+        self.layout.json = true;
+        self.layout.csv = false;
         self
     }
 
     ///
-    /// Adds a file writer. 
-    /// # Param
-    /// * `file_path` - The path of the file to write on.
-    /// * `multi_thread` - If set to true, the writer will be multi thread, otherwise single thread
-    /// * `capacity` - If Some(capacity), specified the buffer capacity of the writer. If None, initializes it with the default capacity.
-    /// * `level` - Max level for this tracer.
+    /// Displays the levels as a single character (`E`, `W`, `I`, `D` and `T`), for denser lines: 
+    /// `-[][] -> {W} disk almost full`. Also applies to the level column of the CSV rows.
     /// 
     #[must_use = "You must call init() to initialize the logger"]
-    pub fn add_writer_file(self, file_path: PathBuf, multi_thread: bool, capacity: Option<usize>) -> Logger {
-        let default_level = self.log_level.clone();
-        self.add_writer_file_level(file_path, multi_thread, capacity, default_level)
+    pub fn with_short_levels(mut self) -> Logger {
+        self.layout.short_levels = true;
+        self
     }
 
     ///
-    /// Adds a file writer. 
-    /// # Param
-    /// * `file_path` - The path of the file to write on.
-    /// * `multi_thread` - If set to true, the writer will be multi thread, otherwise single thread
-    /// * `capacity` - If Some(capacity), specified the buffer capacity of the writer. If None, initializes it with the default capacity.
+    /// Escapes the control characters of the messages before writing them, so a stray byte from external input
+    /// can't corrupt a terminal or split a line: `\n`, `\r` and `\t` as such, the others as `\u{1b}`,
+    /// e.g. `-[][] -> {WARN} bad request "GET /\u{1b}[2J"`. By default the messages are written as they are.
+    /// The bytes that may not be valid UTF-8 can be logged with [`escape_bytes`].
     /// 
     #[must_use = "You must call init() to initialize the logger"]
-    pub fn add_writer_file_with_level(self, file_path: PathBuf, multi_thread: bool, capacity: Option<usize>, level: LevelFilter) -> Logger {
-        self.add_writer_file_level(file_path, multi_thread, capacity, level)
+    pub fn with_sanitize_output(mut self) -> Logger {
+        self.layout.sanitize = true;
+        self
     }
 
-    pub fn init(self) -> Result<(), SetLoggerError> {
-        log::set_max_level(self.log_level);
-        log::set_boxed_logger(Box::new(self))
+    ///
+    /// Displays the timestamp at the start of the lines (default) or at their end, after the message:
+    /// `[][] -> {INFO} server started 10:00:00:000000`. The CSV rows keep the timestamp column first.
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn with_timestamp_position(mut self, position: TimestampPosition) -> Logger {
+        self.layout.timestamp_position = position;
+        self
     }
 
-    pub fn log_level(&self) -> LevelFilter {
-        self.log_level
+    ///
+    /// Colors the lines by level on the stdout writers added after this call.
+    /// Colors are only applied if stdout is a terminal, and never to file writers.
+    /// Each line ends with a color reset (before the line terminator) and a final reset is 
+    /// written at flush, so the terminal is never left colored. The color is also reset before each
+    /// point where the terminal wraps a line longer than its width, then applied again.
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn with_colors(mut self) -> Logger {
+        self.colors = true;
+        self
     }
 
-
-    fn add_writer_stdout_level(mut self, multi_thread: bool, capacity: Option<usize>, level: LevelFilter) -> Logger {
-        let mut writer = BufferedWriter::new().on_stdout();
-
-        if multi_thread { writer = writer.with_separate_thread(); }
-        if let Some(buf_cap) = capacity { writer = writer.with_buffer_capacity(buf_cap) }
-
-        match writer.init() {
-            Ok(initialized_writer) => {
-                self.writers.push(RwLock::new(initialized_writer));
-                self.writer_levels.push(level.to_owned());
-            },
-            Err(error) => println!("Error while initializing writer. Details: {}", error),
-        }
-
+    ///
+    /// Draws the Error records in a box on the stdout writers added after this call, so they stand out
+    /// among the other lines during development:
+    /// ```text
+    /// ┌──────────────────────────────────────
+    /// │ -[][] -> {ERROR} connection refused
+    /// └──────────────────────────────────────
+    /// ```
+    /// As the colors, the box is only drawn if stdout is a terminal, and never on file writers.
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn with_boxed_errors(mut self) -> Logger {
+        self.boxed_errors = true;
         self
     }
 
-
-    fn add_writer_file_level(mut self, file_path: PathBuf, multi_thread: bool, capacity: Option<usize>, level: LevelFilter) -> Logger {
-        let mut writer = BufferedWriter::new().on_file(file_path);
-        
-        if multi_thread { writer = writer.with_separate_thread(); }
-        if let Some(buf_cap) = capacity { writer = writer.with_buffer_capacity(buf_cap) }
-
-        match writer.init() {
-            Ok(initialized_writer) => {
-                self.writers.push(RwLock::new(initialized_writer));
-                self.writer_levels.push(level.to_owned());
-            },
-            Err(error) => println!("Error while initializing writer. Details: {}", error),
-        }
-
+    ///
+    /// Makes the stdout writers added after this call write each record, terminator included, with a single write
+    /// under the stdout lock, so the `println!` of the other threads can't tear the lines. 
+    /// The records are not buffered: each one is written and flushed on its own, whatever the buffer capacity.
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn with_stdout_lock_per_line(mut self) -> Logger {
+        self.stdout_lock_per_line = true;
         self
     }
-}
 
-impl Default for Logger {
-    fn default() -> Self {
-        Logger::new()
+    ///
+    /// Sets whether the file writers added after this call create the parent directories 
+    /// of their file (default true). With false, the file is opened in the existing directory, 
+    /// which allows logging into a pre-provisioned directory where mkdir is forbidden.
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn with_create_dirs(mut self, create_dirs: bool) -> Logger {
+        self.create_dirs = create_dirs;
+        self
     }
-}
 
-impl Log for Logger {
-    fn enabled(&self, metadata: &log::Metadata) -> bool {
-        metadata.level().to_level_filter() <= self.log_level
+    ///
+    /// Makes the file writers added after this call set the permissions of the directories they create 
+    /// to `mode`, whatever the umask of the process: e.g. `0o750` lets a log-reading group in.
+    /// The directories already existing are left as they are. No-op on non-Unix platforms.
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn with_dir_mode(mut self, mode: u32) -> Logger {
+        self.dir_mode = Some(mode);
+        self
+    }
+
+    ///
+    /// Makes the file writers added after this call keep the symlink `link` (e.g. `latest.log`) pointing
+    /// at their file, so `tail -F` follows the logs across the rotations. The link is created at init and 
+    /// replaced atomically after each rotation. A relative `link` is in the directory of the log file.
+    /// No-op on non-Unix platforms.
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn with_latest_link(mut self, link: impl Into<PathBuf>) -> Logger {
+        self.latest_link = Some(link.into());
+        self
+    }
+
+    ///
+    /// Makes the file writers added after this call write in whole blocks of `block_size` bytes 
+    /// (e.g. 4096), which is much faster on some storage (spinning disks, SD cards).
+    /// The buffer capacity is rounded up to a multiple of the block size, and the bytes after the 
+    /// last whole block are only written on explicit flushes and at cleanup.
+    /// # Panics 
+    /// If `block_size` is 0.
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn with_block_alignment(mut self, block_size: usize) -> Logger {
+        assert!(block_size > 0, "The block size must be greater than 0");
+        self.block_size = Some(block_size);
+        self
+    }
+
+    ///
+    /// Makes the file writers added after this call sync the file on the disk, so the records survive 
+    /// a power loss (flushing the buffer only hands them to the OS):
+    /// * [`SyncPolicy::OnFlush`] syncs at each flush of the buffer, the good tradeoff for audit logs.
+    /// * [`SyncPolicy::EveryRecord`] flushes and syncs after each record: very slow, as logging waits for the disk
+    ///   on a writer on the logging thread.
+    /// 
+    /// The stdout writers added after this call ignore the policy, with a notice on the other writers.
+    /// ```no_run
+    /// use rslogger::{Logger, SyncPolicy};
+    /// Logger::new()
+    ///     .with_sync(SyncPolicy::OnFlush)
+    ///     .add_writer_file("audit.log".into(), true, None)
+    ///     .init().unwrap();
+    /// ```
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn with_sync(mut self, policy: SyncPolicy) -> Logger {
+        self.sync = policy;
+        self
+    }
+
+    ///
+    /// Makes the file writers added after this call retry opening their file at init up to `count` times,
+    /// waiting `delay` before the first retry and doubling it before each next one, 
+    /// e.g. in a container whose log volume is mounted after the process starts. 
+    /// init() returns the error of the last attempt if they all fail.
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn with_open_retries(mut self, count: u32, delay: Duration) -> Logger {
+        self.open_retries = (count, delay);
+        self
+    }
+
+    ///
+    /// Makes the file writers added after this call open their file with `mode`: [`OpenMode::Truncate`] 
+    /// discards the content of an existing file, so each run starts fresh (e.g. dev loops, test harnesses).
+    /// A writer combining Truncate with a rotation policy fails to initialize: init() returns the error.
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn with_open_mode(mut self, mode: OpenMode) -> Logger {
+        self.open_mode = mode;
+        self
+    }
+
+    ///
+    /// Makes the file writers added after this call create their files with the permissions `mode`, 
+    /// e.g. `0o600` for logs holding sensitive data. Also applies to the files started by the rotation, 
+    /// and the compressed backups keep the permissions of their file. The umask of the process still applies, 
+    /// and the existing files are left as they are. No-op on non-Unix platforms.
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn with_file_mode(mut self, mode: u32) -> Logger {
+        self.file_mode = Some(mode);
+        self
+    }
+
+    ///
+    /// Audit mode: makes the file writers added after this call sync the file and all its metadata on the disk 
+    /// with `File::sync_all()` after each flush, as `with_sync(SyncPolicy::FullOnFlush)`, so the records flushed 
+    /// before a power loss are found after it. Slow, and it doesn't apply to the stdout writers (see with_sync()).
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn with_fsync(self) -> Logger {
+        self.with_sync(SyncPolicy::FullOnFlush)
+    }
+
+    ///
+    /// Makes the file writers added after this call start a new file at each local midnight ([`RotationPolicy::Daily`])
+    /// or hour ([`RotationPolicy::Hourly`]), renaming the closed one after its period: `app.log` becomes `app.2024-06-03.log`.
+    /// The file is closed with the first record of the next period. At init, a file last written in a previous period
+    /// (e.g. before a restart) is rotated immediately. A clock going backwards never reopens a previous period.
+    /// ```no_run
+    /// use rslogger::{Logger, RotationPolicy};
+    /// Logger::new()
+    ///     .with_rotation(RotationPolicy::Daily)
+    ///     .add_writer_file("app.log".into(), true, None)
+    ///     .init().unwrap();
+    /// ```
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn with_rotation(mut self, policy: RotationPolicy) -> Logger {
+        self.rotation = policy;
+        self
+    }
+
+    ///
+    /// Makes the rotating file writers added after this call name their rotated files after the local time 
+    /// they were closed, to the second ([`RotatedNaming::Timestamp`]): `app.2024-06-03T14-00-00.log`, 
+    /// then `app.2024-06-03T14-00-00.1.log` if two files are closed within the same second. 
+    /// The file being written is always `app.log`, and the rotated files are never renamed again.
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn with_rotated_naming(mut self, naming: RotatedNaming) -> Logger {
+        self.rotated_naming = naming;
+        self
+    }
+
+    ///
+    /// Makes the rotating file writers added after this call keep only the `max_backups` newest rotated files,
+    /// deleting the older ones after each rotation and at init. With N backups, the directory holds at most
+    /// N + 1 files of a writer. Only the files named as rotated from the file of the writer are deleted 
+    /// (`app.<period>.log` for `app.log`), the other files of the directory are never touched.
+    /// A file that can't be deleted is reported as a notice on the other writers, with the next record of the writer.
+    /// ```no_run
+    /// use rslogger::{Logger, RotationPolicy};
+    /// Logger::new()
+    ///     .with_rotation(RotationPolicy::Daily)
+    ///     .with_max_backups(7)
+    ///     .add_writer_file("app.log".into(), true, None)
+    ///     .init().unwrap();
+    /// ```
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn with_max_backups(mut self, max_backups: usize) -> Logger {
+        self.max_backups = Some(max_backups);
+        self
+    }
+
+    ///
+    /// Makes the rotating file writers added after this call delete the rotated files last written 
+    /// more than `max_age` ago, after each rotation and at init. As with_max_backups(), only 
+    /// the files named as rotated from the file of the writer are deleted.
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn with_max_age(mut self, max_age: Duration) -> Logger {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    ///
+    /// Makes the rotating file writers added after this call gzip their rotated files, `app.2024-06-03.log` 
+    /// becoming `app.2024-06-03.log.gz`. The compression runs on a helper thread, so logging doesn't wait for it. 
+    /// A file left uncompressed by a process ending during its compression is compressed at the next rotation 
+    /// or init. The compressed files count as rotated files for with_max_backups().
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn with_compression(mut self) -> Logger {
+        self.compress_backups = true;
+        self
+    }
+
+    ///
+    /// Bounds the queue of the separate thread writers added after this call to `capacity` records,
+    /// so a logging loop faster than the disk can't grow the memory until OOM.
+    /// When the queue of a writer is full, logging waits until the writer thread makes room.
+    /// By default the queues are unbounded.
+    /// The queue capacity is independent of the buffer capacity given when adding the writer: the queue 
+    /// gives backpressure to the logging threads, the buffer batches the writes of the writer thread.
+    /// ```no_run
+    /// use rslogger::Logger;
+    /// Logger::new()
+    ///     .with_queue_capacity(10_000) // Records waiting for the writer thread
+    ///     .add_writer_file("app.log".into(), true, Some(64 * 1024)) // Bytes per write on the file
+    ///     .init().unwrap();
+    /// ```
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn with_queue_capacity(mut self, capacity: usize) -> Logger {
+        self.queue_capacity = Some(capacity);
+        self
+    }
+
+    ///
+    /// Sets what the separate thread writers added after this call do with a record when their 
+    /// bounded queue is full (see [`with_queue_capacity`](Logger::with_queue_capacity)): 
+    /// wait for room (default), or drop records to never block the logging threads.
+    /// The dropped records are counted by [`dropped_records`].
+    /// ```no_run
+    /// use rslogger::{Logger, OverflowPolicy};
+    /// Logger::new()
+    ///     .with_queue_capacity(10_000)
+    ///     .with_overflow_policy(OverflowPolicy::DropOldest)
+    ///     .add_writer_file("app.log".into(), true, None)
+    ///     .init().unwrap();
+    /// ```
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn with_overflow_policy(mut self, policy: OverflowPolicy) -> Logger {
+        self.overflow_policy = policy;
+        self
+    }
+
+    ///
+    /// Warns when the queue of a separate thread writer added after this call reaches `high` records,
+    /// i.e. when the writer can't keep up, and again when the queue goes back down to `low` records.
+    /// Nothing more is reported in between, so a queue oscillating around `high` doesn't flood the log.
+    /// The warnings have the target `rslogger` and are written on the other writers, or on stderr if 
+    /// no other writer accepts them.
+    /// # Panics
+    /// If `low` is not less than `high`.
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn with_queue_watermarks(mut self, high: usize, low: usize) -> Logger {
+        assert!(low < high, "The low watermark must be less than the high watermark");
+        self.queue_watermarks = Some((high, low));
+        self
+    }
+
+    ///
+    /// Makes the separate thread writers added after this call flush their buffer when no record 
+    /// arrives for `idle` after a write. Under load the buffer fills up and is written as usual,
+    /// while in light traffic the records appear at most `idle` after being logged.
+    /// Unlike a periodic flush, nothing happens while no record is written.
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn with_idle_flush(mut self, idle: Duration) -> Logger {
+        self.idle_flush = Some(idle);
+        self
+    }
+
+    ///
+    /// Sets when the writers added after this call flush their buffer. By default the stdout writers 
+    /// flush after each record ([`FlushPolicy::EveryRecord`]), so the lines show up as they are logged, 
+    /// while the file writers flush only when the buffer is full ([`FlushPolicy::Buffered`]).
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn with_flush_policy(mut self, policy: FlushPolicy) -> Logger {
+        self.flush_policy = Some(policy);
+        self
+    }
+
+    ///
+    /// Sets the bytes written before and after each record by the writers added after this call, 
+    /// instead of the default `\n` terminator. Useful for downstream readers expecting framed records,
+    /// e.g. `\x1e` + record + `\n` for JSON text sequences.
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn with_record_framing(mut self, prefix: Vec<u8>, suffix: Vec<u8>) -> Logger {
+        self.record_framing = Some((prefix, suffix));
+        self
+    }
+
+    ///
+    /// Makes the writers added after this call write the record suffix (by default the `\n` terminator)
+    /// as a separator before the next record instead of after each record, so the output doesn't end 
+    /// with a terminator after the last flush. For consumers treating a trailing terminator as the start
+    /// of another record. Appending to a non-empty file starts with a separator.
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn without_final_suffix(mut self) -> Logger {
+        self.final_suffix = false;
+        self
+    }
+
+    ///
+    /// Sets the flush policy for records of `level` on the writers added after this call.
+    /// E.g. flushing Error records immediately while keeping Trace records buffered on the same writer.
+    /// By default all the levels are buffered.
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn with_level_flush(mut self, level: Level, policy: LevelFlush) -> Logger {
+        self.level_flush.retain(|(configured_level, _)| *configured_level != level);
+        self.level_flush.push((level, policy));
+        self
+    }
+
+    ///
+    /// Forwards every record to another logger in addition to the writers, e.g. to migrate 
+    /// gradually from another logging crate. The secondary logger receives the original record
+    /// (if its enabled() accepts it), not the formatted line, and is flushed with this logger.
+    /// Records logged back into this logger by the secondary one are dropped to avoid loops.
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn with_secondary_logger(mut self, secondary: Box<dyn Log>) -> Logger {
+        self.secondary = Some(secondary);
+        self
+    }
+
+    ///
+    /// Makes the writers added after this call start with a line recording the build of the application,
+    /// so a log file sent for support tells which build produced it:
+    /// ```no_run
+    /// use rslogger::Logger;
+    /// Logger::new()
+    ///     .with_build_info(env!("CARGO_PKG_VERSION"))
+    ///     .add_writer_file("app.log".into(), true, None)
+    ///     .init().unwrap();
+    /// // 10:00:00:000000-[][] -> {INFO} Build: 1.4.2
+    /// ```
+    /// The line is written as the writer is initialized, whatever the levels, with the `rslogger` target.
+    /// The build timestamp can be appended to `version`, e.g. from a variable set by a build script.
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn with_build_info(mut self, version: &str) -> Logger {
+        self.build_info = Some(version.to_string());
+        self
+    }
+
+    ///
+    /// Names the threads of the separate thread writers added after this call, as shown in thread dumps
+    /// and `top -H`. By default the name shows the target: `rslogger-stdout`, or `rslog:` followed by
+    /// the file name, cut to the 15 bytes kept by Linux.
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn with_writer_thread_name(mut self, name: &str) -> Logger {
+        self.writer_thread_name = Some(name.to_string());
+        self
+    }
+
+    ///
+    /// Lowers the scheduling priority of the threads of the separate thread writers added after this call,
+    /// so on a busy machine they lose the CPU to the threads of the application rather than the reverse: 
+    /// nice value 10 on Linux, utility QoS class on macOS, below normal priority on Windows.
+    /// Does nothing on the other platforms and for the tokio writer tasks.
+    /// A failure to lower the priority is reported on stderr, as the IO errors of the writers.
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn with_background_priority(mut self) -> Logger {
+        self.background_priority = true;
+        self
+    }
+
+    ///
+    /// Makes the separate thread writers added after this call (`multi_thread` true) share a single thread 
+    /// and a single queue, instead of a thread each. The output is the same, and each writer is still
+    /// flushed and stopped on its own. The queue capacity and the overflow policy don't apply to the shared queue.
+    /// A writer whose target panics drops its records from then on, the other writers of the thread go on.
+    /// If the thread can't be started, the writers fail to initialize.
+    /// ```no_run
+    /// use rslogger::Logger;
+    /// Logger::new()
+    ///     .with_shared_writer_thread()
+    ///     .add_writer_file("app.log".into(), true, None)
+    ///     .add_writer_file("errors.log".into(), true, None)
+    ///     .init().unwrap(); // A single writer thread
+    /// ```
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn with_shared_writer_thread(mut self) -> Logger {
+        self.share_writer_thread = true;
+        self
+    }
+
+    ///
+    /// Drops the writers added after this call when a writer already writes on the same destination:
+    /// stdout, or the same file. Without it, each writer on stdout prints every line again.
+    /// The first writer is kept with its own configuration, the level of the dropped one is not merged into it.
+    /// Each writer dropped is reported with a warning `Writer 1: dropped, same destination as writer 0`.
+    /// ```no_run
+    /// use rslogger::Logger;
+    /// Logger::new()
+    ///     .dedup_destinations()
+    ///     .add_writer_stdout(true, None)
+    ///     .add_writer_stdout(false, None) // Dropped
+    ///     .init().unwrap();
+    /// ```
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn dedup_destinations(mut self) -> Logger {
+        self.dedup_destinations = true;
+        self
+    }
+
+    ///
+    /// Guarantees that every writer receives the records in the same order, e.g. a this-thread stdout 
+    /// writer and a separate thread file writer, so the console and the file can be compared line by line.
+    /// Without it, two records logged at the same time by two threads can reach the writers in different orders.
+    /// 
+    /// The records are handed to the writers one at a time: the logging threads wait for each other
+    /// during the whole dispatch (including the writes of the this-thread writers), which reduces 
+    /// the throughput when many threads log concurrently.
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn with_strict_ordering(mut self) -> Logger {
+        self.strict_ordering = Some(Mutex::new(()));
+        self
+    }
+
+    ///
+    /// Never waits for a writer locked by another thread (logging or flushing on it) for more than 
+    /// a few attempts: the record is dropped for that writer and counted by dropped_records().
+    /// For latency-sensitive code, trading complete logs for a predictable cost of each logging call.
+    /// 
+    /// The separate thread writers hold their lock just long enough to queue the record, so they 
+    /// drop less than the this-thread writers, which write the target under the lock. With a bounded queue, 
+    /// pair it with an overflow policy dropping the records (see [`Logger::with_overflow_policy`]) so
+    /// a full queue doesn't block either. with_strict_ordering() still makes the logging threads wait for each other.
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn with_nonblocking_writers(mut self) -> Logger {
+        self.nonblocking_writers = true;
+        self
+    }
+
+    ///
+    /// Displays the `file:line` of each record before the message. On the colored writers 
+    /// (see with_colors(), stdout on a terminal) the location is an OSC 8 hyperlink to the source file,
+    /// which supporting terminals make clickable to open it in the editor (the others display the plain text).
+    /// File writers always receive the plain `file:line`. The location is not part of the CSV rows.
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn with_clickable_locations(mut self) -> Logger {
+        self.locations = true;
+        self
+    }
+
+    ///
+    /// Moves the layout of the lines (timestamp rendering, padding, CSV quoting) from the logging thread
+    /// to the thread of the separate thread writers, to take latency off the request-handling threads.
+    /// The logging thread still reads the clock and renders the message arguments (they can't outlive the call),
+    /// then sends these pieces instead of the line: slightly more data is copied per record, but less work
+    /// is done inline. The this-thread writers, the capture and the crash dump keep receiving lines laid out inline.
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn with_deferred_formatting(mut self) -> Logger {
+        self.deferred_formatting = true;
+        self
+    }
+
+    ///
+    /// Exits the process with `code` after logging a record at `level` or above (e.g. Error), 
+    /// so a supervisor can restart it. All the writers are flushed before exiting,
+    /// so the triggering record is written too. Only the records passing the logger level trigger the exit.
+    /// ```no_run
+    /// use rslogger::Logger;
+    /// Logger::new().with_exit_on(log::Level::Error, 1).add_writer_stdout(true, None).init().unwrap();
+    /// log::error!("Database unreachable"); // Written, then the process exits with code 1
+    /// ```
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn with_exit_on(mut self, level: Level, code: i32) -> Logger {
+        self.exit_on = Some((level, code));
+        self
+    }
+
+    ///
+    /// Keeps the last few hundred logged lines in memory and, if the process panics, 
+    /// writes them to `path` together with the panic message and location.
+    /// The lines are captured at the logger level, regardless of the levels of the writers.
+    /// The panic hook is installed at init() and chains to any previously installed hook.
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn with_crash_dump(mut self, path: PathBuf) -> Logger {
+        self.crash_dump = Some(Arc::new(CrashDump::new(path)));
+        self
+    }
+
+    ///
+    /// Writes a summary of the run on every writer when the logger is flushed and stopped,
+    /// e.g. at the end of a batch job, so the log tells at a glance how the run went:
+    /// ```no_run
+    /// use rslogger::Logger;
+    /// Logger::new()
+    ///     .with_shutdown_summary(3)
+    ///     .add_writer_file("job.log".into(), true, None)
+    ///     .init().unwrap();
+    /// // ...
+    /// log::logger().flush();
+    /// // 10:00:00:000000-[][] -> {INFO} Summary: 2 ERROR, 5 WARN, 120 INFO, 0 DEBUG, 0 TRACE, 8712 bytes. Top messages: 4x "retrying", 1x "done"
+    /// ```
+    /// The records written are counted per level at the logger level, along with the bytes of their lines.
+    /// The `top_messages` most frequent messages are listed, 0 to leave them out: only the messages 
+    /// without arguments (e.g. `warn!("retrying")`) and the first thousand distinct ones are tracked. 
+    /// The summary is written once, at the first flush.
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn with_shutdown_summary(mut self, top_messages: usize) -> Logger {
+        self.summary = Some(Arc::new(Summary::new(top_messages)));
+        self
+    }
+
+    ///
+    /// Adds a stdout writer. 
+    /// # Param
+    /// * `multi_thread` - If set to true, the writer will be multi thread, otherwise single thread
+    /// * `capacity` - The buffer capacity of the writer, in bytes (`Some(bytes)`) or in records (see [`BufferCapacity`]). If None, initializes it with the default capacity.
+    /// 
+    /// The stdout lock is only taken when the buffer is flushed to stdout, which happens after each record
+    /// by default so the lines show up as they are logged.
+    /// The dominant cost is stdout writing each line separately: for high throughput, use 
+    /// `with_flush_policy(FlushPolicy::Buffered)` and raise the capacity, e.g. to 8KB instead of the default 100 bytes.
+    /// Holding the stdout lock for the whole life of the writer is not an option: it would block
+    /// any print on the other threads, and the lock can't be moved to the separate thread.
+    /// 
+    /// Each stdout writer prints every record it accepts: two of them print the lines twice, see [`Logger::dedup_destinations`].
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn add_writer_stdout(mut self, multi_thread: bool, capacity: impl Into<BufferCapacity>) -> Logger {
+        self = self.add_writer_stdout_level(multi_thread, capacity, WriterLevel::Logger);
+        self
+    }
+
+    ///
+    /// Adds a stdout writer as [`Logger::add_writer_stdout`], returning the error if it fails to initialize
+    /// (e.g. its separate thread can't be started) instead of deferring it to init().
+    /// 
+    pub fn try_add_writer_stdout(mut self, multi_thread: bool, capacity: impl Into<BufferCapacity>) -> Result<Logger, Error> {
+        let writer = self.stdout_writer();
+        self.try_add_customized_writer(writer, multi_thread, capacity, WriterLevel::Logger, |writer| writer)?;
+        Ok(self)
+    }
+
+    ///
+    /// Adds a stdout writer. 
+    /// # Param
+    /// * `multi_thread` - If set to true, the writer will be multi thread, otherwise single thread
+    /// * `capacity` - The buffer capacity of the writer, in bytes (`Some(bytes)`) or in records (see [`BufferCapacity`]). If None, initializes it with the default capacity.
+    /// * `level` - Max level for this tracer, replacing the logger level: it may be more verbose.
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn add_writer_stdout_with_level(mut self, multi_thread: bool, capacity: impl Into<BufferCapacity>, level: LevelFilter) -> Logger {
+        self = self.add_writer_stdout_level(multi_thread, capacity, WriterLevel::Max(level));
+        self
+    }
+
+    ///
+    /// Adds a file writer. 
+    /// The path of the file writers can contain placeholders expanded at init, e.g. `./LOGS/app-{pid}-{date}.log`
+    /// to give each process its own file: `{pid}`, `{hostname}`, `{date}` (`2024-06-03`) and `{time}` (`14-00-00`),
+    /// in local time. A leading `~` is the home directory and `$VAR` or `${VAR}` the value of an environment variable,
+    /// e.g. `$XDG_STATE_HOME/my_app/app.log`. Literal braces are written `{{` and `}}`, a literal `$` is `$$`.
+    /// A writer whose path has an unknown placeholder or an unset variable fails to initialize.
+    /// 
+    /// If the writer fails to initialize (e.g. the file can't be opened), it's not added and init() returns the error, 
+    /// so the program doesn't run without its log file. To handle the error here, see [`Logger::try_add_writer_file`].
+    /// # Param
+    /// * `file_path` - The path of the file to write on.
+    /// * `multi_thread` - If set to true, the writer will be multi thread, otherwise single thread
+    /// * `capacity` - The buffer capacity of the writer, in bytes (`Some(bytes)`) or in records (see [`BufferCapacity`]). If None, initializes it with the default capacity.
+    /// * `level` - Max level for this tracer.
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn add_writer_file(self, file_path: PathBuf, multi_thread: bool, capacity: impl Into<BufferCapacity>) -> Logger {
+        self.add_writer_file_level(file_path, multi_thread, capacity, WriterLevel::Logger)
+    }
+
+    ///
+    /// Adds a file writer as [`Logger::add_writer_file`], returning the error if it fails to initialize
+    /// so the startup can be aborted right away:
+    /// ```no_run
+    /// use rslogger::Logger;
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// Logger::new()
+    ///     .try_add_writer_file("/var/log/my_app/app.log".into(), true, None)?
+    ///     .init()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    /// 
+    pub fn try_add_writer_file(mut self, file_path: PathBuf, multi_thread: bool, capacity: impl Into<BufferCapacity>) -> Result<Logger, Error> {
+        let writer = self.file_writer(file_path);
+        self.try_add_customized_writer(writer, multi_thread, capacity, WriterLevel::Logger, |writer| writer)?;
+        Ok(self)
+    }
+
+    ///
+    /// Adds a writer on a file already open for writing, without opening it by path: e.g. a file descriptor
+    /// inherited from the parent process, a temporary file or a memfd.
+    /// ```no_run
+    /// # #[cfg(unix)] {
+    /// use std::{fs::File, os::fd::FromRawFd};
+    /// use rslogger::Logger;
+    /// // The fd 3 was opened for us by the parent process.
+    /// let file = unsafe { File::from_raw_fd(3) };
+    /// Logger::new()
+    ///     .add_writer_open_file(file, true, None)
+    ///     .init().unwrap();
+    /// # }
+    /// ```
+    /// The CSV header, the block alignment and the sync policy apply as for the other file writers, 
+    /// but not the options tied to the path (rotation, backups, permissions, latest link, exclusive lock).
+    /// # Param
+    /// * `file` - The file to write on, opened for writing (or appending).
+    /// * `multi_thread` - If set to true, the writer will be multi thread, otherwise single thread
+    /// * `capacity` - The buffer capacity of the writer, in bytes (`Some(bytes)`) or in records (see [`BufferCapacity`]). If None, initializes it with the default capacity.
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn add_writer_open_file(self, file: File, multi_thread: bool, capacity: impl Into<BufferCapacity>) -> Logger {
+        let mut writer = BufferedWriter::new().on_open_file(file).with_sync(self.sync);
+        if self.layout.csv { writer = writer.with_header(CSV_HEADER.to_string()); }
+        if let Some(block_size) = self.block_size { writer = writer.with_block_alignment(block_size); }
+        self.add_customized_writer(writer, multi_thread, capacity, WriterLevel::Logger, |writer| writer)
+    }
+
+    ///
+    /// Adds a file writer. 
+    /// # Param
+    /// * `file_path` - The path of the file to write on.
+    /// * `multi_thread` - If set to true, the writer will be multi thread, otherwise single thread
+    /// * `capacity` - The buffer capacity of the writer, in bytes (`Some(bytes)`) or in records (see [`BufferCapacity`]). If None, initializes it with the default capacity.
+    /// * `level` - Max level for this tracer, replacing the logger level: it may be more verbose.
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn add_writer_file_with_level(self, file_path: PathBuf, multi_thread: bool, capacity: impl Into<BufferCapacity>, level: LevelFilter) -> Logger {
+        self.add_writer_file_level(file_path, multi_thread, capacity, WriterLevel::Max(level))
+    }
+
+    ///
+    /// Adds a file writer in `dir` for each `(level, file name)`, writing the records up to that level
+    /// as add_writer_file_with_level(), all with the same threading and buffer capacity:
+    /// ```no_run
+    /// use log::Level;
+    /// use rslogger::Logger;
+    /// Logger::new()
+    ///     .split_by_level("./LOGS".into(), &[(Level::Error, "errors.log"), (Level::Warn, "warnings.log"), (Level::Trace, "all.log")], true, None)
+    ///     .init().unwrap();
+    /// ```
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn split_by_level(mut self, dir: PathBuf, files: &[(Level, &str)], multi_thread: bool, capacity: impl Into<BufferCapacity>) -> Logger {
+        let capacity = capacity.into();
+        for (level, file_name) in files {
+            self = self.add_writer_file_level(dir.join(file_name), multi_thread, capacity, WriterLevel::Max(level.to_level_filter()));
+        }
+        self
+    }
+
+    ///
+    /// Adds a stdout writer configured by `options`, e.g. with its own queue capacity.
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn add_writer_stdout_opts(self, options: WriterOptions) -> Logger {
+        let writer = self.stdout_writer();
+        self.add_customized_writer(writer, options.threaded, options.buffer, options.filter(), |writer| options.apply(writer))
+    }
+
+    ///
+    /// Adds a file writer configured by `options`, e.g. with its own queue capacity.
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn add_writer_file_opts(self, file_path: PathBuf, options: WriterOptions) -> Logger {
+        let writer = self.file_writer(file_path);
+        self.add_customized_writer(writer, options.threaded, options.buffer, options.filter(), |writer| options.apply(writer))
+    }
+
+    ///
+    /// Adds a writer on any `Write` target (e.g. a `TcpStream` or a compressing encoder) configured by `options`,
+    /// going through the same buffering, threading and flushes as the built-in targets:
+    /// ```no_run
+    /// use std::net::TcpStream;
+    /// use rslogger::{Logger, WriterOptions};
+    /// let stream = TcpStream::connect("127.0.0.1:5140").unwrap();
+    /// Logger::new()
+    ///     .add_writer_sink(stream, WriterOptions { threaded: true, ..WriterOptions::default() })
+    ///     .init().unwrap();
+    /// ```
+    /// The sink is flushed when the buffer is, by default only when it's full. The records it logs itself are dropped.
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn add_writer_sink<W: Write + Send + Sync + 'static>(self, sink: W, options: WriterOptions) -> Logger {
+        let writer = BufferedWriter::new().on_custom(sink);
+        self.add_customized_writer(writer, options.threaded, options.buffer, options.filter(), |writer| options.apply(writer))
+    }
+
+    ///
+    /// Adds the writer built by `builder`, initializing it: see [`WriterBuilder`].
+    /// As for the other add_writer_*() functions, init() returns the error if it fails to initialize.
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn add_writer(self, builder: WriterBuilder) -> Logger {
+        let WriterBuilder { target, options } = builder;
+        let writer = match target {
+            BuilderTarget::StdOut => self.stdout_writer(),
+            BuilderTarget::File(file_path) => self.file_writer(file_path),
+            BuilderTarget::Null => BufferedWriter::new().on_null(),
+        };
+        self.add_customized_writer(writer, options.threaded, options.buffer, options.filter(), |writer| options.apply(writer))
+    }
+
+    ///
+    /// Adds a stdout writer accepting only the records with one of the given levels, e.g. Warn and Trace.
+    /// The records must still pass the logger level.
+    /// # Param
+    /// * `multi_thread` - If set to true, the writer will be multi thread, otherwise single thread
+    /// * `capacity` - The buffer capacity of the writer, in bytes (`Some(bytes)`) or in records (see [`BufferCapacity`]). If None, initializes it with the default capacity.
+    /// * `levels` - The levels accepted by this tracer.
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn add_writer_stdout_for_levels(self, multi_thread: bool, capacity: impl Into<BufferCapacity>, levels: &[Level]) -> Logger {
+        self.add_writer_stdout_level(multi_thread, capacity, WriterLevel::only(levels))
+    }
+
+    ///
+    /// Adds a file writer accepting only the records with one of the given levels, e.g. an audit
+    /// file with the Warn and Error records only. The records must still pass the logger level.
+    /// # Param
+    /// * `file_path` - The path of the file to write on.
+    /// * `multi_thread` - If set to true, the writer will be multi thread, otherwise single thread
+    /// * `capacity` - The buffer capacity of the writer, in bytes (`Some(bytes)`) or in records (see [`BufferCapacity`]). If None, initializes it with the default capacity.
+    /// * `levels` - The levels accepted by this tracer.
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn add_writer_file_for_levels(self, file_path: PathBuf, multi_thread: bool, capacity: impl Into<BufferCapacity>, levels: &[Level]) -> Logger {
+        self.add_writer_file_level(file_path, multi_thread, capacity, WriterLevel::only(levels))
+    }
+
+    ///
+    /// Adds a file writer running as a task of the current tokio runtime, writing through a `tokio::fs::File`,
+    /// instead of a dedicated thread. Must be called within a multi-thread tokio runtime. 
+    /// log() only pushes the records on a `tokio::sync::mpsc` channel, the task writes them in batches.
+    /// flush() can be called from sync code: it waits for the task (up to 5 seconds), letting the runtime
+    /// run the other tasks of the calling worker meanwhile. The logger must be flushed before the runtime is 
+    /// dropped, which cancels the task. The rotation, the sync policy and the block alignment don't apply.
+    /// Requires the `async-tokio` feature.
+    /// # Param
+    /// * `file_path` - The path of the file to write on.
+    /// * `capacity` - The buffer capacity of the writer, in bytes (`Some(bytes)`) or in records (see [`BufferCapacity`]). If None, initializes it with the default capacity.
+    /// 
+    #[cfg(feature = "async-tokio")]
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn add_writer_file_async(self, file_path: PathBuf, capacity: impl Into<BufferCapacity>) -> Logger {
+        let writer = self.file_writer(file_path).with_tokio_task();
+        self.add_configured_writer(writer, false, capacity, WriterLevel::Logger)
+    }
+
+    ///
+    /// Adds a file writer running as a task of the current tokio runtime as add_writer_file_async().
+    /// # Param
+    /// * `file_path` - The path of the file to write on.
+    /// * `capacity` - The buffer capacity of the writer, in bytes (`Some(bytes)`) or in records (see [`BufferCapacity`]). If None, initializes it with the default capacity.
+    /// * `level` - Max level for this tracer, replacing the logger level: it may be more verbose.
+    /// 
+    #[cfg(feature = "async-tokio")]
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn add_writer_file_async_with_level(self, file_path: PathBuf, capacity: impl Into<BufferCapacity>, level: LevelFilter) -> Logger {
+        let writer = self.file_writer(file_path).with_tokio_task();
+        self.add_configured_writer(writer, false, capacity, WriterLevel::Max(level))
+    }
+
+    ///
+    /// Adds a file writer running as a task of the current tokio runtime as add_writer_file_async(),
+    /// configured by `options` (`threaded` is ignored). With a queue capacity, the records are pushed with `try_send`:
+    /// with an overflow policy dropping the records, log() never waits for the writer.
+    /// Requires the `async-tokio` feature.
+    /// 
+    #[cfg(feature = "async-tokio")]
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn add_writer_file_async_opts(self, file_path: PathBuf, options: WriterOptions) -> Logger {
+        let writer = self.file_writer(file_path).with_tokio_task();
+        self.add_customized_writer(writer, false, options.buffer, options.filter(), |writer| options.apply(writer))
+    }
+
+    ///
+    /// Adds a file writer accepting the records by target, e.g. everything except a noisy dependency,
+    /// or only the records of this crate. A record is written if its target is in one of the `include` 
+    /// modules (or `include` is empty) and in none of the `exclude` modules. A module includes its submodules:
+    /// `my_crate` matches `my_crate::db` but not `my_crate_utils`.
+    /// # Param
+    /// * `file_path` - The path of the file to write on.
+    /// * `include` - The modules accepted, all if empty.
+    /// * `exclude` - The modules refused.
+    /// * `multi_thread` - If set to true, the writer will be multi thread, otherwise single thread
+    /// * `capacity` - The buffer capacity of the writer, in bytes (`Some(bytes)`) or in records (see [`BufferCapacity`]). If None, initializes it with the default capacity.
+    /// ```no_run
+    /// use rslogger::Logger;
+    /// use std::path::PathBuf;
+    /// Logger::new()
+    ///     .add_writer_file_with_targets(PathBuf::from("./LOGS/app.log"), vec![], vec!["hyper".to_string()], true, None)
+    ///     .add_writer_file_with_targets(PathBuf::from("./LOGS/mine.log"), vec!["my_crate".to_string()], vec![], true, None)
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn add_writer_file_with_targets(self, file_path: PathBuf, include: Vec<String>, exclude: Vec<String>, multi_thread: bool, capacity: impl Into<BufferCapacity>) -> Logger {
+        self.add_writer_file_level(file_path, multi_thread, capacity, WriterFilter { level: WriterLevel::Logger, include, exclude })
+    }
+
+    ///
+    /// Adds a writer discarding everything it receives. 
+    /// The records go through the same formatting, buffering and threading as the other writers,
+    /// so it can be used to benchmark the logging overhead without the IO variance.
+    /// # Param
+    /// * `multi_thread` - If set to true, the writer will be multi thread, otherwise single thread
+    /// * `capacity` - The buffer capacity of the writer, in bytes (`Some(bytes)`) or in records (see [`BufferCapacity`]). If None, initializes it with the default capacity.
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn add_writer_null(self, multi_thread: bool, capacity: impl Into<BufferCapacity>) -> Logger {
+        self.add_configured_writer(BufferedWriter::new().on_null(), multi_thread, capacity, WriterLevel::Logger)
+    }
+
+    ///
+    /// Adds a writer encoding each record as a compact binary frame instead of a line, for the bandwidth-constrained
+    /// links (e.g. a serial device) whose host tool decodes the frames: the level byte (1 for Error to 5 for Trace),
+    /// the length of the message as an unsigned LEB128 varint, then the message in UTF-8.
+    /// Only the message is sent, without timestamp, target or thread. The file options of the logger apply (e.g. with_sync()).
+    /// # Param
+    /// * `file_path` - The file (or device) to write the frames on
+    /// * `multi_thread` - If set to true, the writer will be multi thread, otherwise single thread
+    /// * `capacity` - The buffer capacity of the writer, in bytes (`Some(bytes)`) or in records (see [`BufferCapacity`]). If None, initializes it with the default capacity.
+    /// 
+    #[must_use = "You must call init() to initialize the logger"]
+    pub fn add_writer_framed(self, file_path: PathBuf, multi_thread: bool, capacity: impl Into<BufferCapacity>) -> Logger {
+        let writer = self.file_writer(file_path).with_binary_frames();
+        self.add_configured_writer(writer, multi_thread, capacity, WriterLevel::Logger)
+    }
+
+    ///
+    /// Adds a writer keeping the lines in memory, returning the logger with the handle to read them. 
+    /// The lines are never buffered, so every record is visible as soon as it's logged.
+    /// The logger doesn't need to be initialized, so each test can have its own capture.
+    /// # Example
+    /// ```
+    /// use rslogger::Logger;
+    /// let (logger, captured) = Logger::new().without_timestamps().add_writer_capture();
+    ///
+    /// // The log macros are gated by the global max level even when a logger is passed.
+    /// log::set_max_level(log::LevelFilter::Trace);
+    /// log::warn!(logger: logger, "disk almost full");
+    /// assert!(captured.contains("{WARN} disk almost full"));
+    ///
+    /// captured.clear();
+    /// assert!(captured.lines().is_empty());
+    /// ```
+    /// 
+    pub fn add_writer_capture(self) -> (Logger, CaptureHandle) {
+        let capture = CaptureHandle::default();
+        let writer = BufferedWriter::new().on_capture(capture.clone());
+
+        (self.add_configured_writer(writer, false, None, WriterLevel::Logger), capture)
+    }
+
+    ///
+    /// Installs the logger as the logger of the `log` crate.
+    /// Fails with the error of the first writer that could not be initialized, the logger not being installed, 
+    /// or if a logger was already installed.
+    /// 
+    pub fn init(mut self) -> Result<(), Error> {
+        if let Some(error) = self.init_error.take() {
+            return Err(error);
+        }
+        if let Timestamps::Uptime(start) = &mut self.timestamps {
+            *start = Instant::now();
+        }
+        let crash_dump = self.crash_dump.clone();
+        let max_level = self.max_level();
+
+        // Reachable by the free functions only once installed.
+        let raw = Box::into_raw(Box::new(self));
+        // SAFETY: leaked, so valid for 'static unless reclaimed below.
+        let logger: &'static Logger = unsafe { &*raw };
+        if let Err(error) = log::set_logger(logger) {
+            // SAFETY: the log crate refused the reference, nothing else holds it.
+            drop(unsafe { Box::from_raw(raw) });
+            return Err(error.into());
+        }
+        let _ = LOGGER.set(logger);
+        log::set_max_level(max_level);
+
+        if let Some(crash_dump) = crash_dump {
+            crash_dump.install_hook();
+        }
+        Ok(())
+    }
+
+    pub fn log_level(&self) -> LevelFilter {
+        self.log_level
+    }
+
+    ///
+    /// Runs `f` with the level of this logger replaced by `level` on the current thread only, e.g. to trace
+    /// a single request path in production without changing the level for the whole program.
+    /// Writers configured with their own level keep filtering with it, and the other loggers are not affected.
+    /// While an override of the global logger is active, the max level of the log crate is raised for all the threads,
+    /// so the records filtered on the other threads cost a call to the logger instead of being skipped by the macros.
+    /// 
+    pub fn with_level_override<R, F: FnOnce() -> R>(&self, level: LevelFilter, f: F) -> R {
+        level_override::run(self, level, f)
+    }
+
+    ///
+    /// Returns the number of records dropped by the writers because their queue was full,
+    /// because their separate thread was dead (it panicked) or because they were locked (see with_nonblocking_writers()),
+    /// and the records logged from within the logging pipeline, e.g. by the target of a writer.
+    /// 
+    pub fn dropped_records(&self) -> u64 {
+        self.writers.iter()
+            .map(|writer| writer.read().unwrap_or_else(PoisonError::into_inner).dropped())
+            .sum::<u64>() + self.discarded_records.load(Ordering::Relaxed)
+    }
+
+    ///
+    /// Returns the number of bytes written by the writers but not flushed yet, e.g. the records
+    /// that would be lost if the process was killed now. The writers on a separate thread
+    /// report it after handling each record, so it may lag behind the last records logged.
+    ///
+    pub fn buffered_len(&self) -> usize {
+        self.writers.iter()
+            .map(|writer| writer.read().unwrap_or_else(PoisonError::into_inner).buffered_len())
+            .sum()
+    }
+
+    ///
+    /// Flushes the writers without stopping them, unlike [`Log::flush`]: the logging goes on afterwards, 
+    /// e.g. to make the records durable periodically. The writers on the logging thread are flushed in place,
+    /// the separate threads flush after writing the records already queued, without being waited for.
+    /// For the global logger, see [`flush_soft`](crate::flush_soft()).
+    /// 
+    pub fn flush_soft(&self) {
+        let _pipeline = PipelineGuard::enter();
+        for writer in &self.writers {
+            writer.read().unwrap_or_else(PoisonError::into_inner).flush();
+        }
+    }
+
+    ///
+    /// Flushes and stops the writers as [`Log::flush`], returning what happened on them: the records 
+    /// written and dropped, the time taken by the flush and whether the separate threads stopped cleanly.
+    /// For the global logger, see [`flush_with_stats`](crate::flush_with_stats).
+    /// 
+    pub fn flush_with_stats(&self) -> FlushStats {
+        let start = Instant::now();
+        let mut stats = FlushStats { joined: true, ..FlushStats::default() };
+        let pipeline = PipelineGuard::enter();
+        if let Some(report) = self.summary.as_ref().and_then(|summary| summary.take_report()) {
+            for writer in &self.writers {
+                self.write_info_line(&writer.read().unwrap_or_else(PoisonError::into_inner), format_args!("{report}"));
+            }
+        }
+        for writer in &self.writers {
+            if let Ok(mut writer_mut) = writer.write() {
+                let writer_stats = writer_mut.flush_and_cleanup();
+                stats.written += writer_stats.written;
+                stats.dropped += writer_stats.dropped;
+                stats.joined &= writer_stats.joined;
+            } else {
+                panic!("Cannot get writer as mutable. RWLock is poisoned!");
+            }
+        }
+
+        stats.dropped += self.discarded_records.load(Ordering::Relaxed);
+        drop(pipeline);
+
+        if let Some(secondary) = &self.secondary {
+            secondary.flush();
+        }
+        stats.duration = start.elapsed();
+        stats
+    }
+
+    ///
+    /// Returns the most permissive level accepted by any writer, set as the max level of the log crate
+    /// so the macros skip the records every writer would discard.
+    /// A writer with its own level can raise it above the logger level.
+    /// The capture, the crash dump, the secondary logger and the subscribers accept the logger level.
+    /// 
+    pub(crate) fn max_level(&self) -> LevelFilter {
+        let level = self.target_levels.iter().map(|(_, level)| *level).fold(self.log_level, Ord::max);
+
+        let writers = self.writer_filters.iter()
+            .map(|filter| filter.level.max(level))
+            .max()
+            .unwrap_or(LevelFilter::Off);
+
+        let capturing = self.capture.read().unwrap_or_else(PoisonError::into_inner).is_some();
+        if capturing || self.crash_dump.is_some() || self.secondary.is_some() || subscribe::active() {
+            return writers.max(level);
+        }
+        writers
+    }
+
+
+    fn add_writer_stdout_level(self, multi_thread: bool, capacity: impl Into<BufferCapacity>, filter: impl Into<WriterFilter>) -> Logger {
+        let writer = self.stdout_writer();
+        self.add_configured_writer(writer, multi_thread, capacity, filter)
+    }
+
+    ///
+    /// Creates a stdout writer with the stdout options of the logger.
+    /// 
+    fn stdout_writer(&self) -> BufferedWriter {
+        let mut writer = BufferedWriter::new().on_stdout();
+        if self.colors && std::io::stdout().is_terminal() { writer = writer.with_colors(); }
+        if self.boxed_errors && std::io::stdout().is_terminal() { writer = writer.with_boxed_errors(); }
+        if self.stdout_lock_per_line { writer = writer.with_stdout_lock_per_line(); }
+        writer.with_sync(self.sync)
+    }
+
+
+    fn add_writer_file_level(self, file_path: PathBuf, multi_thread: bool, capacity: impl Into<BufferCapacity>, filter: impl Into<WriterFilter>) -> Logger {
+        let writer = self.file_writer(file_path);
+        self.add_configured_writer(writer, multi_thread, capacity, filter)
+    }
+
+    ///
+    /// Creates a file writer with the file options of the logger.
+    /// 
+    fn file_writer(&self, file_path: PathBuf) -> BufferedWriter {
+        let mut writer = BufferedWriter::new().on_file(file_path).with_create_dirs(self.create_dirs);
+        if let Some(mode) = self.dir_mode { writer = writer.with_dir_mode(mode); }
+        if let Some(mode) = self.file_mode { writer = writer.with_file_mode(mode); }
+        if let Some(link) = &self.latest_link { writer = writer.with_latest_link(link.clone()); }
+        if self.layout.csv { writer = writer.with_header(CSV_HEADER.to_string()); }
+        if let Some(block_size) = self.block_size { writer = writer.with_block_alignment(block_size); }
+        if let Some(max_backups) = self.max_backups { writer = writer.with_max_backups(max_backups); }
+        if let Some(max_age) = self.max_age { writer = writer.with_max_age(max_age); }
+        if self.compress_backups { writer = writer.with_compression(); }
+        writer.with_sync(self.sync).with_open_retries(self.open_retries.0, self.open_retries.1).with_open_mode(self.open_mode).with_rotation(self.rotation).with_rotated_naming(self.rotated_naming)
+    }
+
+    ///
+    /// Returns the level for the records of `target`, unless overridden on this thread by with_level_override().
+    /// 
+    fn effective_level(&self, target: &str) -> LevelFilter {
+        level_override::current(self).unwrap_or_else(|| self.target_level(target))
+    }
+
+    ///
+    /// Returns the level of the first target level matching `target`, or the level of the logger.
+    /// 
+    fn target_level(&self, target: &str) -> LevelFilter {
+        self.target_levels.iter()
+            .find(|(matcher, _)| matcher.matches(target))
+            .map_or(self.log_level, |(_, level)| *level)
+    }
+
+    ///
+    /// Returns true if the writer at `index` accepts records with this metadata, `level` being the effective level.
+    /// 
+    fn writer_accepts(&self, index: usize, metadata: &log::Metadata, level: LevelFilter) -> bool {
+        self.writer_filters[index].accepts(metadata, level)
+    }
+
+    ///
+    /// Returns true if a record with this metadata would be written somewhere: 
+    /// on a writer, in the active capture, in the crash dump or by a subscriber.
+    /// 
+    fn accepts(&self, metadata: &log::Metadata) -> bool {
+        let level = self.effective_level(metadata.target());
+
+        // The writers with their own level may accept the records above the logger level.
+        metadata.level() <= level && (self.crash_dump.is_some() || subscribe::active()
+                || self.capture.read().unwrap_or_else(PoisonError::into_inner).is_some())
+            || (0..self.writers.len()).any(|index| self.writer_accepts(index, metadata, level))
+    }
+
+    ///
+    /// Returns true if this logger is forwarding a record to its secondary logger on this thread.
+    /// 
+    fn is_forwarding(&self) -> bool {
+        FORWARDING.with(Cell::get) == self.address()
+    }
+
+    fn address(&self) -> usize {
+        (self as *const Logger).addr()
+    }
+
+    ///
+    /// Applies the common options to the writer, initializes it and pushes it with its filter.
+    /// 
+    fn add_configured_writer(self, writer: BufferedWriter, multi_thread: bool, capacity: impl Into<BufferCapacity>, filter: impl Into<WriterFilter>) -> Logger {
+        self.add_customized_writer(writer, multi_thread, capacity, filter, |writer| writer)
+    }
+
+    ///
+    /// As add_configured_writer(), customizing the writer after the common options.
+    /// 
+    fn add_customized_writer(mut self, writer: BufferedWriter, multi_thread: bool, capacity: impl Into<BufferCapacity>, 
+        filter: impl Into<WriterFilter>, customize: impl FnOnce(BufferedWriter) -> BufferedWriter) -> Logger {
+        // Deferred to init(), keeping the first error.
+        if let Err(error) = self.try_add_customized_writer(writer, multi_thread, capacity, filter, customize)
+            && self.init_error.is_none() {
+            self.init_error = Some(error);
+        }
+        self
+    }
+
+    ///
+    /// As add_customized_writer(), returning the error if the writer fails to initialize.
+    /// 
+    fn try_add_customized_writer(&mut self, mut writer: BufferedWriter, multi_thread: bool, capacity: impl Into<BufferCapacity>, 
+        filter: impl Into<WriterFilter>, customize: impl FnOnce(BufferedWriter) -> BufferedWriter) -> Result<(), Error> {
+        if self.dedup_destinations && let Some(other) = self.writers.iter()
+            .position(|other| other.read().unwrap_or_else(PoisonError::into_inner).same_destination(&writer)) {
+            self.write_notice(self.writers.len(), format_args!("dropped, same destination as writer {other}"));
+            return Ok(());
+        }
+        if multi_thread { 
+            writer = match self.shared_thread()? {
+                Some(shared_thread) => writer.with_shared_thread(shared_thread),
+                None => writer.with_separate_thread(),
+            };
+        }
+        // A capacity in records overrides the flush policy.
+        if let Some(policy) = self.flush_policy { writer = writer.with_flush_policy(policy); }
+        writer = writer.with_buffer_capacity(capacity);
+        if let Some((prefix, suffix)) = &self.record_framing {
+            writer = writer.with_record_framing(prefix.clone(), suffix.clone());
+        }
+        if !self.final_suffix { writer = writer.without_final_suffix(); }
+        if let Some(name) = &self.writer_thread_name { writer = writer.with_thread_name(name.clone()); }
+        if self.background_priority { writer = writer.with_background_priority(); }
+        for (flush_level, policy) in &self.level_flush {
+            writer = writer.with_level_flush(*flush_level, *policy);
+        }
+        if let Some(idle) = self.idle_flush { writer = writer.with_idle_flush(idle); }
+        if let Some(queue_capacity) = self.queue_capacity { writer = writer.with_queue_capacity(queue_capacity); }
+        writer = writer.with_overflow_policy(self.overflow_policy);
+        if let Some((high, low)) = self.queue_watermarks { writer = writer.with_queue_watermarks(high, low); }
+        writer = customize(writer);
+
+        let initialized_writer = writer.init()?;
+        for warning in initialized_writer.init_warnings() {
+            self.write_notice(self.writers.len(), warning);
+        }
+        for notice in initialized_writer.take_notices() {
+            self.write_notice(self.writers.len(), notice);
+        }
+        if let Some(version) = &self.build_info {
+            self.write_info_line(&initialized_writer, format_args!("Build: {version}"));
+        }
+        self.writers.push(RwLock::new(initialized_writer));
+        self.writer_filters.push(filter.into());
+        Ok(())
+    }
+
+    ///
+    /// Returns the thread shared by the separate thread writers if enabled, starting it at the first call.
+    /// If it can't be started, returns the error of the thread spawn.
+    /// 
+    fn shared_thread(&mut self) -> Result<Option<Arc<SharedThread>>, Error> {
+        if self.share_writer_thread && self.shared_thread.is_none() {
+            let shared_thread = SharedThread::new(self.background_priority).map_err(Error::ThreadSpawn)?;
+            self.shared_thread = Some(Arc::new(shared_thread));
+        }
+        Ok(self.shared_thread.clone())
+    }
+
+    ///
+    /// Formats the record and writes it on the accepting writers, or in the active capture.
+    /// 
+    fn write_record(&self, record: &log::Record) {
+        // Nothing is formatted if the record would be discarded by every writer.
+        if !self.accepts(record.metadata()) {
+            return;
+        }
+        let level = self.effective_level(record.target());
+        let capture = self.capture.read().unwrap_or_else(PoisonError::into_inner);
+
+        let mut target = "";
+
+        if self.target {
+            target = if !record.target().is_empty() {
+                    record.target()
+                } else {
+                    record.module_path().unwrap_or_default()
+                };
+        }
+
+
+        // The name is borrowed from the current thread handle, to format it without copying.
+        let current_thread = std::thread::current();
+        let thread = if self.thread {
+            if let Some(thread_name) = current_thread.name() {
+                Cow::Borrowed(thread_name)
+            } else {
+                Cow::Owned(format!("{:?}", current_thread.id()))
+            }
+        } else {
+            Cow::Borrowed("")
+        };
+        
+        let time = self.timestamps.now();
+        let location = record.file().zip(record.line()).filter(|_| self.locations);
+        let format = |linked: bool| -> Arc<str> {
+            let location = location.map(|(file, line)| format_location(file, line, linked)).unwrap_or_default();
+            let timestamp = self.timestamps.render(time);
+            shared_line(|line| write_line(line, self.layout, &timestamp, record.level(), target, &thread, &location, record.args()))
+        };
+
+        // Shared by the writers, so the separate threads receive it without a copy each.
+        let mut message: Option<Arc<str>> = None;
+
+        if record.level() <= level && (self.crash_dump.is_some() || capture.is_some()) {
+            let line = message.insert(format(false));
+            if let Some(crash_dump) = &self.crash_dump {
+                crash_dump.push(line);
+            }
+            if let Some(capture) = capture.as_ref() {
+                capture.push(line);
+                capture.push_record(record.level(), record.target(), *record.args());
+                if let Some(summary) = &self.summary {
+                    summary.count(record.level(), record.args().as_str(), line.len());
+                }
+                return;
+            }
+        }
+        drop(capture);
+
+        let mut colored_message: Option<Arc<str>> = None;
+        let mut frame_message: Option<Arc<str>> = None;
+        let mut boxed_message: Option<String> = None;
+        let mut deferred: Option<Arc<DeferredRecord>> = None;
+        let mut notices = Vec::new();
+        let _sequence = self.strict_ordering.as_ref().map(|sequence| sequence.lock().unwrap_or_else(PoisonError::into_inner));
+
+        for (index, writer) in self.writers.iter().enumerate() {
+            // Skip as this trace should not be traced on this writer!
+            if !self.writer_accepts(index, record.metadata(), level) {
+                continue;
+            }
+
+            if let Some(writer_mut) = self.lock_writer(writer) {
+                if writer_mut.binary_frames() {
+                    writer_mut.write(frame_message.get_or_insert_with(|| format_frame(record.level(), record.args())));
+                } else if record.level() == Level::Error && writer_mut.boxed_errors() {
+                    // Boxed writers are terminals, where the locations are linked.
+                    let boxed = boxed_message.get_or_insert_with(|| draw_box(&format(location.is_some())));
+                    if writer_mut.colors() {
+                        writer_mut.write(&color_line(record.level(), boxed, terminal::width()).into());
+                    } else {
+                        writer_mut.write(&Arc::from(boxed.as_str()));
+                    }
+                } else if self.deferred_formatting && writer_mut.is_separate_thread() {
+                    let deferred = deferred.get_or_insert_with(|| Arc::new(DeferredRecord {
+                        level: record.level(),
+                        target: target.to_string(),
+                        thread: thread.to_string(),
+                        time,
+                        args: record.args().to_string(),
+                        location: location.map(|(file, line)| (file.to_string(), line)),
+                        timestamps: self.timestamps,
+                        layout: self.layout,
+                    }));
+                    writer_mut.write_deferred(deferred);
+                } else if writer_mut.colors() {
+                    let colored = colored_message.get_or_insert_with(|| {
+                        let line = if location.is_some() {
+                            format(true)
+                        } else {
+                            Arc::clone(message.get_or_insert_with(|| format(false)))
+                        };
+                        color_line(record.level(), &line, terminal::width()).into()
+                    });
+                    writer_mut.write(colored);
+                } else {
+                    writer_mut.write(message.get_or_insert_with(|| format(false)));
+                }
+                if let Some(capture) = writer_mut.capture() {
+                    capture.push_record(record.level(), record.target(), *record.args());
+                }
+                if writer_mut.level_flush(record.level()) == LevelFlush::Immediate {
+                    writer_mut.flush();
+                }
+                if let Some(watermark) = writer_mut.queue_watermark() {
+                    notices.push((index, watermark.to_string()));
+                }
+                notices.extend(writer_mut.take_notices().into_iter().map(|notice| (index, notice)));
+            } else {
+                self.discarded_records.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        // The line of the writers, laid out here only if none of them wrote it as such.
+        if let Some(summary) = &self.summary
+            && record.level() <= level {
+            summary.count(record.level(), record.args().as_str(), message.get_or_insert_with(|| format(false)).len());
+        }
+
+        for (index, notice) in notices {
+            self.write_notice(index, notice);
+        }
+    }
+
+    ///
+    /// Takes the lock of a writer for writing. With nonblocking writers, gives up after 
+    /// NONBLOCKING_ATTEMPTS attempts if another thread holds it.
+    /// # Panics
+    /// If the RWLock is poisoned.
+    /// 
+    fn lock_writer<'a>(&self, writer: &'a RwLock<BufferedWriter>) -> Option<RwLockWriteGuard<'a, BufferedWriter>> {
+        if !self.nonblocking_writers {
+            return Some(writer.write().unwrap_or_else(|_| panic!("Cannot get writer as mutable. RWLock is poisoned!")));
+        }
+        for _ in 0..NONBLOCKING_ATTEMPTS {
+            match writer.try_write() {
+                Ok(writer_mut) => return Some(writer_mut),
+                Err(TryLockError::WouldBlock) => std::hint::spin_loop(),
+                Err(TryLockError::Poisoned(_)) => panic!("Cannot get writer as mutable. RWLock is poisoned!"),
+            }
+        }
+        None
+    }
+
+    ///
+    /// Writes an Info line of the logger itself on a writer, whatever its level: 
+    /// the build line (see with_build_info()) or the shutdown summary (see with_shutdown_summary()).
+    /// 
+    fn write_info_line(&self, writer: &BufferedWriter, message: fmt::Arguments) {
+        let target = if self.target { NOTICE_TARGET } else { "" };
+        if writer.binary_frames() {
+            writer.write(&format_frame(Level::Info, message));
+            return;
+        }
+        let line = format_line(self.layout, &self.timestamps.render(self.timestamps.now()), Level::Info, target, "", "", 
+            message);
+        if writer.colors() {
+            writer.write(&color_line(Level::Info, &line, terminal::width()).into());
+        } else {
+            writer.write(&line.into());
+        }
+        if let Some(capture) = writer.capture() {
+            capture.push_record(Level::Info, NOTICE_TARGET, message);
+        }
+    }
+
+    ///
+    /// Writes a warning about the writer at `index` on the other writers accepting it, or on stderr if none does.
+    /// 
+    fn write_notice(&self, index: usize, notice: impl fmt::Display) {
+        let metadata = log::Metadata::builder().level(Level::Warn).target(NOTICE_TARGET).build();
+        let level = self.effective_level(NOTICE_TARGET);
+        let target = if self.target { NOTICE_TARGET } else { "" };
+        let line: Arc<str> = format_line(self.layout, &self.timestamps.render(self.timestamps.now()), Level::Warn, target, "", "", 
+            format_args!("Writer {index}: {notice}")).into();
+
+        let mut written = false;
+        for (other, writer) in self.writers.iter().enumerate() {
+            if other == index || !self.writer_accepts(other, &metadata, level) {
+                continue;
+            }
+
+            let writer = writer.read().unwrap_or_else(PoisonError::into_inner);
+            if writer.binary_frames() {
+                writer.write(&format_frame(Level::Warn, format_args!("Writer {index}: {notice}")));
+            } else if writer.colors() {
+                writer.write(&color_line(Level::Warn, &line, terminal::width()).into());
+            } else {
+                writer.write(&line);
+            }
+            if let Some(capture) = writer.capture() {
+                capture.push_record(Level::Warn, NOTICE_TARGET, format_args!("Writer {index}: {notice}"));
+            }
+            written = true;
+        }
+
+        if !written {
+            eprintln!("{line}");
+        }
+    }
+}
+
+impl Default for Logger {
+    fn default() -> Self {
+        Logger::new()
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        !self.suspended.load(Ordering::Relaxed) && self.accepts(metadata)
+            || self.secondary.as_ref().is_some_and(|secondary| 
+                !self.is_forwarding() && secondary.enabled(metadata))
+    }
+
+    fn log(&self, record: &log::Record) {
+        // The record was logged back by the secondary logger: it was already handled.
+        if self.suspended.load(Ordering::Relaxed) || self.is_forwarding() {
+            return;
+        }
+
+        // Logged from within the pipeline, e.g. by the target of a writer: writing it would recurse 
+        // or deadlock on the lock of the writer.
+        if pipeline::active() {
+            self.discarded_records.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+
+        if let Some(secondary) = &self.secondary {
+            let previous = FORWARDING.with(|forwarding| forwarding.replace(self.address()));
+            if secondary.enabled(record.metadata()) {
+                secondary.log(record);
+            }
+            FORWARDING.with(|forwarding| forwarding.set(previous));
+        }
+
+        let pipeline = PipelineGuard::enter();
+        self.write_record(record);
+        drop(pipeline);
+
+        if subscribe::active() && record.level() <= self.effective_level(record.target()) {
+            subscribe::notify(record);
+        }
+
+        if let Some((exit_level, code)) = self.exit_on 
+            && record.level() <= exit_level
+            && record.level().to_level_filter() <= self.effective_level(record.target()) {
+            // The triggering record must reach the files before the process ends.
+            self.flush();
+            std::process::exit(code);
+        }
+    }
+
+    ///
+    /// Flushes to ensure that all possible buffered data are logged. 
+    /// This method should be called right before closing the program or when you don't need to 
+    /// log anything else. 
+    /// This is because in case of separate thread, the logger thread will be stopped.
+    /// # Example
+    /// ```
+    /// use rslogger::Logger;
+    /// use log::{info, warn, error};
+    /// Logger::new()
+    ///     .with_level(log::LevelFilter::Trace)
+    ///     .with_local_timestamps()
+    ///     .add_writer_stdout(true, Some(1000))
+    ///     .init().unwrap();
+    /// info!("This is a info test");
+    /// warn!("This is a warn test");
+    /// error!("This is an error test");
+    /// log::logger().flush();
+    /// let result = std::panic::catch_unwind(|| info!("Test"));
+    /// assert!(result.is_err())
+    /// ```
+    /// 
+    fn flush(&self) {
+        self.flush_with_stats();
+    }
+
+}
+
+///
+/// Returns the number of records dropped by the writers of the global logger because their queue was full
+/// (see [`Logger::with_overflow_policy`]) or their separate thread was dead. Returns 0 if the logger was not initialized.
+///
+pub fn dropped_records() -> u64 {
+    LOGGER.get().map_or(0, |logger| logger.dropped_records())
+}
+
+///
+/// Flushes and stops the writers of the global logger as `log::logger().flush()`, returning what 
+/// happened on them (see [`Logger::flush_with_stats`]). Returns None if the logger was not initialized.
+///
+pub fn flush_with_stats() -> Option<FlushStats> {
+    LOGGER.get().map(|logger| logger.flush_with_stats())
+}
+
+///
+/// Flushes the writers of the global logger without stopping them (see [`Logger::flush_soft`]), 
+/// unlike `log::logger().flush()` after which nothing can be logged. Does nothing if the logger was not initialized.
+///
+pub fn flush_soft() {
+    if let Some(logger) = LOGGER.get() {
+        logger.flush_soft();
+    }
+}
+
+///
+/// Returns the number of bytes written by the writers of the global logger but not flushed yet
+/// (see [`Logger::buffered_len`]). Returns 0 if the logger was not initialized.
+///
+pub fn buffered_len() -> usize {
+    LOGGER.get().map_or(0, |logger| logger.buffered_len())
+}
+
+///
+/// Where the timestamp is displayed in the lines (see [`Logger::with_timestamp_position`]).
+///
+#[derive(PartialEq, Clone, Copy, Debug, Default)]
+pub enum TimestampPosition {
+    /// `10:00:00:000000-[][] -> {INFO} message` (default).
+    #[default]
+    Start,
+    /// `[][] -> {INFO} message 10:00:00:000000`, for the viewers showing the message first.
+    End,
+}
+
+///
+/// How the lines of the records are laid out, see with_csv(), with_json(), with_short_levels(), with_timestamp_position() 
+/// and with_sanitize_output().
+///
+#[derive(PartialEq, Clone, Copy, Default)]
+struct LineLayout {
+    /// CSV rows instead of text lines.
+    csv: bool,
+    /// JSON objects instead of text lines.
+    json: bool,
+    /// The levels as a single character.
+    short_levels: bool,
+    /// Where the timestamp is displayed in the text lines.
+    timestamp_position: TimestampPosition,
+    /// The control characters of the messages escaped.
+    sanitize: bool,
+}
+
+impl LineLayout {
+    fn level(self, level: Level) -> &'static str {
+        if !self.short_levels {
+            return level.as_str();
+        }
+        match level {
+            Level::Error => "E",
+            Level::Warn => "W",
+            Level::Info => "I",
+            Level::Debug => "D",
+            Level::Trace => "T",
+        }
+    }
+}
+
+///
+/// Lays out the line of a record: a CSV row, a JSON object, `timestamp-[target][thread] -> {LEVEL} message` 
+/// or `[target][thread] -> {LEVEL} message timestamp`.
+///
+fn format_line(layout: LineLayout, timestamp: &str, level: Level, target: &str, thread: &str, location: &str, args: impl fmt::Display) -> String {
+    let mut line = String::new();
+    write_line(&mut line, layout, timestamp, level, target, thread, location, args);
+    line
+}
+
+///
+/// Lays out the line as format_line(), appending it to `line`.
+///
+#[allow(clippy::too_many_arguments)]
+fn write_line(line: &mut String, layout: LineLayout, timestamp: &str, level: Level, target: &str, thread: &str, location: &str, 
+    args: impl fmt::Display) {
+    let args = Sanitized::new(args, layout.sanitize);
+    let level = layout.level(level);
+    // Writing on a String never fails.
+    let _ = if layout.csv {
+        for field in [timestamp, level, target, thread] {
+            push_csv_field(line, format_args!("{field}"));
+            line.push(',');
+        }
+        push_csv_field(line, format_args!("{args}"));
+        Ok(())
+    } else if layout.json {
+        line.push('{');
+        for (name, field) in [("timestamp", timestamp), ("level", level), ("target", target), ("thread", thread)] {
+            if !field.is_empty() {
+                let _ = write!(line, "\"{name}\":");
+                push_json_string(line, format_args!("{field}"));
+                line.push(',');
+            }
+        }
+        line.push_str("\"message\":");
+        push_json_string(line, format_args!("{args}"));
+        line.push('}');
+        Ok(())
+    } else if layout.timestamp_position == TimestampPosition::End {
+        let separator = if timestamp.is_empty() { "" } else { " " };
+        write!(line, "[{target}][{thread}] -> {{{level}}} {location}{args}{separator}{timestamp}")
+    } else {
+        write!(line, "{timestamp}-[{target}][{thread}] -> {{{level}}} {location}{args}")
+    };
+}
+
+thread_local! {
+    /// Reused to lay out the lines shared by the writers, so they are formatted without allocating.
+    static LINE_BUFFER: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+///
+/// Lays out a line with `layout` in the buffer of the thread, then copies it once into the shared line.
+/// Falls back to a new buffer if the layout logs itself (e.g. from the Display of an argument).
+///
+fn shared_line(layout: impl FnOnce(&mut String)) -> Arc<str> {
+    LINE_BUFFER.with(|buffer| match buffer.try_borrow_mut() {
+        Ok(mut buffer) => {
+            buffer.clear();
+            layout(&mut buffer);
+            Arc::from(buffer.as_str())
+        }
+        Err(_) => {
+            let mut line = String::new();
+            layout(&mut line);
+            line.into()
+        }
+    })
+}
+
+///
+/// Lays out the message of a binary frame writer: the level byte followed by the text, the writer inserts the length.
+///
+fn format_frame(level: Level, args: impl fmt::Display) -> Arc<str> {
+    shared_line(|frame| {
+        frame.push(char::from(level as u8));
+        let _ = write!(frame, "{args}");
+    })
+}
+
+///
+/// Renders `file:line ` in front of the message, as an OSC 8 hyperlink to the source file if `linked`.
+/// Relative paths are resolved against the current directory, which is usually the crate root.
+///
+fn format_location(file: &str, line: u32, linked: bool) -> String {
+    if !linked {
+        return format!("{file}:{line} ");
+    }
+
+    let path = std::env::current_dir().map(|dir| dir.join(file)).unwrap_or_else(|_| PathBuf::from(file));
+    format!("\x1b]8;;file://{}\x1b\\{file}:{line}\x1b]8;;\x1b\\ ", path.display())
+}
+
+///
+/// Renders `now` in UTC and with `offset`: `10:00:00:000000Z (12:00 local)`.
+///
+fn dual_timestamp(now: UtcDateTime, offset: UtcOffset) -> String {
+    format!(
+        "{}Z ({} local)",
+        now.format(TIMESTMAMP_FORMAT).unwrap(),
+        now.to_offset(offset).format(DUAL_LOCAL_FORMAT).unwrap()
+    )
+}
+
+///
+/// Renders the elapsed time as `D+HH:MM:SS.nnnnnnnnn`.
+///
+fn uptime_timestamp(elapsed: Duration) -> String {
+    let seconds = elapsed.as_secs();
+    format!(
+        "{}+{:02}:{:02}:{:02}.{:09}",
+        seconds / 86_400,
+        seconds / 3600 % 24,
+        seconds / 60 % 60,
+        seconds % 60,
+        elapsed.subsec_nanos()
+    )
+}
+
+///
+/// Quotes the field if it contains a comma, a quote or a newline, doubling the quotes (RFC 4180).
+/// 
+fn push_csv_field(line: &mut String, field: fmt::Arguments) {
+    let start = line.len();
+    let _ = line.write_fmt(field);
+    if line[start..].contains([',', '"', '\n', '\r']) {
+        let field = line.split_off(start);
+        line.push('"');
+        line.push_str(&field.replace('"', "\"\""));
+        line.push('"');
+    }
+}
+
+///
+/// Writes the field as a JSON string, escaping the quotes, the backslashes and the control characters.
+/// 
+fn push_json_string(line: &mut String, field: fmt::Arguments) {
+    line.push('"');
+    let start = line.len();
+    let _ = line.write_fmt(field);
+    if line[start..].contains(|c: char| c == '"' || c == '\\' || c.is_ascii_control()) {
+        let field = line.split_off(start);
+        for c in field.chars() {
+            let _ = match c {
+                '"' => write!(line, "\\\""),
+                '\\' => write!(line, "\\\\"),
+                '\n' => write!(line, "\\n"),
+                '\r' => write!(line, "\\r"),
+                '\t' => write!(line, "\\t"),
+                c if c.is_ascii_control() => write!(line, "\\u{:04x}", c as u32),
+                c => write!(line, "{c}"),
+            };
+        }
+    }
+    line.push('"');
+}
+
+///
+/// Draws a box on the left of the lines of a record, with rules as long as the longest line.
+///
+fn draw_box(line: &str) -> String {
+    let rule = "─".repeat(line.lines().map(visible_width).max().unwrap_or_default() + 1);
+    let mut boxed = format!("┌{rule}\n");
+    for line in line.lines() {
+        boxed.push_str("│ ");
+        boxed.push_str(line);
+        boxed.push('\n');
+    }
+    boxed.push('└');
+    boxed.push_str(&rule);
+    boxed
+}
+
+///
+/// Returns the number of characters displayed by the line, skipping the ANSI escape sequences 
+/// (colors and links).
+///
+fn visible_width(line: &str) -> usize {
+    let mut width = 0;
+    let mut chars = line.chars();
+    while let Some(char) = chars.next() {
+        if char != '\x1b' {
+            width += 1;
+            continue;
+        }
+
+        match chars.next() {
+            // Up to the final letter, e.g. `\x1b[31m`.
+            Some('[') => {
+                let _ = chars.by_ref().find(char::is_ascii_alphabetic);
+            }
+            // Up to the string terminator `\x1b\\`.
+            Some(']') => {
+                let _ = chars.by_ref().find(|char| *char == '\x1b');
+                chars.next();
+            }
+            _ => {}
+        }
+    }
+    width
+}
+
+///
+/// Returns the ANSI color sequence used for the lines of the given level.
+/// 
+fn level_color(level: Level) -> &'static str {
+    match level {
+        Level::Error => "\x1b[31m",
+        Level::Warn => "\x1b[33m",
+        Level::Info => "\x1b[32m",
+        Level::Debug => "\x1b[34m",
+        Level::Trace => "\x1b[35m",
+    }
+}
+
+///
+/// Colors the line for the given level. The color is reset before each line terminator and, when the
+/// terminal width is known, before each point where the terminal wraps the line, then applied again:
+/// the rows scrolled in by the terminal and a line cut short by a crash are never left colored.
+///
+fn color_line(level: Level, line: &str, width: Option<usize>) -> String {
+    let color = level_color(level);
+    let mut colored = String::with_capacity(line.len() + 2 * color.len() + ANSI_RESET.len());
+    colored.push_str(color);
+
+    let mut column = 0;
+    let mut rest = line;
+    while let Some(char) = rest.chars().next() {
+        let length = match char {
+            '\x1b' => escape_length(rest),
+            _ => char.len_utf8(),
+        };
+        match char {
+            '\x1b' => {}
+            '\n' => {
+                colored.push_str(ANSI_RESET);
+                colored.push('\n');
+                colored.push_str(color);
+                column = 0;
+                rest = &rest[length..];
+                continue;
+            }
+            _ if width == Some(column) => {
+                colored.push_str(ANSI_RESET);
+                colored.push_str(color);
+                column = 1;
+            }
+            _ => column += 1,
+        }
+        colored.push_str(&rest[..length]);
+        rest = &rest[length..];
+    }
+
+    colored.push_str(ANSI_RESET);
+    colored
+}
+
+///
+/// Returns the length in bytes of the ANSI escape sequence at the start of the string, as skipped
+/// by `visible_width()`.
+///
+fn escape_length(sequence: &str) -> usize {
+    let end = match sequence.as_bytes().get(1) {
+        Some(b'[') => sequence[2..].find(|char: char| char.is_ascii_alphabetic()).map(|end| end + 3),
+        Some(b']') => sequence[2..].find('\x1b').map(|end| (end + 4).min(sequence.len())),
+        Some(_) => Some(2),
+        None => Some(1),
+    };
+    end.unwrap_or(sequence.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use log::{Metadata, Level, Record};
+
+    use super::*;
+
+    #[test]
+    fn test_default_level() {
+        let builder = Logger::new();
+        assert_eq!(builder.log_level(), LevelFilter::Trace);
+    }
+
+    #[test]
+    fn test_creation_level() {
+        let builder = Logger::new().with_level(LevelFilter::Debug);
+        assert_eq!(builder.log_level(), LevelFilter::Debug);
+    }
+
+    #[test]
+    fn test_logger_enabled() {
+        let logger = Logger::new().with_level(LevelFilter::Debug).add_writer_null(false, None);
+        assert_eq!(logger.log_level(), LevelFilter::Debug);
+        assert!(logger.enabled(&create_log("test_enabled", Level::Debug)));
+    }
+
+    #[test]
+    fn test_enabled_follows_writers() {
+        let logger = Logger::new().with_level(LevelFilter::Trace);
+        assert!(!logger.enabled(&create_log("test_enabled", Level::Error)));
+
+        let logger = Logger::new()
+            .with_level(LevelFilter::Trace)
+            .add_writer_stdout_with_level(false, None, LevelFilter::Info)
+            .add_writer_stdout_with_level(false, None, LevelFilter::Warn);
+        assert!(logger.enabled(&create_log("test_enabled", Level::Info)));
+        assert!(!logger.enabled(&create_log("test_enabled", Level::Debug)));
+
+        let logger = logger.add_writer_null(false, None);
+        assert!(logger.enabled(&create_log("test_enabled", Level::Trace)));
+
+        let logger = Logger::new().with_level(LevelFilter::Warn).add_writer_stdout_with_level(false, None, LevelFilter::Info);
+        assert!(logger.enabled(&create_log("test_enabled", Level::Info)));
+        assert!(!logger.enabled(&create_log("test_enabled", Level::Debug)));
+    }
+
+    #[test]
+    fn test_max_level_from_writers() {
+        let logger = Logger::new()
+            .with_level(LevelFilter::Trace)
+            .add_writer_stdout_with_level(false, None, LevelFilter::Info)
+            .add_writer_stdout_with_level(false, None, LevelFilter::Warn);
+        assert_eq!(logger.max_level(), LevelFilter::Info);
+
+        let logger = Logger::new().with_level(LevelFilter::Warn).add_writer_stdout_with_level(false, None, LevelFilter::Trace);
+        assert_eq!(logger.max_level(), LevelFilter::Trace);
+
+        let logger = Logger::new().with_level(LevelFilter::Debug).add_writer_null(false, None);
+        assert_eq!(logger.max_level(), LevelFilter::Debug);
+
+        assert_eq!(Logger::new().max_level(), LevelFilter::Off);
+
+        // A target level raises the writers following the logger level, not the capped ones.
+        let logger = Logger::new()
+            .with_level(LevelFilter::Info)
+            .with_target_level("app::db", LevelFilter::Trace)
+            .add_writer_stdout_with_level(false, None, LevelFilter::Warn);
+        assert_eq!(logger.max_level(), LevelFilter::Warn);
+        assert_eq!(logger.add_writer_null(false, None).max_level(), LevelFilter::Trace);
+
+        // The secondary logger accepts the logger level, whatever the writers.
+        let logger = Logger::new()
+            .with_level(LevelFilter::Debug)
+            .with_secondary_logger(Box::new(Logger::new()))
+            .add_writer_stdout_with_level(false, None, LevelFilter::Error);
+        assert_eq!(logger.max_level(), LevelFilter::Debug);
+    }
+
+    #[test]
+    fn test_build_info() {
+        let (logger, captured) = Logger::new()
+            .with_level(LevelFilter::Error)
+            .without_timestamps()
+            .with_build_info("1.4.2 (2026-10-16)")
+            .add_writer_capture();
+        logger.log(&Record::builder().level(Level::Error).args(format_args!("failure")).build());
+
+        assert_eq!(captured.lines(), vec!["-[][] -> {INFO} Build: 1.4.2 (2026-10-16)", "-[][] -> {ERROR} failure"]);
+    }
+
+    #[test]
+    fn test_shutdown_summary() {
+        let (logger, captured) = Logger::new()
+            .with_level(LevelFilter::Info)
+            .without_timestamps()
+            .with_shutdown_summary(1)
+            .add_writer_capture();
+        for _ in 0..2 {
+            logger.log(&Record::builder().level(Level::Warn).args(format_args!("retrying")).build());
+        }
+        logger.log(&Record::builder().level(Level::Debug).args(format_args!("hidden")).build());
+        // Formatted, so not listed in the top messages.
+        for attempt in 0..3 {
+            logger.log(&Record::builder().level(Level::Info).args(format_args!("attempt {attempt}")).build());
+        }
+        logger.flush();
+        logger.flush();
+
+        assert_eq!(captured.lines()[5..], 
+            ["-[][] -> {INFO} Summary: 0 ERROR, 2 WARN, 3 INFO, 0 DEBUG, 0 TRACE, 123 bytes. Top messages: 2x \"retrying\""]);
+    }
+
+    #[test]
+    fn test_writer_init_error() {
+        let path = temp_log_path("init-error");
+        std::fs::write(&path, "not a directory").unwrap();
+        let unreachable = path.join("app.log");
+
+        // The directory can't be created over the file.
+        let error = Logger::new().try_add_writer_file(unreachable.clone(), false, None).err().unwrap();
+        assert!(matches!(&error, Error::Io { path: dir, .. } if *dir == path), "{error}");
+        assert!(error.to_string().starts_with(&format!("IO error on {}: ", path.display())), "{error}");
+        assert!(Logger::new().try_add_writer_stdout(false, None).is_ok());
+
+        // Deferred to init(), which doesn't install the logger.
+        let logger = Logger::new()
+            .add_writer_file(unreachable.clone(), false, None)
+            .add_writer_stdout(false, None);
+        assert_eq!(logger.writers.len(), 1);
+        match logger.init() {
+            Err(Error::Io { path: dir, .. }) => assert_eq!(dir, path),
+            other => panic!("unexpected init result {other:?}"),
+        }
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_nonblocking_writers() {
+        let (logger, captured) = Logger::new()
+            .without_timestamps()
+            .with_nonblocking_writers()
+            .add_writer_capture();
+        let record = |message| logger.log(&Record::builder().level(Level::Info).args(format_args!("{message}")).build());
+
+        // Held by a flush of another thread.
+        let flushing = logger.writers[0].read().unwrap();
+        record("dropped");
+        drop(flushing);
+        record("written");
+
+        assert_eq!(captured.lines(), vec!["-[][] -> {INFO} written"]);
+        assert_eq!(logger.dropped_records(), 1);
+        assert_eq!(logger.flush_with_stats().dropped, 1);
+    }
+
+    #[test]
+    fn test_add_writer() {
+        let path = temp_log_path("add-writer");
+        let builder = WriterBuilder::new()
+            .on_file(path.clone())
+            .with_separate_thread()
+            .with_buffer_capacity(BufferCapacity::Records(2))
+            .with_level(LevelFilter::Info);
+        assert_eq!(builder.capacity(), BufferCapacity::Records(2));
+        assert!(builder.is_separate_thread());
+        assert_eq!(builder.target(), &BuilderTarget::File(path.clone()));
+        assert!(!WriterBuilder::new().is_separate_thread());
+        assert_eq!(WriterBuilder::new().on_null().target(), &BuilderTarget::Null);
+
+        let logger = Logger::new()
+            .with_level(LevelFilter::Warn)
+            .without_timestamps()
+            .add_writer(builder)
+            .add_writer(WriterBuilder::new().on_null());
+        assert_eq!(logger.writers.len(), 2);
+        assert!(logger.writers[0].read().unwrap().is_separate_thread());
+
+        for level in Level::iter() {
+            logger.log(&Record::builder().level(level).args(format_args!("{level}")).build());
+        }
+        logger.flush();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "-[][] -> {ERROR} ERROR\n-[][] -> {WARN} WARN\n-[][] -> {INFO} INFO\n");
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_log_within_pipeline() {
+        let (logger, captured) = Logger::new().without_timestamps().add_writer_capture();
+
+        let pipeline = PipelineGuard::enter();
+        logger.log(&Record::builder().level(Level::Info).args(format_args!("logged by a writer")).build());
+        drop(pipeline);
+        logger.log(&Record::builder().level(Level::Info).args(format_args!("logged")).build());
+
+        assert_eq!(captured.lines(), vec!["-[][] -> {INFO} logged"]);
+        assert_eq!(logger.dropped_records(), 1);
+    }
+
+    #[test]
+    fn test_writer_sink() {
+        #[derive(Clone, Default)]
+        struct SharedSink {
+            bytes: Arc<Mutex<Vec<u8>>>,
+            flushes: Arc<Mutex<usize>>,
+        }
+
+        impl Write for SharedSink {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.bytes.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                *self.flushes.lock().unwrap() += 1;
+                Ok(())
+            }
+        }
+
+        let sink = SharedSink::default();
+        let logger = Logger::new()
+            .without_timestamps()
+            .add_writer_sink(sink.clone(), WriterOptions { threaded: true, buffer: BufferCapacity::Bytes(4096), ..WriterOptions::default() });
+        for message in ["first", "second"] {
+            logger.log(&Record::builder().level(Level::Info).args(format_args!("{message}")).build());
+        }
+        assert!(sink.bytes.lock().unwrap().is_empty());
+        logger.flush();
+
+        assert_eq!(String::from_utf8(sink.bytes.lock().unwrap().clone()).unwrap(), "-[][] -> {INFO} first\n-[][] -> {INFO} second\n");
+        assert!(*sink.flushes.lock().unwrap() > 0);
+    }
+
+    #[test]
+    fn test_non_blocking_writer() {
+        // A pipe whose reader is paused: writing blocks until the gate is released.
+        #[derive(Clone, Default)]
+        struct BlockedPipe {
+            gate: Arc<Mutex<()>>,
+            lines: Arc<Mutex<usize>>,
+        }
+
+        impl Write for BlockedPipe {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                let _gate = self.gate.lock().unwrap();
+                *self.lines.lock().unwrap() += buf.iter().filter(|byte| **byte == b'\n').count();
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let pipe = BlockedPipe::default();
+        let gate = pipe.gate.lock().unwrap();
+        let logger = Logger::new()
+            .without_timestamps()
+            .add_writer_sink(pipe.clone(), WriterOptions { queue_capacity: Some(4), buffer: BufferCapacity::Bytes(0), ..WriterOptions::non_blocking() });
+
+        let started = Instant::now();
+        for index in 0..100 {
+            logger.log(&Record::builder().level(Level::Info).args(format_args!("record {index}")).build());
+        }
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert!(logger.dropped_records() > 0);
+
+        drop(gate);
+        logger.flush();
+        assert_eq!(*pipe.lines.lock().unwrap() as u64 + logger.dropped_records(), 100);
+    }
+
+    #[test]
+    fn test_split_by_level() {
+        let dir = temp_log_path("split-by-level");
+        let logger = Logger::new()
+            .with_level(LevelFilter::Info)
+            .without_timestamps()
+            .split_by_level(dir.clone(), &[(Level::Error, "errors.log"), (Level::Warn, "warnings.log")], true, None);
+        assert_eq!(logger.writers.len(), 2);
+
+        for level in Level::iter() {
+            logger.log(&Record::builder().level(level).args(format_args!("{level}")).build());
+        }
+        logger.flush();
+
+        assert_eq!(std::fs::read_to_string(dir.join("errors.log")).unwrap(), "-[][] -> {ERROR} ERROR\n");
+        assert_eq!(std::fs::read_to_string(dir.join("warnings.log")).unwrap(), "-[][] -> {ERROR} ERROR\n-[][] -> {WARN} WARN\n");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_add_writer_open_file() {
+        let path = temp_log_path("open-file");
+        let file = std::fs::OpenOptions::new().create(true).append(true).open(&path).unwrap();
+        let logger = Logger::new()
+            .without_timestamps()
+            .with_csv()
+            .add_writer_open_file(file, true, None);
+        logger.log(&Record::builder().level(Level::Info).args(format_args!("written on the open file")).build());
+        logger.flush();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), format!("{CSV_HEADER}\n,INFO,,,written on the open file\n"));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_writer_level_above_logger() {
+        let path = temp_log_path("above-logger");
+        let (logger, captured) = Logger::new()
+            .with_level(LevelFilter::Warn)
+            .without_timestamps()
+            .add_writer_file_with_level(path.clone(), false, None, LevelFilter::Debug)
+            .add_writer_capture();
+
+        for level in Level::iter() {
+            logger.log(&Record::builder().level(level).args(format_args!("{level}")).build());
+        }
+        logger.flush();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "-[][] -> {ERROR} ERROR\n-[][] -> {WARN} WARN\n-[][] -> {INFO} INFO\n-[][] -> {DEBUG} DEBUG\n");
+        assert_eq!(captured.lines(), vec!["-[][] -> {ERROR} ERROR", "-[][] -> {WARN} WARN"]);
+    }
+
+    #[test]
+    fn test_writer_for_levels() {
+        let path = temp_log_path("for-levels");
+        let logger = Logger::new()
+            .with_level(LevelFilter::Debug)
+            .without_timestamps()
+            .add_writer_file_for_levels(path.clone(), false, None, &[Level::Warn, Level::Trace, Level::Debug]);
+        assert_eq!(logger.max_level(), LevelFilter::Debug);
+
+        for level in Level::iter() {
+            logger.log(&Record::builder().level(level).args(format_args!("{level}")).build());
+        }
+        logger.flush();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "-[][] -> {WARN} WARN\n-[][] -> {DEBUG} DEBUG\n");
+    }
+
+    #[test]
+    fn test_timestamp_default() {
+        let builder = Logger::new();
+        assert!(builder.timestamps == Timestamps::Local);
+    }
+
+    #[test]
+    fn test_utc_timestamp() {
+        let builder = Logger::new().with_utc_timestamps();
+        assert!(builder.timestamps == Timestamps::Utc);
+    }
+
+    #[test]
+    fn test_offset_timestamp() {
+        let offset = UtcOffset::from_hms(2, 0, 0).unwrap();
+        let builder = Logger::new().with_offset(offset);
+        assert!(builder.timestamps == Timestamps::Offset(offset));
+    }
+
+    #[test]
+    fn test_dual_timestamp() {
+        let now = time::macros::datetime!(2024-06-01 10:00:00.25 UTC).to_utc();
+        let offset = UtcOffset::from_hms(2, 0, 0).unwrap();
+        assert_eq!(dual_timestamp(now, offset), "10:00:00:250000Z (12:00 local)");
+    }
+
+    #[test]
+    fn test_uptime_timestamp() {
+        assert_eq!(uptime_timestamp(Duration::from_nanos(1_500)), "0+00:00:00.000001500");
+        assert_eq!(uptime_timestamp(Duration::new(2 * 86_400 + 3 * 3600 + 4 * 60 + 5, 6)), "2+03:04:05.000000006");
     }
 
-    fn log(&self, record: &log::Record) {
-        if !self.enabled(record.metadata()) {
-            return;
-        }
+    #[test]
+    fn test_written_bytes_snapshot() {
+        let path = temp_log_path("snapshot");
+        let logger = Logger::new()
+            .without_timestamps()
+            .with_thread()
+            .with_target()
+            .with_record_framing(b"<<".to_vec(), b">>\r\n".to_vec())
+            .add_writer_file(path.clone(), false, None)
+            .add_writer_file(path.clone(), true, None);
 
-        let mut target = "";
+        std::thread::Builder::new().name("worker".to_string()).spawn(move || {
+            logger.log(&Record::builder().level(Level::Warn).target("app::db").args(format_args!("pool {} exhausted", 3)).build());
+            logger.flush();
+        }).unwrap().join().unwrap();
 
-        if self.target {
-            target = if !record.target().is_empty() {
-                    record.target()
-                } else {
-                    record.module_path().unwrap_or_default()
-                };
-        }
+        let expected = "<<-[app::db][worker] -> {WARN} pool 3 exhausted>>\r\n";
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), expected.repeat(2));
+    }
 
+    #[cfg(feature = "async-tokio")]
+    #[test]
+    fn test_async_file_writer() {
+        let path = temp_log_path("async");
+        let runtime = tokio::runtime::Builder::new_multi_thread().worker_threads(1).build().unwrap();
 
-        let thread = if self.thread {
-            if let Some(thread_name) = std::thread::current().name() {
-                format!("{}", thread_name)
-            } else {
-                format!("{:?}", std::thread::current().id())
+        runtime.block_on(async {
+            let logger = Logger::new().without_timestamps().add_writer_file_async(path.clone(), None);
+            logger.log(&Record::builder().level(Level::Info).args(format_args!("from the runtime")).build());
+            logger.flush();
+        });
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "-[][] -> {INFO} from the runtime\n");
+    }
+
+    #[cfg(feature = "async-tokio")]
+    #[test]
+    fn test_async_file_writer_options() {
+        let path = temp_log_path("async-options");
+        let runtime = tokio::runtime::Builder::new_multi_thread().worker_threads(1).build().unwrap();
+
+        runtime.block_on(async {
+            let logger = Logger::new()
+                .without_timestamps()
+                .add_writer_file_async_opts(path.clone(), WriterOptions { 
+                    level: Some(LevelFilter::Warn), 
+                    queue_capacity: Some(16),
+                    ..WriterOptions::default() 
+                });
+            for level in [Level::Info, Level::Warn] {
+                logger.log(&Record::builder().level(level).args(format_args!("{level}")).build());
             }
-        } else {
-            "".to_string()
-        };
-        
-        let timestamp = match self.timestamps {
-            Timestamps::None => "".to_string(),
-            Timestamps::Local => format!(
-                "{}",
-                OffsetDateTime::now_local()
-                    .expect(concat!(
-                        "Could not determine the UTC offset on this system. ",
-                        "Consider displaying UTC time instead. ",
-                        "Possible causes are that the time crate does not implement \"local_offset_at\" ",
-                        "on your system, or that you are running in a multi-threaded environment and ",
-                        "the time crate is returning \"None\" from \"local_offset_at\" to avoid unsafe ",
-                        "behaviour. See the time crate's documentation for more information. ",
-                        "(https://time-rs.github.io/internal-api/time/index.html#feature-flags)"
-                    ))
-                    .format(TIMESTMAMP_FORMAT)
-                    .unwrap()
-            ),
-            Timestamps::Utc => format!( "{}", UtcDateTime::now().format(TIMESTMAMP_FORMAT).unwrap()),
+            logger.flush();
+        });
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "-[][] -> {WARN} WARN\n");
+    }
+
+    #[cfg(feature = "async-tokio")]
+    #[test]
+    fn test_async_file_writer_from_task() {
+        let path = temp_log_path("async-task");
+        let runtime = tokio::runtime::Builder::new_multi_thread().worker_threads(1).build().unwrap();
+
+        runtime.block_on(async {
+            let logger = Logger::new()
+                .without_timestamps()
+                .add_writer_file_async_opts(path.clone(), WriterOptions { queue_capacity: Some(4), ..WriterOptions::default() });
+            // On the only worker: waiting for room and flushing must let the writer task run.
+            tokio::spawn(async move {
+                for index in 0..100 {
+                    logger.log(&Record::builder().level(Level::Info).args(format_args!("{index}")).build());
+                }
+                logger.flush();
+            }).await.unwrap();
+        });
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 100);
+    }
+
+    #[cfg(feature = "async-tokio")]
+    #[test]
+    fn test_async_file_writer_runtime_shutdown() {
+        let path = temp_log_path("async-shutdown");
+        let runtime = tokio::runtime::Builder::new_multi_thread().worker_threads(1).build().unwrap();
+        let logger = runtime.block_on(async { Logger::new().add_writer_file_async_with_level(path.clone(), None, LevelFilter::Debug) });
+        assert_eq!(logger.writers.len(), 1);
+
+        // The writer task doesn't hold the runtime, which cancels it.
+        let start = Instant::now();
+        drop(runtime);
+        logger.flush();
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let logger = runtime.block_on(async { Logger::new().add_writer_file_async(path.clone(), None) });
+        assert!(logger.writers.is_empty());
+    }
+
+    #[test]
+    fn test_deferred_formatting() {
+        let path = temp_log_path("deferred");
+        let logger = Logger::new()
+            .with_utc_timestamps()
+            .with_target()
+            .with_deferred_formatting()
+            .add_writer_file(path.clone(), true, None)
+            .add_writer_file(path.clone(), false, None);
+
+        logger.log(&Record::builder().level(Level::Info).target("app").args(format_args!("ready in {}ms", 12)).build());
+        logger.flush();
+
+        // Both writers render the same pieces, including the time read once.
+        let content = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], lines[1]);
+        assert!(lines[0].ends_with("-[app][] -> {INFO} ready in 12ms"));
+    }
+
+    #[test]
+    fn test_locations() {
+        let (logger, captured) = Logger::new().without_timestamps().with_clickable_locations().add_writer_capture();
+
+        logger.log(&Record::builder().level(Level::Info).file(Some("src/main.rs")).line(Some(12)).args(format_args!("started")).build());
+        assert_eq!(captured.lines(), vec!["-[][] -> {INFO} src/main.rs:12 started"]);
+
+        let linked = format_location("src/main.rs", 12, true);
+        assert!(linked.starts_with("\x1b]8;;file:///"));
+        assert!(linked.ends_with("src/main.rs\x1b\\src/main.rs:12\x1b]8;;\x1b\\ "));
+    }
+
+    #[test]
+    fn test_idle_flush() {
+        let path = temp_log_path("idle-flush");
+        let logger = Logger::new()
+            .without_timestamps()
+            .with_idle_flush(Duration::from_millis(20))
+            .add_writer_file(path.clone(), true, Some(4096));
+
+        logger.log(&Record::builder().level(Level::Info).args(format_args!("quiet")).build());
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while std::fs::read_to_string(&path).unwrap_or_default().is_empty() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "-[][] -> {INFO} quiet\n");
+    }
+
+    #[test]
+    fn test_deferred_time_is_event_time() {
+        let logger = Logger::new().with_uptime_timestamps().with_deferred_formatting();
+
+        // The record is rendered later, with the time read when it was logged.
+        let time = logger.timestamps.now();
+        std::thread::sleep(Duration::from_millis(30));
+        let record = DeferredRecord {
+            level: Level::Info,
+            target: String::new(),
+            thread: String::new(),
+            time,
+            args: "event".to_string(),
+            location: None,
+            timestamps: logger.timestamps,
+            layout: LineLayout::default(),
         };
+        let RecordTime::Elapsed(elapsed) = time else { panic!("Uptime timestamps read the elapsed time") };
+        assert!(elapsed < Duration::from_millis(30));
+        assert_eq!(record.line(false), format!("{}-[][] -> {{INFO}} event", uptime_timestamp(elapsed)));
+    }
 
-        let message = format!("{timestamp}-[{target}][{thread}] -> {{{}}} {}", record.level().to_string(), record.args());
+    #[test]
+    fn test_strict_ordering() {
+        let this_thread_path = temp_log_path("strict-this-thread");
+        let separate_path = temp_log_path("strict-separate");
+        let logger: &'static Logger = Box::leak(Box::new(Logger::new()
+            .without_timestamps()
+            .with_strict_ordering()
+            .add_writer_file(this_thread_path.clone(), false, None)
+            .add_writer_file(separate_path.clone(), true, None)));
 
-        for (index, writer) in self.writers.iter().enumerate() {
-            if index >= self.writer_levels.len() {
-                panic!("Level index out of range!");
+        let threads: Vec<_> = (0..4).map(|thread| std::thread::spawn(move || {
+            for i in 0..200 {
+                logger.log(&Record::builder().level(Level::Info).args(format_args!("{thread}-{i}")).build());
             }
+        })).collect();
+        threads.into_iter().for_each(|thread| thread.join().unwrap());
+        logger.flush();
 
-            // Skip as this trace should not be traced on this writer!
-            if record.metadata().level().to_level_filter() > self.writer_levels[index] {
-                continue;
-            }
+        let this_thread = std::fs::read_to_string(&this_thread_path).unwrap();
+        assert_eq!(this_thread.lines().count(), 800);
+        assert_eq!(this_thread, std::fs::read_to_string(&separate_path).unwrap());
+    }
 
-            if let Ok(writer_mut) = writer.write() {
-                writer_mut.write(message.as_str());
-            } else {
-                panic!("Cannot get writer as mutable. RWLock is poisoned!");
+    #[test]
+    fn test_shared_writer_thread() {
+        let paths = ["own-threads", "own-threads-errors", "shared-thread", "shared-thread-errors"].map(temp_log_path);
+        let own_threads = Logger::new()
+            .without_timestamps()
+            .add_writer_file(paths[0].clone(), true, None)
+            .add_writer_file_with_level(paths[1].clone(), true, None, LevelFilter::Error);
+        let shared_thread = Logger::new()
+            .without_timestamps()
+            .with_shared_writer_thread()
+            .add_writer_file(paths[2].clone(), true, None)
+            .add_writer_file_with_level(paths[3].clone(), true, None, LevelFilter::Error);
+
+        for logger in [&own_threads, &shared_thread] {
+            for i in 0..100 {
+                let level = if i % 10 == 0 { Level::Error } else { Level::Info };
+                logger.log(&Record::builder().level(level).args(format_args!("record {i}")).build());
             }
         }
+        let stats = shared_thread.flush_with_stats();
+        own_threads.flush();
+
+        assert_eq!((stats.written, stats.joined), (110, true));
+        let [own, own_errors, shared, shared_errors] = paths.each_ref().map(|path| std::fs::read_to_string(path).unwrap());
+        assert_eq!(own.lines().count(), 100);
+        assert_eq!(own_errors.lines().count(), 10);
+        assert_eq!(own, shared);
+        assert_eq!(own_errors, shared_errors);
     }
 
-    ///
-    /// Flushes to ensure that all possible buffered data are logged. 
-    /// This method should be called right before closing the program or when you don't need to 
-    /// log anything else. 
-    /// This is because in case of separate thread, the logger thread will be stopped.
-    /// # Example
-    /// ```
-    /// use rslogger::Logger;
-    /// use log::{info, warn, error};
-    /// Logger::new()
-    ///     .with_level(log::LevelFilter::Trace)
-    ///     .with_local_timestamps()
-    ///     .add_writer_stdout(true, Some(1000))
-    ///     .init().unwrap();
-    /// info!("This is a info test");
-    /// warn!("This is a warn test");
-    /// error!("This is an error test");
-    /// log::logger().flush();
-    /// let result = std::panic::catch_unwind(|| info!("Test"));
-    /// assert!(result.is_err())
-    /// ```
-    /// 
-    fn flush(&self) {
-        for writer in &self.writers {
-            if let Ok(mut writer_mut) = writer.write() {
-                writer_mut.flush_and_cleanup();
-            } else {
-                panic!("Cannot get writer as mutable. RWLock is poisoned!");
-            }
+    #[test]
+    fn test_writer_targets() {
+        let path = temp_log_path("targets");
+        let logger = Logger::new()
+            .without_timestamps()
+            .with_target()
+            .add_writer_file_with_targets(path.clone(), vec!["app".to_string()], vec!["app::noisy".to_string()], false, None);
+
+        for target in ["app", "app::db", "app::noisy", "app::noisy::inner", "application", "hyper"] {
+            logger.log(&Record::builder().level(Level::Info).target(target).args(format_args!("from {target}")).build());
         }
+        logger.flush();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "-[app][] -> {INFO} from app\n-[app::db][] -> {INFO} from app::db\n");
     }
 
-}
+    #[test]
+    fn test_level_flush_immediate() {
+        let path = temp_log_path("level-flush");
+        let logger = Logger::new()
+            .without_timestamps()
+            .with_level_flush(Level::Error, LevelFlush::Immediate)
+            .add_writer_file(path.clone(), false, Some(10000));
 
-#[cfg(test)]
-mod tests {
-    use log::{Metadata, Level};
+        logger.log(&Record::builder().level(Level::Trace).args(format_args!("buffered")).build());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+
+        logger.log(&Record::builder().level(Level::Error).args(format_args!("flushed")).build());
+        let content = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert!(content.contains("buffered"));
+        assert!(content.ends_with("flushed\n"));
+    }
 
-    use super::*;
 
     #[test]
-    fn test_default_level() {
-        let builder = Logger::new();
-        assert_eq!(builder.log_level(), LevelFilter::Trace);
+    fn test_colored_line_ends_with_reset() {
+        let captured = CaptureHandle::default();
+        let writer = BufferedWriter::new().on_capture(captured.clone()).with_colors();
+        let logger = Logger::new().without_timestamps().add_configured_writer(writer, false, None, WriterLevel::Logger);
+
+        logger.log(&Record::builder().level(Level::Error).args(format_args!("boom")).build());
+        assert_eq!(captured.lines(), vec!["\x1b[31m-[][] -> {ERROR} boom\x1b[0m"]);
     }
 
+    #[cfg(feature = "test-util")]
     #[test]
-    fn test_creation_level() {
-        let builder = Logger::new().with_level(LevelFilter::Debug);
-        assert_eq!(builder.log_level(), LevelFilter::Debug);
+    fn test_assert_logged_whatever_the_layout() {
+        let (logger, captured) = Logger::new()
+            .with_short_levels()
+            .with_csv()
+            .with_target()
+            .add_writer_capture();
+
+        logger.log(&Record::builder().level(Level::Warn).target("app::db").args(format_args!("disk at {}%", 95)).build());
+        assert!(captured.lines()[0].contains(",W,"));
+        assert_logged!(captured, Level::Warn, target: "app::db", matches: r"disk at \d+%");
+        assert_not_logged!(captured, Level::Error, contains: "disk");
     }
 
     #[test]
-    fn test_logger_enabled() {
-        let logger = Logger::new().with_level(LevelFilter::Debug);
-        assert_eq!(logger.log_level(), LevelFilter::Debug);
-        assert!(logger.enabled(&create_log("test_enabled", Level::Debug)));
+    fn test_colored_line_reset_at_terminal_width() {
+        assert_eq!(color_line(Level::Warn, "abcdefg", Some(3)), "\x1b[33mabc\x1b[0m\x1b[33mdef\x1b[0m\x1b[33mg\x1b[0m");
+        assert_eq!(color_line(Level::Warn, "abc", Some(3)), "\x1b[33mabc\x1b[0m");
+        assert_eq!(color_line(Level::Warn, "abcd", None), "\x1b[33mabcd\x1b[0m");
+        // Escape sequences take no column, a line terminator starts a new row.
+        assert_eq!(
+            color_line(Level::Info, "\x1b]8;;file:///a.rs\x1b\\ab\x1b]8;;\x1b\\c\nde", Some(2)),
+            "\x1b[32m\x1b]8;;file:///a.rs\x1b\\ab\x1b]8;;\x1b\\\x1b[0m\x1b[32mc\x1b[0m\n\x1b[32mde\x1b[0m"
+        );
     }
 
     #[test]
-    fn test_timestamp_default() {
-        let builder = Logger::new();
-        assert!(builder.timestamps == Timestamps::Local);
+    fn test_without_create_dirs() {
+        let dir = std::env::temp_dir().join(format!("rslogger-no-dirs-{}", std::process::id()));
+        let logger = Logger::new()
+            .with_create_dirs(false)
+            .add_writer_file(dir.join("app.log"), false, None);
+
+        assert!(logger.writers.is_empty());
+        assert!(!dir.exists());
     }
 
     #[test]
-    fn test_utc_timestamp() {
-        let builder = Logger::new().with_utc_timestamps();
-        assert!(builder.timestamps == Timestamps::Utc);
+    fn test_null_writer() {
+        let logger = Logger::new()
+            .add_writer_null(false, None)
+            .add_writer_null(true, Some(0));
+        assert_eq!(logger.writers.len(), 2);
+
+        logger.log(&Record::builder().level(Level::Info).args(format_args!("discarded")).build());
+        logger.flush();
+    }
+
+    #[test]
+    fn test_fsync() {
+        let path = temp_log_path("fsync");
+        let (logger, captured) = Logger::new().without_timestamps().with_fsync().add_writer_capture();
+        let logger = logger.add_writer_file(path.clone(), false, None).add_writer_stdout_with_level(false, None, LevelFilter::Off);
+        assert!(logger.init_error.is_none());
+
+        // Only the stdout writer ignores the policy, reported on the writers added before it.
+        assert_eq!(captured.lines(), vec![
+            "-[][] -> {WARN} Writer 2: the sync policy FullOnFlush only applies to the file writers, it's ignored",
+        ]);
+
+        logger.log(&Record::builder().level(Level::Info).args(format_args!("audited")).build());
+        logger.flush();
+        let content = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(content, "-[][] -> {WARN} Writer 2: the sync policy FullOnFlush only applies to the file writers, it's ignored\n\
+            -[][] -> {INFO} audited\n");
+    }
+
+    #[test]
+    fn test_writer_options() {
+        let path = temp_log_path("options");
+        let logger = Logger::new()
+            .with_level(LevelFilter::Info)
+            .without_timestamps()
+            .with_queue_capacity(1000)
+            .add_writer_file_opts(path.clone(), WriterOptions {
+                threaded: true,
+                buffer: BufferCapacity::Bytes(0),
+                queue_capacity: Some(1),
+                level: Some(LevelFilter::Warn),
+                exclude: vec!["noisy".to_string()],
+                ..WriterOptions::default()
+            });
+
+        for level in [Level::Info, Level::Warn, Level::Error] {
+            logger.log(&Record::builder().level(level).args(format_args!("{level}")).build());
+        }
+        logger.log(&Record::builder().level(Level::Error).target("noisy::db").args(format_args!("excluded")).build());
+        logger.flush();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(content, "-[][] -> {WARN} WARN\n-[][] -> {ERROR} ERROR\n");
+    }
+
+    #[test]
+    fn test_dedup_destinations() {
+        let path = temp_log_path("dedup");
+        let relative = path.parent().unwrap().join(".").join(path.file_name().unwrap());
+        let templated = path.parent().unwrap().join("rslogger-dedup-{pid}.log");
+        let logger = Logger::new()
+            .without_timestamps()
+            .dedup_destinations()
+            .add_writer_file(path.clone(), false, Some(0))
+            .add_writer_file(relative, false, Some(0))
+            .add_writer_file(templated, false, Some(0))
+            .add_writer_stdout(false, None)
+            .add_writer_stdout(true, None);
+        assert_eq!(logger.writers.len(), 2);
+
+        logger.log(&Record::builder().level(Level::Info).args(format_args!("once")).build());
+        let content = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(content, "-[][] -> {WARN} Writer 1: dropped, same destination as writer 0\n".repeat(2) 
+            + "-[][] -> {WARN} Writer 2: dropped, same destination as writer 1\n-[][] -> {INFO} once\n");
+    }
+
+    #[test]
+    fn test_flush_policy_every_record() {
+        let path = temp_log_path("every-record");
+        let logger = Logger::new()
+            .without_timestamps()
+            .with_flush_policy(FlushPolicy::EveryRecord)
+            .add_writer_file(path.clone(), false, Some(10000));
+
+        logger.log(&Record::builder().level(Level::Info).args(format_args!("visible")).build());
+        let content = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(content, "-[][] -> {INFO} visible\n");
+    }
+
+    #[test]
+    fn test_unbuffered_file() {
+        let path = temp_log_path("unbuffered");
+        let logger = Logger::new().without_timestamps().add_writer_file(path.clone(), false, Some(0));
+
+        let mut expected = String::new();
+        for message in ["first", "second", "third"] {
+            logger.log(&Record::builder().level(Level::Info).args(format_args!("{message}")).build());
+            expected.push_str(&format!("-[][] -> {{INFO}} {message}\n"));
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), expected);
+        }
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_record_framing() {
+        let path = temp_log_path("framing");
+        let logger = Logger::new()
+            .without_timestamps()
+            .with_record_framing(b"\x1e".to_vec(), b"\n".to_vec())
+            .add_writer_file(path.clone(), false, Some(0));
+
+        logger.log(&Record::builder().level(Level::Info).args(format_args!("framed")).build());
+        let content = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(content, "\x1e-[][] -> {INFO} framed\n");
+    }
+
+    #[test]
+    fn test_without_final_suffix() {
+        let path = temp_log_path("final-suffix");
+        for message in ["first", "second"] {
+            let logger = Logger::new()
+                .without_timestamps()
+                .without_final_suffix()
+                .add_writer_file(path.clone(), true, None);
+            logger.log(&Record::builder().level(Level::Info).args(format_args!("{message}")).build());
+            logger.log(&Record::builder().level(Level::Info).args(format_args!("{message} again")).build());
+            logger.flush();
+        }
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(content, "-[][] -> {INFO} first\n-[][] -> {INFO} first again\n-[][] -> {INFO} second\n-[][] -> {INFO} second again");
+    }
+
+    #[test]
+    fn test_boxed_errors() {
+        assert_eq!(visible_width("\x1b[31mred\x1b[0m \x1b]8;;file:///a.rs\x1b\\a.rs:1\x1b]8;;\x1b\\"), 10);
+        assert_eq!(draw_box("ab\nc"), "┌───\n│ ab\n│ c\n└───");
+
+        let boxed = CaptureHandle::default();
+        let logger = Logger::new()
+            .without_timestamps()
+            .add_configured_writer(BufferedWriter::new().on_capture(boxed.clone()).with_boxed_errors(), false, None, WriterLevel::Logger);
+        logger.log(&Record::builder().level(Level::Warn).args(format_args!("warning")).build());
+        logger.log(&Record::builder().level(Level::Error).args(format_args!("failure")).build());
+
+        assert_eq!(boxed.lines(), vec![
+            "-[][] -> {WARN} warning",
+            "┌─────────────────────────",
+            "│ -[][] -> {ERROR} failure",
+            "└─────────────────────────",
+        ]);
+    }
+
+    #[test]
+    fn test_secondary_logger() {
+        let (secondary, secondary_captured) = Logger::new().with_level(LevelFilter::Trace).add_writer_capture();
+
+        let (logger, captured) = Logger::new()
+            .with_level(LevelFilter::Info)
+            .without_timestamps()
+            .with_secondary_logger(Box::new(secondary))
+            .add_writer_capture();
+
+        logger.log(&Record::builder().level(Level::Info).args(format_args!("both")).build());
+        logger.log(&Record::builder().level(Level::Trace).args(format_args!("secondary only")).build());
+
+        assert_eq!(captured.lines(), vec!["-[][] -> {INFO} both"]);
+        assert_eq!(secondary_captured.lines().len(), 2);
+        assert!(secondary_captured.contains("{TRACE} secondary only"));
+        assert!(logger.enabled(&create_log("test_secondary", Level::Trace)));
+    }
+
+    #[test]
+    fn test_level_override() {
+        let capped = CaptureHandle::default();
+        let (logger, captured) = Logger::new()
+            .with_level(LevelFilter::Info)
+            .without_timestamps()
+            .add_configured_writer(BufferedWriter::new().on_capture(capped.clone()), false, None, WriterLevel::Max(LevelFilter::Info))
+            .add_writer_capture();
+        let debug_record = Record::builder().level(Level::Debug).args(format_args!("overridden")).build();
+
+        logger.log(&debug_record);
+        assert!(captured.lines().is_empty());
+
+        let (other, other_captured) = Logger::new().with_level(LevelFilter::Info).add_writer_capture();
+        logger.with_level_override(LevelFilter::Debug, || {
+            assert!(logger.enabled(&create_log("test_override", Level::Debug)));
+            logger.log(&debug_record);
+            // The override is scoped to its logger.
+            assert!(!other.enabled(&create_log("test_override", Level::Debug)));
+            other.log(&debug_record);
+        });
+        assert!(other_captured.lines().is_empty());
+        assert_eq!(captured.lines(), vec!["-[][] -> {DEBUG} overridden"]);
+        assert!(capped.lines().is_empty());
+        assert!(!logger.enabled(&create_log("test_override", Level::Debug)));
+    }
+
+    #[test]
+    fn test_suspended() {
+        let (logger, captured) = Logger::new().without_timestamps().add_writer_capture();
+        let metadata = create_log("test_suspended", Level::Info);
+
+        logger.suspended.store(true, Ordering::Relaxed);
+        assert!(!logger.enabled(&metadata));
+        logger.log(&Record::builder().level(Level::Info).args(format_args!("dropped")).build());
+        assert!(captured.lines().is_empty());
+
+        logger.suspended.store(false, Ordering::Relaxed);
+        assert!(logger.enabled(&metadata));
+        logger.log(&Record::builder().level(Level::Info).args(format_args!("logged")).build());
+        assert_eq!(captured.lines(), vec!["-[][] -> {INFO} logged"]);
+    }
+
+    #[test]
+    fn test_target_levels() {
+        let logger = Logger::new()
+            .with_level(LevelFilter::Info)
+            .with_target_level("app::db::pool", LevelFilter::Trace)
+            .with_target_level_to_depth("app", LevelFilter::Debug, 1)
+            .with_target_level("app", LevelFilter::Warn)
+            .add_writer_null(false, None);
+
+        for (target, level) in [
+            ("app", LevelFilter::Debug),
+            ("app::db", LevelFilter::Debug),
+            ("app::db::pool", LevelFilter::Trace),
+            ("app::db::cache", LevelFilter::Warn),
+            ("app::db::cache::lru", LevelFilter::Warn),
+            ("application", LevelFilter::Info),
+            ("other::app", LevelFilter::Info),
+        ] {
+            assert_eq!(logger.target_level(target), level, "{target}");
+        }
+        assert_eq!(logger.max_level(), LevelFilter::Trace);
+
+        let top_only = Logger::new().with_level(LevelFilter::Info).with_target_level_to_depth("app", LevelFilter::Off, 0);
+        assert_eq!(top_only.target_level("app"), LevelFilter::Off);
+        assert_eq!(top_only.target_level("app::db"), LevelFilter::Info);
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_target_regex() {
+        let (logger, captured) = Logger::new()
+            .with_level(LevelFilter::Info)
+            .without_timestamps()
+            .with_target()
+            .with_target_regex("::internal$", LevelFilter::Trace)
+            .with_target_regex("^noisy", LevelFilter::Off)
+            .add_writer_capture();
+
+        for target in ["app::internal", "app::db", "noisy::db"] {
+            logger.log(&Record::builder().level(Level::Debug).target(target).args(format_args!("debug")).build());
+            logger.log(&Record::builder().level(Level::Info).target(target).args(format_args!("info")).build());
+        }
+
+        assert_eq!(captured.lines(), vec![
+            "-[app::internal][] -> {DEBUG} debug",
+            "-[app::internal][] -> {INFO} info",
+            "-[app::db][] -> {INFO} info",
+        ]);
+        assert_eq!(logger.max_level(), LevelFilter::Trace);
+        assert!(Logger::new().try_with_target_regex("(", LevelFilter::Trace).is_err());
+    }
+
+    #[test]
+    fn test_sanitize_output() {
+        let (logger, captured) = Logger::new().without_timestamps().with_sanitize_output().add_writer_capture();
+        logger.log(&Record::builder().level(Level::Warn).args(format_args!("bad request \"GET /\x1b[2J\"\nforged line")).build());
+        logger.log(&Record::builder().level(Level::Info).args(format_args!("payload {}", escape_bytes(b"\xff\xfe ok"))).build());
+
+        assert_eq!(captured.lines(), vec![
+            r#"-[][] -> {WARN} bad request "GET /\u{1b}[2J"\nforged line"#, r"-[][] -> {INFO} payload \xff\xfe ok",
+        ]);
+    }
+
+    #[test]
+    fn test_queue_watermark_notice() {
+        let (logger, first) = Logger::new().without_timestamps().with_target().add_writer_capture();
+        let (logger, second) = logger.add_writer_capture();
+
+        logger.write_notice(0, writer::QueueWatermark::High(5));
+        assert!(first.lines().is_empty());
+        assert_eq!(second.lines(), vec!["-[rslogger][] -> {WARN} Writer 0: queue above the high watermark (5 records queued), the writer can't keep up"]);
+    }
+
+    #[test]
+    fn test_backup_failure_notice() {
+        let dir = std::env::temp_dir().join(format!("rslogger-backup-notice-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        // Named as a rotated file, and can't be deleted as a file.
+        std::fs::create_dir_all(dir.join("app.2000-01-01.log")).unwrap();
+
+        let (logger, captured) = Logger::new().without_timestamps().add_writer_capture();
+        let logger = logger.with_rotation(RotationPolicy::Daily).with_max_backups(0).add_writer_file(dir.join("app.log"), false, None);
+        assert!(logger.init_error.is_none());
+        let lines = captured.lines();
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(lines.len(), 1);
+        let expected = format!("-[][] -> {{WARN}} Writer 1: unable to delete the rotated file {}: ", dir.join("app.2000-01-01.log").display());
+        assert!(lines[0].starts_with(&expected), "{}", lines[0]);
+    }
+
+    #[test]
+    fn test_flush_with_stats() {
+        let logger = Logger::new()
+            .add_writer_null(true, None)
+            .add_writer_null(false, None);
+        for _ in 0..3 {
+            logger.log(&Record::builder().level(Level::Info).args(format_args!("record")).build());
+        }
+
+        let stats = logger.flush_with_stats();
+        assert_eq!((stats.written, stats.dropped, stats.joined), (6, 0, true));
+    }
+
+    #[test]
+    fn test_buffered_len() {
+        let logger = Logger::new()
+            .without_timestamps()
+            .add_writer_null(false, Some(1024))
+            .add_writer_null(false, Some(0));
+        logger.log(&Record::builder().level(Level::Info).args(format_args!("record")).build());
+
+        assert_eq!(logger.buffered_len(), "-[][] -> {INFO} record\n".len());
+        logger.flush();
+        assert_eq!(logger.buffered_len(), 0);
+    }
+
+    #[test]
+    fn test_csv() {
+        let path = temp_log_path("csv");
+        let logger = Logger::new()
+            .without_timestamps()
+            .with_csv()
+            .add_writer_file(path.clone(), false, Some(0));
+
+        logger.log(&Record::builder().level(Level::Warn).args(format_args!("a, \"quoted\"\nvalue")).build());
+        logger.log(&Record::builder().level(Level::Info).args(format_args!("plain")).build());
+        drop(logger);
+
+        let reopened = Logger::new().with_csv().add_writer_file(path.clone(), false, Some(0));
+        drop(reopened);
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(content, "timestamp,level,target,thread,message\n,WARN,,,\"a, \"\"quoted\"\"\nvalue\"\n,INFO,,,plain\n");
+    }
+
+    #[test]
+    fn test_json() {
+        let (logger, captured) = Logger::new().without_timestamps().with_json().add_writer_capture();
+        logger.log(&Record::builder().level(Level::Warn).target("app").args(format_args!("a \"quoted\"\\path\n\u{1b}")).build());
+        let logger = logger.with_target().with_thread();
+        logger.log(&Record::builder().level(Level::Info).target("app").args(format_args!("plain")).build());
+
+        let thread = std::thread::current().name().unwrap_or_default().to_string();
+        assert_eq!(captured.lines(), [
+            r#"{"level":"WARN","message":"a \"quoted\"\\path\n\u001b"}"#.to_string(),
+            format!(r#"{{"level":"INFO","target":"app","thread":"{thread}","message":"plain"}}"#),
+        ]);
+    }
+
+    #[test]
+    fn test_framed_writer() {
+        let path = temp_log_path("framed");
+        let logger = Logger::new().with_target().add_writer_framed(path.clone(), false, Some(0));
+        logger.log(&Record::builder().level(Level::Warn).target("app").args(format_args!("disk almost full")).build());
+        logger.log(&Record::builder().level(Level::Trace).args(format_args!("{}", "x".repeat(200))).build());
+        drop(logger);
+
+        let content = std::fs::read(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        let mut expected = [&[2, 16][..], b"disk almost full", &[5, 0xC8, 0x01]].concat();
+        expected.extend_from_slice("x".repeat(200).as_bytes());
+        assert_eq!(content, expected);
+    }
+
+    #[test]
+    fn test_flush_soft() {
+        let path = temp_log_path("flush-soft");
+        let logger = Logger::new()
+            .without_timestamps()
+            .add_writer_file(path.clone(), true, Some(4096));
+        let content = |expected: &str| {
+            let start = Instant::now();
+            while std::fs::read_to_string(&path).unwrap() != expected && start.elapsed() < Duration::from_secs(5) {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            std::fs::read_to_string(&path).unwrap()
+        };
+
+        logger.log(&Record::builder().level(Level::Info).args(format_args!("first")).build());
+        logger.flush_soft();
+        assert_eq!(content("-[][] -> {INFO} first\n"), "-[][] -> {INFO} first\n");
+
+        // The writer thread is still running.
+        logger.log(&Record::builder().level(Level::Info).args(format_args!("second")).build());
+        let stats = logger.flush_with_stats();
+        assert_eq!((stats.written, stats.joined), (2, true));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_short_levels() {
+        let (logger, captured) = Logger::new().without_timestamps().with_short_levels().add_writer_capture();
+        for level in Level::iter() {
+            logger.log(&Record::builder().level(level).args(format_args!("{level}")).build());
+        }
+        assert_eq!(captured.lines(), vec![
+            "-[][] -> {E} ERROR", "-[][] -> {W} WARN", "-[][] -> {I} INFO", "-[][] -> {D} DEBUG", "-[][] -> {T} TRACE",
+        ]);
+    }
+
+    #[test]
+    fn test_timestamp_position() {
+        let (logger, captured) = Logger::new().with_timestamp_position(TimestampPosition::End).add_writer_capture();
+        logger.log(&Record::builder().level(Level::Info).args(format_args!("started")).build());
+
+        let line = &captured.lines()[0];
+        let (message, timestamp) = line.rsplit_once(' ').unwrap();
+        assert_eq!(message, "[][] -> {INFO} started");
+        assert_eq!(timestamp.len(), "10:00:00:000000".len(), "{line}");
+
+        let (logger, captured) = Logger::new().without_timestamps().with_timestamp_position(TimestampPosition::End).add_writer_capture();
+        logger.log(&Record::builder().level(Level::Info).args(format_args!("started")).build());
+        assert_eq!(captured.lines(), vec!["[][] -> {INFO} started"]);
+    }
+
+    #[test]
+    fn test_exit_on_flushes_before_exit() {
+        // The exit is tested in a child process running this test with the variable set.
+        if let Ok(path) = std::env::var("RSLOGGER_EXIT_ON_PATH") {
+            let logger = Logger::new()
+                .without_timestamps()
+                .with_exit_on(Level::Error, 3)
+                .add_writer_file(PathBuf::from(path), true, Some(4096));
+            logger.log(&Record::builder().level(Level::Warn).args(format_args!("warning")).build());
+            logger.log(&Record::builder().level(Level::Error).args(format_args!("fatal")).build());
+            unreachable!("The process should have exited");
+        }
+
+        let path = temp_log_path("exit-on");
+        let status = std::process::Command::new(std::env::current_exe().unwrap())
+            .args(["--exact", "tests::test_exit_on_flushes_before_exit", "--nocapture"])
+            .env("RSLOGGER_EXIT_ON_PATH", &path)
+            .status()
+            .unwrap();
+
+        assert_eq!(status.code(), Some(3));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "-[][] -> {WARN} warning\n-[][] -> {ERROR} fatal\n");
+    }
+
+    #[test]
+    fn test_init_after_another_logger() {
+        // Installing a logger is global, so it's tested in a child process running this test with the variable set.
+        if std::env::var("RSLOGGER_INIT_CHILD").is_ok() {
+            struct Other;
+            impl Log for Other {
+                fn enabled(&self, _: &log::Metadata) -> bool { true }
+                fn log(&self, _: &Record) {}
+                fn flush(&self) {}
+            }
+            log::set_logger(&Other).unwrap();
+
+            let (logger, captured) = Logger::new().without_timestamps().add_writer_capture();
+            assert!(matches!(logger.init(), Err(Error::SetLogger(_))));
+            assert!(crate::flush_with_stats().is_none());
+            log::warn!("logged by the other logger");
+            assert!(captured.lines().is_empty());
+            return;
+        }
+
+        let status = std::process::Command::new(std::env::current_exe().unwrap())
+            .args(["--exact", "tests::test_init_after_another_logger", "--nocapture"])
+            .env("RSLOGGER_INIT_CHILD", "1")
+            .status()
+            .unwrap();
+        assert!(status.success());
     }
 
+    fn temp_log_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("rslogger-{}-{}.log", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
 
-    fn create_log(name: &str, level: Level) -> Metadata {
+    fn create_log(name: &str, level: Level) -> Metadata<'_> {
         let mut builder = Metadata::builder();
         builder.level(level);
         builder.target(name);
@@ -0,0 +1,176 @@
+use std::io::{self, Write};
+
+use log::{Level, Log, Record};
+
+///
+/// Adapter turning the bytes written into it into log records, one per line, sent to the global logger.
+/// Useful to stream the output of a child process into the log:
+/// ```no_run
+/// use std::process::{Command, Stdio};
+/// use rslogger::LineLogger;
+///
+/// let mut child = Command::new("ffmpeg").stdout(Stdio::piped()).spawn().unwrap();
+/// let mut adapter = LineLogger::new(log::Level::Info, "child::ffmpeg");
+/// std::io::copy(child.stdout.as_mut().unwrap(), &mut adapter).unwrap();
+/// ```
+/// Bytes after the last newline are kept until the line is completed, and logged when the adapter is dropped.
+/// Invalid UTF-8 is replaced with `U+FFFD`, and `\r\n` terminators are accepted.
+///
+pub struct LineLogger {
+    level: Level,
+    target: String,
+    /// Bytes written after the last newline.
+    partial: Vec<u8>,
+    logger: &'static dyn Log,
+}
+
+impl LineLogger {
+
+    ///
+    /// Creates an adapter logging each line with the given level and target.
+    ///
+    pub fn new(level: Level, target: &str) -> LineLogger {
+        LineLogger { level, target: target.to_string(), partial: Vec::new(), logger: log::logger() }
+    }
+
+    fn log_line(&self, line: &[u8]) {
+        if self.level > log::max_level() {
+            return;
+        }
+
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        self.logger.log(&Record::builder()
+            .level(self.level)
+            .target(&self.target)
+            .args(format_args!("{}", String::from_utf8_lossy(line)))
+            .build());
+    }
+}
+
+impl Write for LineLogger {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.partial.extend_from_slice(buf);
+
+        while let Some(newline) = self.partial.iter().position(|byte| *byte == b'\n') {
+            let line: Vec<u8> = self.partial.drain(..=newline).collect();
+            self.log_line(&line[..newline]);
+        }
+        Ok(buf.len())
+    }
+
+    ///
+    /// Does nothing: an incomplete line is only logged once completed or when the adapter is dropped,
+    /// so a flush in the middle of a line doesn't split it into two records.
+    ///
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for LineLogger {
+    fn drop(&mut self) {
+        if !self.partial.is_empty() {
+            let partial = std::mem::take(&mut self.partial);
+            self.log_line(&partial);
+        }
+    }
+}
+
+///
+/// Returns a writer for libraries expecting a `Write` (pretty-printers, report generators...), 
+/// logging their output through the global logger with the given level and target.
+///
+/// Unlike [`LineLogger`], the output is not split into lines: everything written between two 
+/// flushes becomes a single record, so a multi-line report shares a single timestamp.
+/// The record is emitted when the writer is flushed or dropped (the buffered text is kept in memory 
+/// until then). The record then goes through the buffering of the logger writers as any other record.
+/// ```no_run
+/// use std::io::Write;
+/// let mut writer = rslogger::writer_for(log::Level::Info, "report");
+/// writeln!(writer, "rows: {}", 42).unwrap();
+/// writeln!(writer, "errors: {}", 0).unwrap();
+/// writer.flush().unwrap(); // One record with two lines
+/// ```
+///
+pub fn writer_for(level: Level, target: &str) -> impl Write + Send {
+    RecordWriter { level, target: target.to_string(), pending: Vec::new(), logger: log::logger() }
+}
+
+///
+/// The writer returned by [`writer_for`].
+///
+struct RecordWriter {
+    level: Level,
+    target: String,
+    /// Bytes written since the last flush.
+    pending: Vec<u8>,
+    logger: &'static dyn Log,
+}
+
+impl Write for RecordWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    ///
+    /// Logs everything written since the last flush as a single record, without the final newline.
+    ///
+    fn flush(&mut self) -> io::Result<()> {
+        if self.pending.is_empty() || self.level > log::max_level() {
+            self.pending.clear();
+            return Ok(());
+        }
+
+        let pending = std::mem::take(&mut self.pending);
+        let text = String::from_utf8_lossy(&pending);
+        self.logger.log(&Record::builder()
+            .level(self.level)
+            .target(&self.target)
+            .args(format_args!("{}", text.trim_end_matches(['\r', '\n'])))
+            .build());
+        Ok(())
+    }
+}
+
+impl Drop for RecordWriter {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Logger;
+
+    use super::*;
+
+    #[test]
+    fn test_one_record_per_line() {
+        let (logger, captured) = Logger::new().without_timestamps().with_target().add_writer_capture();
+        log::set_max_level(log::LevelFilter::Trace);
+
+        let mut adapter = LineLogger::new(Level::Warn, "child::test");
+        adapter.logger = Box::leak(Box::new(logger));
+
+        adapter.write_all(b"first\r\nsecond\nthi").unwrap();
+        assert_eq!(captured.lines(), vec!["-[child::test][] -> {WARN} first", "-[child::test][] -> {WARN} second"]);
+
+        drop(adapter);
+        assert!(captured.contains("{WARN} thi"));
+    }
+
+    #[test]
+    fn test_record_per_flush() {
+        let (logger, captured) = Logger::new().without_timestamps().add_writer_capture();
+        log::set_max_level(log::LevelFilter::Trace);
+
+        let mut writer = RecordWriter { level: Level::Info, target: "report".to_string(), pending: Vec::new(), logger: Box::leak(Box::new(logger)) };
+        writeln!(writer, "rows: {}", 42).unwrap();
+        writeln!(writer, "errors: {}", 0).unwrap();
+        assert!(captured.lines().is_empty());
+
+        writer.flush().unwrap();
+        assert_eq!(captured.lines(), vec!["-[][] -> {INFO} rows: 42", "errors: 0"]);
+    }
+}
@@ -0,0 +1,37 @@
+use std::cell::Cell;
+
+thread_local! {
+    ///
+    /// Set while this thread runs the logging pipeline: handing a record to the writers, flushing them,
+    /// or writing the records as the thread of a writer. A record logged from there (e.g. by the target
+    /// of a writer) would recurse into the pipeline or deadlock on the lock of the writer.
+    ///
+    static IN_PIPELINE: Cell<bool> = const { Cell::new(false) };
+}
+
+///
+/// Marks this thread as running the logging pipeline until dropped.
+///
+pub struct PipelineGuard {
+    previous: bool,
+}
+
+impl PipelineGuard {
+
+    pub fn enter() -> PipelineGuard {
+        PipelineGuard { previous: IN_PIPELINE.with(|in_pipeline| in_pipeline.replace(true)) }
+    }
+}
+
+impl Drop for PipelineGuard {
+    fn drop(&mut self) {
+        IN_PIPELINE.with(|in_pipeline| in_pipeline.set(self.previous));
+    }
+}
+
+///
+/// Returns true if this thread runs the logging pipeline, so the records it logs must be dropped.
+///
+pub fn active() -> bool {
+    IN_PIPELINE.with(Cell::get)
+}
@@ -0,0 +1,75 @@
+//!
+//! Lowers the scheduling priority of the writer threads, see `with_background_priority()`.
+//!
+use std::io;
+
+/// The nice value of the background writer threads on Linux.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const BACKGROUND_NICE: libc::c_int = 10;
+
+///
+/// Lowers the scheduling priority of the calling thread, so it loses the CPU to the other threads:
+/// nice value 10 on Linux, utility QoS class on macOS and iOS, below normal priority on Windows.
+/// Does nothing on the other platforms, where the priority can't be set per thread.
+///
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn lower_current_thread() -> io::Result<()> {
+    // On Linux the nice value is per thread, addressed by the thread id.
+    let thread_id = unsafe { libc::gettid() } as libc::id_t;
+    // A thread already nicer is left as is: lowering the nice value requires privileges.
+    if unsafe { libc::getpriority(libc::PRIO_PROCESS, thread_id) } >= BACKGROUND_NICE {
+        return Ok(());
+    }
+
+    match unsafe { libc::setpriority(libc::PRIO_PROCESS, thread_id, BACKGROUND_NICE) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+#[cfg(target_vendor = "apple")]
+pub(crate) fn lower_current_thread() -> io::Result<()> {
+    match unsafe { libc::pthread_set_qos_class_self_np(libc::qos_class_t::QOS_CLASS_UTILITY, 0) } {
+        0 => Ok(()),
+        error => Err(io::Error::from_raw_os_error(error)),
+    }
+}
+
+#[cfg(windows)]
+pub(crate) fn lower_current_thread() -> io::Result<()> {
+    use std::ffi::c_void;
+
+    const THREAD_PRIORITY_BELOW_NORMAL: i32 = -1;
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn GetCurrentThread() -> *mut c_void;
+        fn SetThreadPriority(thread: *mut c_void, priority: i32) -> i32;
+    }
+
+    match unsafe { SetThreadPriority(GetCurrentThread(), THREAD_PRIORITY_BELOW_NORMAL) } {
+        0 => Err(io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_vendor = "apple", windows)))]
+pub(crate) fn lower_current_thread() -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lower_current_thread() {
+        let nice = std::thread::spawn(|| {
+            let before = unsafe { libc::getpriority(libc::PRIO_PROCESS, libc::gettid() as libc::id_t) };
+            lower_current_thread().unwrap();
+            (before, unsafe { libc::getpriority(libc::PRIO_PROCESS, libc::gettid() as libc::id_t) })
+        }).join().unwrap();
+
+        assert_eq!(nice, (nice.0, BACKGROUND_NICE.max(nice.0)));
+    }
+}
@@ -0,0 +1,91 @@
+use std::fmt::{self, Write};
+
+///
+/// Displays a message with its control characters escaped if enabled, see with_sanitize_output():
+/// `\n`, `\r` and `\t` as such, the others (e.g. the ESC of the terminal sequences) as `\u{1b}`.
+///
+pub struct Sanitized<D> {
+    message: D,
+    enabled: bool,
+}
+
+impl<D: fmt::Display> Sanitized<D> {
+
+    pub fn new(message: D, enabled: bool) -> Sanitized<D> {
+        Sanitized { message, enabled }
+    }
+}
+
+impl<D: fmt::Display> fmt::Display for Sanitized<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.enabled {
+            write!(Escaper(f), "{}", self.message)
+        } else {
+            self.message.fmt(f)
+        }
+    }
+}
+
+///
+/// Writes the text it receives on the formatter, escaping the control characters.
+///
+struct Escaper<'a, 'b>(&'a mut fmt::Formatter<'b>);
+
+impl Write for Escaper<'_, '_> {
+    fn write_str(&mut self, text: &str) -> fmt::Result {
+        let mut rest = text;
+        while let Some(index) = rest.find(char::is_control) {
+            self.0.write_str(&rest[..index])?;
+            let control = rest[index..].chars().next().unwrap();
+            match control {
+                '\n' => self.0.write_str("\\n")?,
+                '\r' => self.0.write_str("\\r")?,
+                '\t' => self.0.write_str("\\t")?,
+                other => write!(self.0, "{}", other.escape_unicode())?,
+            }
+            rest = &rest[index + control.len_utf8()..];
+        }
+        self.0.write_str(rest)
+    }
+}
+
+///
+/// Displays bytes from external input as text, e.g. `info!("received {}", rslogger::escape_bytes(&payload))`:
+/// the valid UTF-8 is written as is, except the control characters escaped as by with_sanitize_output(),
+/// and the invalid bytes are hex-encoded as `\xff`.
+///
+pub fn escape_bytes(bytes: &[u8]) -> EscapedBytes<'_> {
+    EscapedBytes(bytes)
+}
+
+///
+/// The bytes displayed by [`escape_bytes`].
+///
+#[derive(Clone, Copy, Debug)]
+pub struct EscapedBytes<'a>(&'a [u8]);
+
+impl fmt::Display for EscapedBytes<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut escaper = Escaper(f);
+        for chunk in self.0.utf8_chunks() {
+            escaper.write_str(chunk.valid())?;
+            for byte in chunk.invalid() {
+                write!(escaper.0, "\\x{byte:02x}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(Sanitized::new("plain text, accents: é", true).to_string(), "plain text, accents: é");
+        assert_eq!(Sanitized::new("two\nlines", false).to_string(), "two\nlines");
+        assert_eq!(Sanitized::new("two\nlines\r\tand \x1b[31mred\x1b[0m\u{85}", true).to_string(), "two\\nlines\\r\\tand \\u{1b}[31mred\\u{1b}[0m\\u{85}");
+        assert_eq!(escape_bytes(b"ok \xff\xfe\n\xc3\xa9").to_string(), "ok \\xff\\xfe\\né");
+    }
+}
@@ -0,0 +1,131 @@
+use std::{
+    cell::Cell,
+    sync::{atomic::{AtomicBool, AtomicU64, Ordering}, Arc, PoisonError, RwLock},
+    time::SystemTime,
+};
+
+use log::Level;
+
+use crate::LOGGER;
+
+type Subscriber = dyn Fn(&LogEvent) + Send + Sync;
+
+/// The subscribers with their id, in the order they subscribed.
+static SUBSCRIBERS: RwLock<Vec<(u64, Arc<Subscriber>)>> = RwLock::new(Vec::new());
+
+/// Set while there is any subscriber, so the records are not handed to an empty list.
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    /// Set while this thread runs the subscribers: the records they log are not handed back to them.
+    static NOTIFYING: Cell<bool> = const { Cell::new(false) };
+}
+
+///
+/// A record handed to the subscribers (see [`subscribe`]).
+///
+#[derive(Clone, Copy, Debug)]
+pub struct LogEvent<'a> {
+    pub level: Level,
+    /// The target of the record, whether the logger displays it or not.
+    pub target: &'a str,
+    /// The formatted arguments of the record, without the timestamp and the other decorations.
+    pub message: &'a str,
+    /// When the record was logged.
+    pub timestamp: SystemTime,
+}
+
+///
+/// A subscriber registered by [`subscribe`], until [`Subscription::unsubscribe`] is called.
+///
+#[derive(Debug)]
+pub struct Subscription(u64);
+
+impl Subscription {
+
+    ///
+    /// Removes the subscriber: it's not called for the records logged from now on.
+    ///
+    pub fn unsubscribe(self) {
+        let mut subscribers = SUBSCRIBERS.write().unwrap_or_else(PoisonError::into_inner);
+        subscribers.retain(|(id, _)| *id != self.0);
+        ACTIVE.store(!subscribers.is_empty(), Ordering::Relaxed);
+        drop(subscribers);
+        crate::level_override::restore_max_level();
+    }
+}
+
+///
+/// Calls `f` with every record accepted by the logger level (as the capture, whatever the writers),
+/// after the writers, e.g. to feed an in-app log viewer. Any number of subscribers can be registered,
+/// they are called in order on the logging thread, so they should be fast.
+/// The records logged by a subscriber are written but not handed to the subscribers.
+/// ```
+/// use std::sync::{Arc, Mutex};
+/// use rslogger::Logger;
+/// Logger::new().with_level(log::LevelFilter::Info).init().unwrap();
+///
+/// let messages = Arc::new(Mutex::new(Vec::new()));
+/// let subscriber_messages = Arc::clone(&messages);
+/// let subscription = rslogger::subscribe(move |event| {
+///     subscriber_messages.lock().unwrap().push(format!("{}: {}", event.level, event.message));
+/// });
+/// log::info!("seen");
+/// log::debug!("filtered by the logger level");
+/// subscription.unsubscribe();
+/// log::info!("logged after unsubscribing");
+/// assert_eq!(*messages.lock().unwrap(), ["INFO: seen"]);
+/// ```
+///
+pub fn subscribe(f: impl Fn(&LogEvent) + Send + Sync + 'static) -> Subscription {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    SUBSCRIBERS.write().unwrap_or_else(PoisonError::into_inner).push((id, Arc::new(f)));
+    ACTIVE.store(true, Ordering::Relaxed);
+
+    // The writers may accept less than the logger level, the subscribers accept all of it.
+    if let Some(logger) = LOGGER.get() && logger.max_level() > log::max_level() {
+        log::set_max_level(logger.max_level());
+    }
+    Subscription(id)
+}
+
+///
+/// Returns true if there is any subscriber.
+///
+pub(crate) fn active() -> bool {
+    ACTIVE.load(Ordering::Relaxed)
+}
+
+///
+/// Hands the record to the subscribers, unless it's logged by one of them.
+///
+pub(crate) fn notify(record: &log::Record) {
+    if NOTIFYING.with(Cell::get) {
+        return;
+    }
+    // Called out of the lock, so a subscriber can subscribe or unsubscribe.
+    let subscribers: Vec<_> = SUBSCRIBERS.read().unwrap_or_else(PoisonError::into_inner)
+        .iter()
+        .map(|(_, subscriber)| Arc::clone(subscriber))
+        .collect();
+
+    let message = record.args().to_string();
+    let event = LogEvent { level: record.level(), target: record.target(), message: &message, timestamp: SystemTime::now() };
+
+    // Reset on drop, so a panicking subscriber doesn't leave the flag set.
+    struct Reset;
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            NOTIFYING.with(|notifying| notifying.set(false));
+        }
+    }
+
+    NOTIFYING.with(|notifying| notifying.set(true));
+    let _reset = Reset;
+    for subscriber in subscribers {
+        subscriber(&event);
+    }
+}
+
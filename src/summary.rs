@@ -0,0 +1,128 @@
+use std::{
+    fmt,
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::{atomic::{AtomicBool, AtomicU64, Ordering}, OnceLock},
+};
+
+use log::Level;
+
+/// Number of distinct messages counted for the top messages: the new ones logged afterwards are not tracked.
+const MAX_TRACKED_MESSAGES: usize = 1024;
+
+///
+/// Counts the records logged, to write a summary of the run at shutdown.
+///
+pub struct Summary {
+    /// Number of most frequent messages listed.
+    top: usize,
+    /// Records logged per level, Error first.
+    counts: [AtomicU64; 5],
+    /// Bytes of the lines logged.
+    bytes: AtomicU64,
+    /// Occurrences of each distinct message, up to MAX_TRACKED_MESSAGES messages, in an open addressing 
+    /// table so the logging threads count them without a lock. Empty if `top` is 0.
+    messages: Box<[MessageSlot]>,
+    /// Set once the report is taken, so it's written at the first shutdown only.
+    reported: AtomicBool,
+}
+
+impl Summary {
+
+    pub fn new(top: usize) -> Summary {
+        Summary {
+            top,
+            counts: Default::default(),
+            bytes: AtomicU64::new(0),
+            messages: (0..if top == 0 { 0 } else { MAX_TRACKED_MESSAGES }).map(|_| MessageSlot::default()).collect(),
+            reported: AtomicBool::new(false),
+        }
+    }
+
+    ///
+    /// Counts a record logged in a line of `bytes` bytes, with `message` if it has no arguments 
+    /// (see `fmt::Arguments::as_str()`): the formatted messages are not listed in the top messages.
+    ///
+    pub fn count(&self, level: Level, message: Option<&'static str>, bytes: usize) {
+        self.counts[level as usize - 1].fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+        let Some(message) = message.filter(|_| !self.messages.is_empty()) else {
+            return;
+        };
+
+        let mut hasher = DefaultHasher::new();
+        message.hash(&mut hasher);
+        let start = hasher.finish() as usize;
+        // Linear probing: a slot keeps the first message stored in it, the table being never cleared.
+        for probe in 0..self.messages.len() {
+            let slot = &self.messages[(start + probe) % self.messages.len()];
+            if *slot.message.get_or_init(|| message) == message {
+                slot.count.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+        }
+    }
+
+    ///
+    /// Returns the report of the records counted so far the first time it's called, None afterwards.
+    ///
+    pub fn take_report(&self) -> Option<String> {
+        if self.reported.swap(true, Ordering::Relaxed) {
+            return None;
+        }
+        Some(self.to_string())
+    }
+}
+
+impl fmt::Display for Summary {
+    ///
+    /// `Summary: 1 ERROR, 2 WARN, 3 INFO, 0 DEBUG, 0 TRACE, 1234 bytes. Top messages: 2x "retrying", 1x "done"`
+    ///
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Summary: ")?;
+        for (level, count) in Level::iter().zip(&self.counts) {
+            write!(f, "{} {level}, ", count.load(Ordering::Relaxed))?;
+        }
+        write!(f, "{} bytes", self.bytes.load(Ordering::Relaxed))?;
+
+        let mut top: Vec<(&str, u64)> = self.messages.iter()
+            .filter_map(|slot| slot.message.get().map(|message| (*message, slot.count.load(Ordering::Relaxed))))
+            .collect();
+        // The most frequent first, then alphabetically so the report is stable.
+        top.sort_by(|(message, count), (other_message, other_count)| other_count.cmp(count).then(message.cmp(other_message)));
+        for (index, (message, count)) in top.into_iter().take(self.top).enumerate() {
+            write!(f, "{}{count}x {message:?}", if index == 0 { ". Top messages: " } else { ", " })?;
+        }
+        Ok(())
+    }
+}
+
+///
+/// A message of the table of Summary and its occurrences.
+///
+#[derive(Default)]
+struct MessageSlot {
+    message: OnceLock<&'static str>,
+    count: AtomicU64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        let summary = Summary::new(2);
+        for (level, message) in [(Level::Warn, "retrying"), (Level::Error, "failed"), (Level::Warn, "retrying"), (Level::Info, "done")] {
+            summary.count(level, Some(message), message.len());
+        }
+        // Formatted, so not listed.
+        summary.count(Level::Info, None, 10);
+        assert_eq!(summary.take_report().unwrap(),
+            "Summary: 1 ERROR, 2 WARN, 2 INFO, 0 DEBUG, 0 TRACE, 36 bytes. Top messages: 2x \"retrying\", 1x \"done\"");
+        assert_eq!(summary.take_report(), None);
+
+        let no_top = Summary::new(0);
+        no_top.count(Level::Trace, Some("ignored"), 7);
+        assert_eq!(no_top.to_string(), "Summary: 0 ERROR, 0 WARN, 0 INFO, 0 DEBUG, 1 TRACE, 7 bytes");
+    }
+}
@@ -0,0 +1,33 @@
+use std::sync::atomic::Ordering;
+
+use crate::LOGGER;
+
+///
+/// Suspends all logging until [`resume`] is called, e.g. around a performance-critical section.
+/// While suspended, the global logger drops the records as soon as they reach it, 
+/// without checking the writers filters nor formatting them.
+/// The max level of the log crate is unchanged, so the macros still call the logger.
+/// ```no_run
+/// use rslogger::Logger;
+/// Logger::new().add_writer_stdout(false, None).init().unwrap();
+/// rslogger::suspend();
+/// log::info!("This is dropped");
+/// rslogger::resume();
+/// log::info!("This is logged");
+/// ```
+/// Does nothing if the logger was not initialized.
+///
+pub fn suspend() {
+    if let Some(logger) = LOGGER.get() {
+        logger.suspended.store(true, Ordering::Relaxed);
+    }
+}
+
+///
+/// Resumes the logging suspended by [`suspend`]. The records logged in between are not replayed.
+///
+pub fn resume() {
+    if let Some(logger) = LOGGER.get() {
+        logger.suspended.store(false, Ordering::Relaxed);
+    }
+}
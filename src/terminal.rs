@@ -0,0 +1,70 @@
+//!
+//! Reads the width of the terminal attached to stdout, see `with_colors()`.
+//!
+
+///
+/// Returns the number of columns of the terminal attached to stdout, falling back to the `COLUMNS`
+/// environment variable when the terminal can't be queried. Returns None if the width is unknown.
+///
+pub(crate) fn width() -> Option<usize> {
+    query_width().or_else(|| std::env::var("COLUMNS").ok()?.trim().parse().ok()).filter(|width| *width > 0)
+}
+
+#[cfg(unix)]
+fn query_width() -> Option<usize> {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    match unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } {
+        0 => Some(size.ws_col as usize),
+        _ => None,
+    }
+}
+
+#[cfg(windows)]
+fn query_width() -> Option<usize> {
+    use std::ffi::c_void;
+
+    const STD_OUTPUT_HANDLE: u32 = -11i32 as u32;
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct Coord {
+        x: i16,
+        y: i16,
+    }
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct SmallRect {
+        left: i16,
+        top: i16,
+        right: i16,
+        bottom: i16,
+    }
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct ConsoleScreenBufferInfo {
+        size: Coord,
+        cursor_position: Coord,
+        attributes: u16,
+        window: SmallRect,
+        maximum_window_size: Coord,
+    }
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn GetStdHandle(handle: u32) -> *mut c_void;
+        fn GetConsoleScreenBufferInfo(console: *mut c_void, info: *mut ConsoleScreenBufferInfo) -> i32;
+    }
+
+    let mut info = ConsoleScreenBufferInfo::default();
+    match unsafe { GetConsoleScreenBufferInfo(GetStdHandle(STD_OUTPUT_HANDLE), &mut info) } {
+        0 => None,
+        _ => Some((info.window.right - info.window.left + 1) as usize),
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn query_width() -> Option<usize> {
+    None
+}
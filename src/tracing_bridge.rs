@@ -0,0 +1,224 @@
+//!
+//! Bridge from the `tracing` crate, available with the `tracing` feature.
+//! Installs a minimal `tracing` subscriber turning the events into `log` records,
+//! so they flow through the writers of this logger with the `log` records.
+//!
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt::{self, Write},
+    sync::{atomic::{AtomicU64, Ordering}, Mutex, PoisonError},
+};
+
+use log::{Level, Log, Record};
+use tracing::{
+    dispatcher::SetGlobalDefaultError,
+    field::{Field, Visit},
+    span, subscriber::Interest, Event, Metadata, Subscriber,
+};
+
+thread_local! {
+    /// Ids of the spans entered on this thread, innermost last.
+    static ENTERED: RefCell<Vec<u64>> = const { RefCell::new(Vec::new()) };
+}
+
+///
+/// Installs the bridge as the global `tracing` subscriber.
+/// The events are logged through the global logger with the mapped level, the same target
+/// and location, and the fields of the event and of the entered spans flattened into the message:
+/// `span{field=1}: message key=value`.
+/// ```no_run
+/// use rslogger::Logger;
+/// Logger::new().add_writer_stdout(false, None).init().unwrap();
+/// rslogger::tracing_bridge::init().unwrap();
+/// tracing::info!(user = "bob", "logged in");
+/// ```
+/// # Errors
+/// If a global `tracing` subscriber was already set.
+///
+pub fn init() -> Result<(), SetGlobalDefaultError> {
+    tracing::subscriber::set_global_default(LogSubscriber::new(log::logger()))
+}
+
+struct SpanData {
+    /// `name{field=value ...}`, as displayed in front of the messages.
+    label: String,
+    references: usize,
+}
+
+struct LogSubscriber {
+    last_id: AtomicU64,
+    spans: Mutex<HashMap<u64, SpanData>>,
+    logger: &'static dyn Log,
+}
+
+impl LogSubscriber {
+
+    fn new(logger: &'static dyn Log) -> LogSubscriber {
+        LogSubscriber { last_id: AtomicU64::new(0), spans: Mutex::new(HashMap::new()), logger }
+    }
+
+    fn spans(&self) -> std::sync::MutexGuard<'_, HashMap<u64, SpanData>> {
+        self.spans.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Subscriber for LogSubscriber {
+    fn register_callsite(&self, _metadata: &'static Metadata<'static>) -> Interest {
+        // The log level can change at runtime, so the interest is evaluated at each call.
+        Interest::sometimes()
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        if metadata.is_span() {
+            return true;
+        }
+
+        let level = to_log_level(metadata.level());
+        level <= log::max_level()
+            && self.logger.enabled(&log::Metadata::builder().level(level).target(metadata.target()).build())
+    }
+
+    fn new_span(&self, span: &span::Attributes<'_>) -> span::Id {
+        let id = self.last_id.fetch_add(1, Ordering::Relaxed) + 1;
+
+        let mut fields = FieldsVisitor::default();
+        span.record(&mut fields);
+        let label = if fields.fields.is_empty() {
+            span.metadata().name().to_string()
+        } else {
+            format!("{}{{{}}}", span.metadata().name(), fields.fields.trim_start())
+        };
+
+        self.spans().insert(id, SpanData { label, references: 1 });
+        span::Id::from_u64(id)
+    }
+
+    fn record(&self, span: &span::Id, values: &span::Record<'_>) {
+        let mut fields = FieldsVisitor::default();
+        values.record(&mut fields);
+
+        if let Some(data) = self.spans().get_mut(&span.into_u64()) {
+            match data.label.strip_suffix('}') {
+                Some(label) => data.label = format!("{label}{}}}", fields.fields),
+                None => data.label = format!("{}{{{}}}", data.label, fields.fields.trim_start()),
+            }
+        }
+    }
+
+    fn record_follows_from(&self, _span: &span::Id, _follows: &span::Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let metadata = event.metadata();
+        let mut fields = FieldsVisitor::default();
+        event.record(&mut fields);
+
+        let mut message = String::new();
+        ENTERED.with(|entered| {
+            let spans = self.spans();
+            for id in entered.borrow().iter() {
+                if let Some(data) = spans.get(id) {
+                    let _ = write!(message, "{}:", data.label);
+                }
+            }
+        });
+        if !message.is_empty() {
+            message.push(' ');
+        }
+        message.push_str(&fields.message);
+        message.push_str(&fields.fields);
+
+        self.logger.log(&Record::builder()
+            .level(to_log_level(metadata.level()))
+            .target(metadata.target())
+            .module_path(metadata.module_path())
+            .file(metadata.file())
+            .line(metadata.line())
+            .args(format_args!("{message}"))
+            .build());
+    }
+
+    fn enter(&self, span: &span::Id) {
+        ENTERED.with(|entered| entered.borrow_mut().push(span.into_u64()));
+    }
+
+    fn exit(&self, span: &span::Id) {
+        ENTERED.with(|entered| {
+            let mut entered = entered.borrow_mut();
+            if let Some(position) = entered.iter().rposition(|id| *id == span.into_u64()) {
+                entered.remove(position);
+            }
+        });
+    }
+
+    fn clone_span(&self, span: &span::Id) -> span::Id {
+        if let Some(data) = self.spans().get_mut(&span.into_u64()) {
+            data.references += 1;
+        }
+        span.clone()
+    }
+
+    fn try_close(&self, span: span::Id) -> bool {
+        let mut spans = self.spans();
+        let closed = match spans.get_mut(&span.into_u64()) {
+            Some(data) => {
+                data.references -= 1;
+                data.references == 0
+            }
+            None => false,
+        };
+
+        if closed {
+            spans.remove(&span.into_u64());
+        }
+        closed
+    }
+}
+
+///
+/// Collects the `message` field and the other fields as ` key=value` pairs.
+///
+#[derive(Default)]
+struct FieldsVisitor {
+    message: String,
+    fields: String,
+}
+
+impl Visit for FieldsVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{value:?}");
+        } else {
+            let _ = write!(self.fields, " {}={value:?}", field.name());
+        }
+    }
+}
+
+fn to_log_level(level: &tracing::Level) -> Level {
+    match *level {
+        tracing::Level::ERROR => Level::Error,
+        tracing::Level::WARN => Level::Warn,
+        tracing::Level::INFO => Level::Info,
+        tracing::Level::DEBUG => Level::Debug,
+        tracing::Level::TRACE => Level::Trace,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_with_span() {
+        let (logger, captured) = crate::Logger::new().without_timestamps().with_target().add_writer_capture();
+        log::set_max_level(log::LevelFilter::Trace);
+
+        tracing::subscriber::with_default(LogSubscriber::new(Box::leak(Box::new(logger))), || {
+            let span = tracing::info_span!("request", id = 7);
+            let _entered = span.enter();
+            tracing::warn!(target: "app::auth", user = "bob", "logged {}", "in");
+        });
+
+        assert_eq!(captured.lines(), vec![r#"-[app::auth][] -> {WARN} request{id=7}: logged in user="bob""#]);
+    }
+}
@@ -1,332 +1,4288 @@
+use log::Level;
+use crate::{capture::CaptureHandle, pipeline::PipelineGuard, priority, DeferredRecord, Error};
 use std::{
-    fs, io::{BufWriter, Write}, 
-    path::PathBuf, 
+    collections::VecDeque,
+    fmt,
+    fs::{self, File}, io::{self, BufWriter, ErrorKind, IoSlice, Write}, 
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf}, 
     sync::{
-        mpsc::{channel, Receiver, Sender
-        }, RwLock}, 
-    thread::{self, JoinHandle}
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        mpsc::{channel, sync_channel, Receiver, RecvTimeoutError, SendError, Sender, SyncSender, TrySendError
+        }, Arc, Mutex, PoisonError, RwLock}, 
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime},
 };
+use time::{format_description::FormatItem, OffsetDateTime, Time, UtcOffset};
 
 const DEFAULT_BUFFER_CAPACITY : usize = 100;
 
-#[derive(PartialEq, Clone)]
+/// The buffer capacity of the writers flushed every N records, so the buffer rarely fills up first.
+const RECORDS_BUFFER_CAPACITY: usize = 64 * 1024;
+
+/// Max number of records written by the separate thread under a single lock of the BufWriter.
+const BATCH_RECORDS: usize = 512;
+/// Max number of bytes written by the separate thread under a single lock of the BufWriter.
+const BATCH_BYTES: usize = 64 * 1024;
+
+/// The minimum time between two reports of the IO failures of a writer.
+const IO_REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How long stopping a tokio writer task waits for it, e.g. while the runtime is busy or shutting down.
+#[cfg(feature = "async-tokio")]
+const TASK_STOP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The length of the thread names kept by Linux, as shown by `top -H` (the rest is cut).
+const THREAD_NAME_LEN: usize = 15;
+
+/// The period of the daily rotated files, in their name.
+const DAY_FORMAT: &[FormatItem] = time::macros::format_description!("[year]-[month]-[day]");
+/// The period of the hourly rotated files, in their name.
+const HOUR_FORMAT: &[FormatItem] = time::macros::format_description!("[year]-[month]-[day]-[hour]");
+/// The init time, for the `{time}` placeholder of the file paths.
+const TIME_FORMAT: &[FormatItem] = time::macros::format_description!("[hour]-[minute]-[second]");
+/// The time a file was closed, in its name with RotatedNaming::Timestamp.
+const CUT_FORMAT: &[FormatItem] = time::macros::format_description!("[year]-[month]-[day]T[hour]-[minute]-[second]");
+
+/// Resets all the ANSI colors and styles.
+pub const ANSI_RESET: &str = "\x1b[0m";
+
+#[derive(PartialEq, Clone, Debug)]
 pub enum WriteTarget {
     StdOut, 
-    File
+    File,
+    Capture,
+    Null,
+    Custom,
+    OpenFile,
 }
 
-enum WriteMode {
-    ThisThread,
-    SeparateThread,
+///
+/// The capacity of the buffer of a writer. A plain `usize` converts to bytes, and `None` to the default capacity.
+/// 
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum BufferCapacity {
+    /// The buffer holds up to this many bytes. With 0, every record is written on the target with a single write and flushed.
+    Bytes(usize),
+    /// The buffer is flushed every this many records (or when its 64 KiB are full).
+    Records(usize),
 }
 
-enum MsgType {
-    Msg(String),
-    Flush,
-    FlushAndStop,
+impl Default for BufferCapacity {
+    fn default() -> Self {
+        BufferCapacity::Bytes(DEFAULT_BUFFER_CAPACITY)
+    }
 }
 
-pub struct BufferedWriter {
+impl From<usize> for BufferCapacity {
+    fn from(bytes: usize) -> Self {
+        BufferCapacity::Bytes(bytes)
+    }
+}
 
-    ///
-    /// The target to write to
-    /// 
-    target: WriteTarget,
+impl From<Option<usize>> for BufferCapacity {
+    fn from(bytes: Option<usize>) -> Self {
+        bytes.map(BufferCapacity::Bytes).unwrap_or_default()
+    }
+}
 
-    ///
-    /// Whether to write on the caller thread or on a separate thread
-    /// 
-    mode: WriteMode,
+///
+/// When a writer flushes its buffer, on top of the level flush policies (see `with_flush_policy`).
+/// 
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum FlushPolicy {
+    /// The buffer is written when it's full or explicitly flushed (default for the file writers).
+    Buffered,
+    /// The buffer is flushed after each record, as a `LineWriter` flushes after each line (default for stdout).
+    /// The records are still written as a whole, without the partial writes of an unbuffered writer.
+    EveryRecord,
+    /// The buffer is flushed every this many records (and when it's full), so a tailed file shows
+    /// the progress at a steady pace. Counted where the records are written, on the separate thread if any.
+    EveryN(usize),
+}
 
-    ///
-    /// The file path to write to.
-    /// Only meaningful if writing on a file 
-    /// 
-    file_path: PathBuf,
+///
+/// When a file writer makes the OS write its data on the disk, so it survives a power loss (see `with_sync`).
+/// Flushing the buffer only hands the data to the OS page cache.
+/// 
+#[derive(PartialEq, Clone, Copy, Debug, Default)]
+pub enum SyncPolicy {
+    /// The OS writes the data when it wants (default).
+    #[default]
+    Never,
+    /// The data is synced at each flush of the buffer: the good tradeoff for audit logs.
+    OnFlush,
+    /// As OnFlush, with `File::sync_all()`: all the metadata of the file (e.g. its modification time) is synced too.
+    FullOnFlush,
+    /// The buffer is flushed and synced after each record. Very slow: each record waits for the disk.
+    EveryRecord,
+}
 
-    ///
-    /// The capacity of the buffer.
-    /// If set to 0, it will write on the target line by line. 
-    /// Otherwise it will buffer and then flush.
-    /// 
-    buffer_capacity: usize,
+///
+/// How a file writer opens an existing file at init (see `with_open_mode`).
+/// 
+#[derive(PartialEq, Clone, Copy, Debug, Default)]
+pub enum OpenMode {
+    /// The records are appended after the content of the file (default).
+    #[default]
+    Append,
+    /// The content of the file is discarded, so each run starts a fresh file (e.g. in a dev loop).
+    /// Can't be combined with a rotation policy.
+    Truncate,
+}
 
-    ///
-    /// The BufWriters on the target Stdout.
-    /// - Option because it's only initialized at init()
-    /// - Box so I can use the dynamic features
-    /// - RwLock because we need async interior mutability (It's needed for integration with log crate)
-    /// 
-    buf_writer: Option<Box<RwLock<BufWriter<dyn Write + Send + Sync>>>>,
+///
+/// When a file writer closes its file and starts a new one (see `with_rotation`). 
+/// The closed file is renamed after its period, e.g. `app.log` becomes `app.2024-06-03.log`.
+/// 
+#[derive(PartialEq, Clone, Copy, Debug, Default)]
+pub enum RotationPolicy {
+    /// The writer keeps writing the same file (default).
+    #[default]
+    Never,
+    /// A new file at each local midnight, the closed one named e.g. `app.2024-06-03.log`.
+    Daily,
+    /// A new file at each local hour, the closed one named e.g. `app.2024-06-03-14.log`.
+    Hourly,
+}
 
+impl RotationPolicy {
     ///
-    /// The handler of the separate thread, 
-    /// only meaningful if the mode is SeparateThread.
+    /// Returns the period containing `time`, in local time (UTC if the local offset can't be determined),
+    /// and the time the next period starts.
     /// 
-    thread_handler: Option<JoinHandle<()>>,
+    fn period(self, time: SystemTime) -> (String, SystemTime) {
+        let local = local_time(time);
+        let (format, start, length) = match self {
+            // Never doesn't rotate, its period is never asked.
+            RotationPolicy::Never | RotationPolicy::Daily => (DAY_FORMAT, local.replace_time(Time::MIDNIGHT), time::Duration::DAY),
+            RotationPolicy::Hourly => (HOUR_FORMAT, local.replace_time(Time::MIDNIGHT).replace_hour(local.hour()).unwrap(), time::Duration::HOUR),
+        };
+        (local.format(format).unwrap(), (start + length).into())
+    }
+}
 
+///
+/// How a rotated file is named (see `with_rotated_naming`). The file being written keeps its name, 
+/// and the rotated files are never renamed again.
+/// 
+#[derive(PartialEq, Clone, Copy, Debug, Default)]
+pub enum RotatedNaming {
+    /// After the period of its records: `app.2024-06-03.log` (default).
+    #[default]
+    Period,
+    /// After the local time it was closed, to the second: `app.2024-06-03T14-00-00.log`.
+    /// A file left by a previous run in a previous period is named after its last write.
+    Timestamp,
+}
+
+impl RotatedNaming {
     ///
-    /// The sender to send messages on the separate thread. 
-    /// only meaningful if the mode is SeparateThread.
+    /// Returns what is inserted in the name of a file holding the records of `period`, closed at `cut`.
     /// 
-    sender: Option<Sender<MsgType>>,
+    fn label(self, period: &str, cut: SystemTime) -> String {
+        match self {
+            RotatedNaming::Period => period.to_string(),
+            RotatedNaming::Timestamp => local_time(cut).format(CUT_FORMAT).unwrap(),
+        }
+    }
 }
 
+///
+/// Returns `time` in local time, in UTC if the local offset can't be determined.
+/// 
+fn local_time(time: SystemTime) -> OffsetDateTime {
+    let utc = OffsetDateTime::from(time);
+    utc.to_offset(UtcOffset::local_offset_at(utc).unwrap_or(UtcOffset::UTC))
+}
 
-impl BufferedWriter {
+///
+/// What a writer does after writing a record of a given level.
+/// 
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum LevelFlush {
+    /// The writer is flushed right after the record is written.
+    Immediate,
+    /// The record stays in the buffer until it's full or explicitly flushed (default).
+    Buffered,
+}
 
-    ///
-    /// Initializes the Writer with default mode as ThisThread.
-    /// 
-    pub fn new() -> BufferedWriter {
-        BufferedWriter { 
-            target: WriteTarget::StdOut,
-            mode: WriteMode::ThisThread, 
-            file_path: PathBuf::default(), 
-            buffer_capacity: DEFAULT_BUFFER_CAPACITY, 
-            buf_writer: None,
-            thread_handler: None, 
-            sender: None 
+///
+/// What a writer does with a record when its bounded queue is full (see `with_queue_capacity`).
+/// 
+#[derive(PartialEq, Clone, Copy, Debug, Default)]
+pub enum OverflowPolicy {
+    /// Logging waits until the writer thread makes room (default). No record is lost.
+    #[default]
+    Block,
+    /// The new record is dropped, the queued ones are kept.
+    DropNewest,
+    /// The new record is queued and the oldest record not yet queued is dropped.
+    /// The records exceeding the queue wait in a ring of the same capacity on the logging side,
+    /// moved to the queue on the next record or flush.
+    DropOldest,
+}
+
+///
+/// A crossing of the queue watermarks of a writer, with the number of records queued.
+/// 
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum QueueWatermark {
+    /// The queue reached the high watermark.
+    High(usize),
+    /// The queue went back down to the low watermark.
+    Low(usize),
+}
+
+impl fmt::Display for QueueWatermark {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueueWatermark::High(queued) => write!(f, "queue above the high watermark ({queued} records queued), the writer can't keep up"),
+            QueueWatermark::Low(queued) => write!(f, "queue back below the low watermark ({queued} records queued)"),
         }
     }
+}
 
-    pub fn on_stdout(mut self) -> BufferedWriter {
-        self.target = WriteTarget::StdOut;
-        self
-    }
+///
+/// What happened on the writers at shutdown, returned by [`Logger::flush_with_stats`](crate::Logger::flush_with_stats).
+/// The counts cover the whole life of the writers.
+/// 
+#[derive(PartialEq, Clone, Copy, Debug, Default)]
+pub struct FlushStats {
+    /// The records handed to the targets without error.
+    pub written: u64,
+    /// The records dropped because a queue was full, or because a separate thread had stopped.
+    pub dropped: u64,
+    /// The time taken to flush and stop the writers.
+    pub duration: Duration,
+    /// False if a separate thread panicked instead of stopping cleanly.
+    pub joined: bool,
+}
 
-    pub fn on_file(mut self, file_path: PathBuf) -> BufferedWriter {
-        self.target = WriteTarget::File;
-        self.file_path = file_path;
-        self
-    }
+///
+/// The record counters of a writer, shared with its separate thread.
+/// 
+#[derive(Default)]
+struct Counters {
+    /// The records in the queue, decremented by the separate thread as it receives them.
+    queued: AtomicUsize,
+    /// The records handed to the target without error.
+    written: AtomicU64,
+    /// The records dropped because the queue was full or the separate thread was dead.
+    dropped: AtomicU64,
+    /// The bytes in the buffer of the separate thread, updated after each message.
+    buffered: AtomicUsize,
+}
 
+impl Counters {
+    ///
+    /// Counts a record received by the separate thread, to be written.
     /// 
-    /// Sets the write mode to ThisThread (default). 
-    /// With this mode, the logging operations will happen on the thread which is calling the write().
-    /// 
-    #[must_use = "You must call init() to initialize the writer"]
-    #[allow(dead_code)]
-    pub fn with_this_thread(mut self) -> BufferedWriter {
-        self.mode = WriteMode::ThisThread;
-        self
+    fn received(&self) {
+        self.queued.fetch_sub(1, Ordering::Relaxed);
     }
 
+    ///
+    /// Counts the records handed to the target without error.
     /// 
-    /// With this mode, the logging operations will happen of a dedicated separate thread.
-    /// 
-    pub fn with_separate_thread(mut self) -> BufferedWriter {
-        self.mode = WriteMode::SeparateThread;
-        self
+    fn written(&self, records: u64) {
+        self.written.fetch_add(records, Ordering::Relaxed);
     }
 
     ///
-    /// Sets the capcity of the buffer. 
-    /// Not calling this function will use the default capacity. 
-    /// Calling this function with capacity = 0, will flush log by log on the target 
+    /// Records the bytes in the buffer, for buffered_len() on the logging side.
     /// 
-    pub fn with_buffer_capacity(mut self, capacity: usize) -> BufferedWriter {
-        self.buffer_capacity = capacity;
-        self
+    fn update_buffered(&self, buf_writer: &RwLock<Sink>) {
+        let buffered = buf_writer.read().unwrap_or_else(PoisonError::into_inner).buffered_len();
+        self.buffered.store(buffered, Ordering::Relaxed);
     }
+}
+
+///
+/// The IO state of a writer, shared with its separate thread: whether the pipe is broken, and the failures to report.
+/// 
+#[derive(Default)]
+struct IoState {
+    broken_pipe: AtomicBool,
+    /// Set from a failure until an operation succeeds again, so the successes don't take the lock.
+    failing: AtomicBool,
+    reports: Mutex<IoReports>,
+}
 
+#[derive(Default)]
+struct IoReports {
+    /// The failures since the last success.
+    failures: u64,
+    /// The failures since the last report.
+    unreported: u64,
+    reported_at: Option<Instant>,
+}
+
+impl IoState {
     ///
-    /// Initializes the BufferedWriter. To be necessarily called before any write. 
-    /// In case of failures returns an error with the description of the error
+    /// Returns true if the reader of the target went away: nothing is written anymore.
     /// 
-    pub fn init(self) -> Result<BufferedWriter, String> {
-        match self.init_writers() {
-            Ok(moved_self) => {
-                match &moved_self.mode {
-                    WriteMode::SeparateThread => return moved_self.init_separate_thread(),
-                    _ => return Ok(moved_self),
-                }
-            },
-            Err(error) => return Err(error),
-        }
+    fn broken(&self) -> bool {
+        self.broken_pipe.load(Ordering::Relaxed)
     }
 
     ///
-    /// Writes the message on the target using the configured mode. 
-    /// # Panics 
-    /// If called before init()
+    /// Records the result of an IO operation, returning the report to print if any: the first failure, then at most 
+    /// one every IO_REPORT_INTERVAL while the failures go on, and once that the writer works again.
+    /// A broken pipe is never reported, it marks the writer as broken instead.
     /// 
-    pub fn write(&self, message: &str) {
-        match &self.mode {
-            WriteMode::ThisThread => BufferedWriter::write_on_this_thread(
-                message, self.buf_writer.as_ref().unwrap()),
-            WriteMode::SeparateThread => {
-                let _ = self.sender.as_ref().unwrap().send(MsgType::Msg(message.to_string()));
+    fn report(&self, result: io::Result<()>, message: &str, now: Instant) -> Option<String> {
+        let err = match result {
+            Ok(()) if !self.failing.load(Ordering::Relaxed) => return None,
+            Ok(()) => {
+                self.failing.store(false, Ordering::Relaxed);
+                let mut reports = self.reports.lock().unwrap_or_else(PoisonError::into_inner);
+                let failures = std::mem::take(&mut reports.failures);
+                *reports = IoReports::default();
+                return Some(format!("The writer works again, after {failures} failed operations"));
+            }
+            Err(err) if err.kind() == ErrorKind::BrokenPipe => {
+                self.broken_pipe.store(true, Ordering::Relaxed);
+                return None;
+            }
+            Err(err) => err,
+        };
+
+        self.failing.store(true, Ordering::Relaxed);
+        let mut reports = self.reports.lock().unwrap_or_else(PoisonError::into_inner);
+        reports.failures += 1;
+        match reports.reported_at {
+            Some(reported_at) if now.duration_since(reported_at) < IO_REPORT_INTERVAL => {
+                reports.unreported += 1;
+                None
+            }
+            Some(_) => {
+                let unreported = std::mem::take(&mut reports.unreported);
+                reports.reported_at = Some(now);
+                Some(format!("{message}: {err} ({unreported} more failures not reported)"))
+            }
+            None => {
+                reports.reported_at = Some(now);
+                Some(format!("{message}: {err}"))
             }
         }
     }
+}
 
-    ///
-    /// Immediately flushes the buffer. 
-    /// # Panics 
-    /// If called before init()
-    /// 
-    #[allow(dead_code)]
-    pub fn flush(&self) {
-        match &self.mode {
-            WriteMode::ThisThread => 
-                BufferedWriter::flush_on_this_thread(self.buf_writer.as_ref().unwrap()),
-            WriteMode::SeparateThread => 
-                self.sender.as_ref().unwrap().send(MsgType::Flush).unwrap_or_default(),
-        }
+///
+/// The failures of a writer away from its records (e.g. a rotated file that can't be deleted),
+/// taken by the logger to report them on the other writers, see [`BufferedWriter::take_notices`].
+/// 
+#[derive(Default)]
+struct Notices {
+    /// Set while notices are waiting, so the logger polls them without taking the lock.
+    pending: AtomicBool,
+    notices: Mutex<Vec<String>>,
+}
+
+impl Notices {
+    fn push(&self, notice: String) {
+        self.notices.lock().unwrap_or_else(PoisonError::into_inner).push(notice);
+        self.pending.store(true, Ordering::Release);
     }
 
-    /// 
-    /// Flushes the buffer and clean up the writer. 
-    /// If the mode is SeparateThread, the thread will be stopped and it's no longer possible
-    /// to write anything else. 
-    /// After calling this method you can only restart to write if you call the init method again.
-    /// Use this method before dropping the BufferedWriter to ensure all the data are flushed.
-    /// 
-    pub fn flush_and_cleanup(&mut self) {
-        match &self.mode {
-            WriteMode::ThisThread => 
-                BufferedWriter::flush_on_this_thread(self.buf_writer.as_ref().unwrap()),
-            WriteMode::SeparateThread => {
-                self.sender.as_ref().unwrap().send(MsgType::FlushAndStop).unwrap_or_default();
-                self.sender.take();
-                self.buf_writer.take();
-                self.thread_handler.take().unwrap().join().expect("Unable to join the Logger Tread.");
-            }
+    fn take(&self) -> Vec<String> {
+        if !self.pending.swap(false, Ordering::Acquire) {
+            return Vec::new();
         }
+        std::mem::take(&mut *self.notices.lock().unwrap_or_else(PoisonError::into_inner))
     }
+}
 
+///
+/// The bytes written around each record.
+/// 
+#[derive(Clone)]
+struct RecordFraming {
+    prefix: Vec<u8>,
+    suffix: Vec<u8>,
+    /// If false, the suffix of each record is only written before the next one, never after the last.
+    final_suffix: bool,
+    /// Set when the suffix of the last record is held back. Shared with the separate thread, if any.
+    suffix_pending: Arc<AtomicBool>,
+    /// Write each record as a binary frame instead, see with_binary_frames().
+    binary: bool,
+}
 
-    // ------------------------------------- Private ------------------------------- //
+impl Default for RecordFraming {
+    fn default() -> Self {
+        RecordFraming { prefix: Vec::new(), suffix: b"\n".to_vec(), final_suffix: true, suffix_pending: Arc::default(), binary: false }
+    }
+}
 
-    ///
-    /// Initializes the writers depending on the target.
-    /// This routine is common to Single and Multi Thread.
-    /// Can panic if the writers are initialized before.
-    /// 
-    fn init_writers(mut self) -> Result<BufferedWriter, String> {
+///
+/// Sink writing only whole blocks on the inner writer, keeping the remainder until the next write.
+/// The remainder is written (unaligned) only on flush or drop.
+/// 
+struct BlockAligned<W: Write> {
+    inner: W,
+    block_size: usize,
+    pending: Vec<u8>,
+}
 
-        // Check if data is not corrupted
-        if !self.buf_writer.is_none() {
-            panic!("The BufWriter should be None at this point");
-        }
+impl<W: Write> Write for BlockAligned<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
 
-        match self.target {
-            // Init for stdout
-            WriteTarget::StdOut => {
-                self.buf_writer = Some(Box::new(
-                    RwLock::new(
-                        BufWriter::with_capacity(self.buffer_capacity, std::io::stdout())
-                    )
-                ));
-                Ok(self)
-            }
-            // Init for file
-            WriteTarget::File => {
-                // Create the folder if it doesn't exists
-                if let Some(dir) = &self.file_path.parent() {
-                    if let Err(err) = fs::create_dir_all(dir) {
-                        return Err(format!("Error while creating directory for logging. Details: {}", err));
-                    }
-                }
+        let aligned = self.pending.len() - self.pending.len() % self.block_size;
+        if aligned > 0 {
+            self.inner.write_all(&self.pending[..aligned])?;
+            self.pending.drain(..aligned);
+        }
+        Ok(buf.len())
+    }
 
-                // Open the file
-                match fs::OpenOptions::new()
-                    .create(true).append(true)
-                    .open(&self.file_path) {
-                        Err(err) => {
-                            Err(format!("Error while opening log file. Details: {}", err))
-                        }
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.write_all(&self.pending)?;
+        self.pending.clear();
+        self.inner.flush()
+    }
+}
 
-                        // Ok, initialize bufwriter
-                        Ok(file_handler) => {
-                            self.buf_writer = Some(Box::new(
-                                RwLock::new(
-                                    BufWriter::with_capacity(self.buffer_capacity, file_handler)
-                                )
-                            ));
-                            Ok(self)
-                        }
-                    }
-            }
-        }
+impl<W: Write> Drop for BlockAligned<W> {
+    fn drop(&mut self) {
+        let _ = self.flush();
     }
+}
 
-    ///
-    /// Initializes the separate thread for writing in SeparateThread Mode
-    /// Can panic if the data structure is corrupted here
-    /// 
-    fn init_separate_thread(mut self) -> Result<BufferedWriter, String> {
-        // Check for data structure consistency
-        if ! self.thread_handler.is_none() {
-            panic!("Thread handler should be None at this point");
-        }
+///
+/// A target whose data can be synced on the disk.
+/// 
+trait SyncData: Write {
+    fn sync_data(&self) -> io::Result<()>;
+    fn sync_all(&self) -> io::Result<()>;
+}
 
-        if ! self.sender.is_none() {
-            panic!("Sender should be None at this point");
-        }
+impl SyncData for fs::File {
+    fn sync_data(&self) -> io::Result<()> {
+        fs::File::sync_data(self)
+    }
 
-        if self.buf_writer.is_none() {
-            panic!("BufWriter should be initialized at this point");
-        }
+    fn sync_all(&self) -> io::Result<()> {
+        fs::File::sync_all(self)
+    }
+}
 
-        let (sender, receiver) : (Sender<MsgType>, Receiver<MsgType>) = channel();
-        self.sender = Some(sender); 
+///
+/// Sink syncing the inner target at each flush, unless the policy is Never.
+/// 
+struct Synced<W: SyncData> {
+    inner: W,
+    policy: SyncPolicy,
+}
 
-        // Note that after the init, the bufwriter cannot be used anymore because it was moved to the other thread.
-        let buf_writer_to_move: Box<RwLock<BufWriter<dyn Write + Send + Sync>>> = self.buf_writer.take().unwrap();
-
-        match thread::Builder::new().spawn(move | | {
-            while let Ok(new_message) = receiver.recv() {
-                match new_message {
-                    MsgType::Msg(msg) => BufferedWriter::write_on_this_thread(&msg, &*buf_writer_to_move),
-                    MsgType::Flush => BufferedWriter::flush_on_this_thread(&*buf_writer_to_move),
-                    MsgType::FlushAndStop => {
-                        BufferedWriter::flush_on_this_thread(&*&buf_writer_to_move);
-                        break;
-                    }
-                }
-            }
-        }) {
-            Err(err) => return Err(format!("Unable to start Writer thread. Details {}", err)),
-            Ok(handler) => self.thread_handler = Some(handler),
+impl<W: SyncData> Write for Synced<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()?;
+        match self.policy {
+            SyncPolicy::Never => Ok(()),
+            SyncPolicy::OnFlush | SyncPolicy::EveryRecord => self.inner.sync_data(),
+            SyncPolicy::FullOnFlush => self.inner.sync_all(),
         }
-        
-        Ok(self)
     }
+}
 
+///
+/// What a writer does with its rotated files after each rotation: compress them, 
+/// and delete the ones beyond the limits.
+/// 
+#[derive(PartialEq, Clone, Copy, Debug, Default)]
+struct Backups {
+    /// The max number of rotated files, the newest ones.
+    max_backups: Option<usize>,
+    /// The max age of the rotated files, from their last write.
+    max_age: Option<Duration>,
+    /// Whether the rotated files are gzipped.
+    compress: bool,
+}
 
+impl Backups {
     ///
-    /// Writes on this thread using the buf_writer passed.
-    /// Used to avoid moving of self problem when initializing the separate thread.
-    /// # Panics
-    /// If the RWLock of the BufWriter is poisoned and cannot be taken for writing.
+    /// Compresses and prunes the files rotated from `path`. The compression runs on a helper thread,
+    /// so the writes go on meanwhile, and the pruning after it. The failures are pushed on `notices`.
     /// 
-    fn write_on_this_thread(message: &str, buf_writer: &RwLock<BufWriter<dyn Write + Send + Sync>>) {
-        if let Ok(mut writer_mut) = buf_writer.write() {
-            writer_mut.write(format!("{message}\n").as_bytes()).expect("Unable to write");
-        } else {
-            panic!("Cannot get writer as mutable. RWLock is poisoned!");
+    fn process(self, path: &Path, notices: &Arc<Notices>) {
+        if self == Backups::default() {
+            return;
+        }
+        if !self.compress {
+            return self.prune(path, notices);
+        }
+
+        let rotated_from = path.to_path_buf();
+        let notices_to_move = Arc::clone(notices);
+        if let Err(err) = thread::Builder::new().name("rslogger-gzip".to_string()).spawn(move || {
+            // One compression at a time, so two rotations never compress the same file.
+            let _compression = COMPRESSION.lock().unwrap_or_else(PoisonError::into_inner);
+            compress_backups(&rotated_from, &notices_to_move);
+            self.prune(&rotated_from, &notices_to_move);
+        }) {
+            notices.push(format!("unable to start the compression of the rotated files of {}: {err}", path.display()));
         }
     }
 
     ///
-    /// Flushes the buf_writer passed on this thread.
-    /// Used to avoid moving of self problems when initializing the separate thread.
-    /// # Panics 
-    /// If the RWLock of the BufWriter is poisoned and cannot be taken for writing.
+    /// Deletes the files rotated from `path` beyond the limits, the oldest periods first. 
+    /// Only the files named as rotated from `path` are considered, the other files of the directory are never touched.
+    /// The files that can't be listed or deleted are pushed on `notices`.
     /// 
-    fn flush_on_this_thread(buf_writer: &RwLock<BufWriter<dyn Write + Send + Sync>>) {
-        if let Ok(mut writer_mut) = buf_writer.write() {
-            writer_mut.flush().expect("Unable to flush");
-        } else {
-            panic!("Cannot get writer as mutable. RWLock is poisoned!");
+    fn prune(self, path: &Path, notices: &Notices) {
+        if self.max_backups.is_none() && self.max_age.is_none() {
+            return;
+        }
+
+        let backups = match list_backups(path) {
+            Ok(backups) => backups,
+            Err(err) => return notices.push(err.to_string()),
+        };
+        let now = SystemTime::now();
+        let mut kept = 0;
+        let mut previous = None;
+        for (period, index, backup) in backups {
+            // A file being compressed and its compressed copy count as one.
+            if previous.as_ref() != Some(&(period.clone(), index)) {
+                kept += 1;
+                previous = Some((period, index));
+            }
+            let too_many = self.max_backups.is_some_and(|max_backups| kept > max_backups);
+            let too_old = self.max_age.is_some_and(|max_age| {
+                fs::metadata(&backup).and_then(|metadata| metadata.modified())
+                    .is_ok_and(|modified| now.duration_since(modified).is_ok_and(|age| age > max_age))
+            });
+            if (too_many || too_old) && let Err(err) = fs::remove_file(&backup) {
+                notices.push(format!("unable to delete the rotated file {}: {err}", backup.display()));
+            }
         }
     }
 }
 
-impl Drop for BufferedWriter {
-    fn drop(&mut self) {
-        self.flush_and_cleanup();
-    }
-}
\ No newline at end of file
+/// Held while compressing rotated files.
+static COMPRESSION: Mutex<()> = Mutex::new(());
+
+///
+/// Returns the period, the index and the path of the files rotated from `path`, the newest first.
+/// In case of failure listing the directory, returns the error naming it.
+/// 
+fn list_backups(path: &Path) -> io::Result<Vec<(String, usize, PathBuf)>> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let entries = fs::read_dir(dir)
+        .map_err(|err| io::Error::new(err.kind(), format!("unable to list the rotated files in {}: {err}", dir.display())))?;
+
+    let mut backups: Vec<_> = entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let (period, index) = rotated_period(path, &entry.file_name().to_string_lossy())?;
+            Some((period, index, entry.path()))
+        })
+        .collect();
+    backups.sort_unstable_by(|a, b| (&b.0, b.1).cmp(&(&a.0, a.1)));
+    Ok(backups)
+}
+
+///
+/// Gzips the files rotated from `path` not compressed yet, including the ones left by a compression
+/// interrupted by the end of the process. The files that can't be compressed are pushed on `notices` and left as they are.
+/// 
+fn compress_backups(path: &Path, notices: &Notices) {
+    let backups = match list_backups(path) {
+        Ok(backups) => backups,
+        Err(err) => return notices.push(err.to_string()),
+    };
+    for (_, _, backup) in backups {
+        if backup.extension().is_some_and(|extension| extension == "gz") || with_suffix(&backup, ".gz").exists() {
+            continue;
+        }
+        if let Err(err) = gzip_file(&backup) {
+            notices.push(format!("unable to compress the rotated file {}: {err}", backup.display()));
+        }
+    }
+}
+
+///
+/// Replaces `file` with `file.gz`, keeping its modification time for the max age. 
+/// The compressed file is written aside and renamed when complete, so an interrupted compression leaves the original file.
+/// 
+fn gzip_file(file: &Path) -> io::Result<()> {
+    let metadata = fs::metadata(file)?;
+    let modified = metadata.modified()?;
+    let partial = with_suffix(file, ".gz.partial");
+    let compressed = File::create(&partial)?;
+    // As restricted as the original, which may hold sensitive data.
+    compressed.set_permissions(metadata.permissions())?;
+    crate::gzip::compress(File::open(file)?, &compressed)?;
+    compressed.set_modified(modified)?;
+    // On the disk before the rename, so a crash never leaves a truncated `.gz` without the original.
+    compressed.sync_all()?;
+    drop(compressed);
+
+    fs::rename(&partial, with_suffix(file, ".gz"))?;
+    fs::remove_file(file)
+}
+
+///
+/// Creates `dir` and its missing parents, setting the permissions of the ones created to `mode` if any.
+/// The mode is set after the creation, so the umask doesn't apply to it.
+/// 
+fn create_dirs(dir: &Path, mode: Option<u32>) -> io::Result<()> {
+    let created: Vec<&Path> = dir.ancestors()
+        .take_while(|ancestor| !ancestor.as_os_str().is_empty() && !ancestor.exists())
+        .collect();
+    fs::create_dir_all(dir)?;
+
+    #[cfg(unix)]
+    if let Some(mode) = mode {
+        use std::os::unix::fs::PermissionsExt;
+        // From the outermost, so a mode without the search permission doesn't block the next ones.
+        for created in created.iter().rev() {
+            fs::set_permissions(created, fs::Permissions::from_mode(mode))?;
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = (created, mode);
+    Ok(())
+}
+
+///
+/// Points the symlink `link` at the file `path`, replacing it atomically: the new link is created
+/// under a temporary name and renamed over the old one, so a reader never finds it missing.
+/// The link is relative if it's in the directory of the file, so the directory can be moved.
+/// No-op on non-Unix platforms.
+/// 
+fn update_link(link: &Path, path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        let target = match path.file_name() {
+            Some(name) if link.parent() == path.parent() => PathBuf::from(name),
+            _ => std::path::absolute(path)?,
+        };
+        let temp = with_suffix(link, ".tmp");
+        match fs::remove_file(&temp) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+            _ => {}
+        }
+        std::os::unix::fs::symlink(target, &temp)?;
+        fs::rename(&temp, link)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (link, path);
+        Ok(())
+    }
+}
+
+///
+/// Returns the options opening a log file with `mode`, creating it with the permissions `file_mode` if any
+/// (minus the umask, Unix only).
+/// 
+fn open_options(mode: OpenMode, file_mode: Option<u32>) -> fs::OpenOptions {
+    let mut options = fs::OpenOptions::new();
+    match mode {
+        OpenMode::Append => options.create(true).append(true),
+        OpenMode::Truncate => options.create(true).write(true).truncate(true),
+    };
+    #[cfg(unix)]
+    if let Some(file_mode) = file_mode {
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, file_mode);
+    }
+    #[cfg(not(unix))]
+    let _ = file_mode;
+    options
+}
+
+///
+/// Returns the name of the host, `unknown` if it can't be determined.
+/// 
+fn hostname() -> String {
+    #[cfg(unix)]
+    {
+        let mut name = [0u8; 256];
+        if unsafe { libc::gethostname(name.as_mut_ptr().cast(), name.len()) } == 0 {
+            let len = name.iter().position(|byte| *byte == 0).unwrap_or(name.len());
+            return String::from_utf8_lossy(&name[..len]).into_owned();
+        }
+    }
+    std::env::var("COMPUTERNAME").or_else(|_| std::env::var("HOSTNAME")).unwrap_or_else(|_| "unknown".to_string())
+}
+
+///
+/// Expands a file path: a leading `~` to the home directory, the environment variables `$VAR` and `${VAR}`, 
+/// and the placeholders `{pid}`, `{hostname}`, `{date}` (`2024-06-03`) and `{time}` (`14-00-00`), in local time. 
+/// `$$`, `{{` and `}}` are literal characters. Paths which are not UTF-8 are kept as they are.
+/// Returns an error naming the placeholder or the variable if it's unknown, unset or not closed.
+/// 
+fn expand_path(path: &Path) -> Result<PathBuf, String> {
+    let Some(template) = path.to_str() else {
+        return Ok(path.to_path_buf());
+    };
+    let now = local_time(SystemTime::now());
+    let variable = |name: &str| std::env::var(name).map_err(|_| format!("the environment variable `{name}` of `{template}` is not set"));
+    let mut expanded = String::with_capacity(template.len());
+    let mut rest = template;
+
+    if let Some(after) = rest.strip_prefix('~') && (after.is_empty() || after.starts_with(['/', '\\'])) {
+        let home = if cfg!(windows) { "USERPROFILE" } else { "HOME" };
+        expanded.push_str(&variable(home)?);
+        rest = after;
+    }
+
+    while let Some(special) = rest.find(['{', '}', '$']) {
+        expanded.push_str(&rest[..special]);
+        rest = &rest[special..];
+        if let Some(after) = ["{{", "}}", "$$"].iter().find_map(|escape| rest.strip_prefix(escape)) {
+            expanded.push_str(&rest[..1]);
+            rest = after;
+            continue;
+        }
+
+        if let Some(after) = rest.strip_prefix('$') {
+            let (name, after) = match after.strip_prefix('{') {
+                Some(braced) => braced.split_once('}').ok_or_else(|| format!("unmatched brace in `{template}`"))?,
+                None => after.split_at(after.find(|c: char| !c.is_alphanumeric() && c != '_').unwrap_or(after.len())),
+            };
+            if name.is_empty() {
+                return Err(format!("missing variable name after `$` in `{template}`, write `$$` for a literal `$`"));
+            }
+            expanded.push_str(&variable(name)?);
+            rest = after;
+            continue;
+        }
+
+        let Some(end) = rest.find('}').filter(|_| rest.starts_with('{')) else {
+            return Err(format!("unmatched brace in `{template}`, write `{{{{` and `}}}}` for literal braces"));
+        };
+        match &rest[..=end] {
+            "{pid}" => expanded.push_str(&std::process::id().to_string()),
+            "{hostname}" => expanded.push_str(&hostname()),
+            "{date}" => expanded.push_str(&now.format(DAY_FORMAT).unwrap()),
+            "{time}" => expanded.push_str(&now.format(TIME_FORMAT).unwrap()),
+            unknown => return Err(format!("unknown placeholder `{unknown}` in `{template}`, expected {{pid}}, {{hostname}}, {{date}} or {{time}}")),
+        }
+        rest = &rest[end + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(PathBuf::from(expanded))
+}
+
+///
+/// Takes the advisory lock of `file` (`flock` on Unix, `LockFileEx` on Windows), failing if another process holds it.
+/// The lock is released when the file is closed.
+/// 
+fn lock_exclusive(file: &File, path: &Path) -> io::Result<()> {
+    match file.try_lock() {
+        Ok(()) => Ok(()),
+        Err(fs::TryLockError::WouldBlock) => Err(io::Error::new(
+            io::ErrorKind::WouldBlock, 
+            format!("{} is in use by another process (pid unknown)", path.display()),
+        )),
+        Err(fs::TryLockError::Error(err)) => Err(err),
+    }
+}
+
+///
+/// Opens `path` with `options`, retrying up to `retries` times on failure: the first retry after `delay`,
+/// each next one after twice the previous delay. Returns the last error if all the attempts failed.
+/// 
+fn open_with_retries(path: &Path, options: &fs::OpenOptions, retries: u32, delay: Duration) -> io::Result<File> {
+    let mut delay = delay;
+    let mut attempt = 0;
+    loop {
+        match options.open(path) {
+            Err(_) if attempt < retries => {
+                std::thread::sleep(delay);
+                delay = delay.saturating_mul(2);
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+///
+/// Encodes `value` as an unsigned LEB128 varint: 7 bits per byte from the lowest, the high bit set on all but the last byte.
+/// 
+fn varint(mut value: usize) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+    bytes
+}
+
+///
+/// Returns `path` with `suffix` appended to its file name.
+/// 
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+///
+/// Returns the label (period or time) and the index of `name` if it's the name of a file rotated 
+/// from `path` (see rotated_path()), with either naming, compressed or not, None otherwise.
+/// 
+fn rotated_period(path: &Path, name: &str) -> Option<(String, usize)> {
+    let stem = path.file_stem()?.to_string_lossy();
+    let extension = path.extension().map(|extension| format!(".{}", extension.to_string_lossy())).unwrap_or_default();
+    let name = name.strip_suffix(".gz").unwrap_or(name);
+    let rotated = name.strip_prefix(&format!("{stem}."))?.strip_suffix(&extension)?;
+
+    let (period, index) = match rotated.split_once('.') {
+        Some((period, index)) if !index.is_empty() && index.bytes().all(|byte| byte.is_ascii_digit()) => (period, index.parse().ok()?),
+        Some(_) => return None,
+        None => (rotated, 0),
+    };
+    // `2024-06-03`, `2024-06-03-14` or `2024-06-03T14-00-00`
+    let is_period = match period.len() {
+        10 | 13 => period.bytes().enumerate()
+            .all(|(position, byte)| if matches!(position, 4 | 7 | 10) { byte == b'-' } else { byte.is_ascii_digit() }),
+        19 => period.bytes().enumerate().all(|(position, byte)| match position {
+            4 | 7 | 13 | 16 => byte == b'-',
+            10 => byte == b'T',
+            _ => byte.is_ascii_digit(),
+        }),
+        _ => false,
+    };
+    is_period.then(|| (period.to_string(), index))
+}
+
+///
+/// When the period of a rotating file ends, shared by the file with the sink buffering it.
+/// 
+#[derive(Default)]
+struct PeriodEnd {
+    /// When the next period starts, in nanoseconds since the epoch.
+    next_period: AtomicU64,
+    /// Set by the sink once the records of the ended period are on the file, so it rotates before the next write.
+    reached: AtomicBool,
+}
+
+impl PeriodEnd {
+    fn set(&self, next_period: SystemTime) {
+        let nanos = next_period.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_nanos();
+        self.next_period.store(u64::try_from(nanos).unwrap_or(u64::MAX), Ordering::Relaxed);
+    }
+
+    ///
+    /// Returns true if the next period started.
+    /// 
+    fn passed(&self) -> bool {
+        let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_nanos();
+        now >= u128::from(self.next_period.load(Ordering::Relaxed))
+    }
+}
+
+///
+/// File target starting a new file when the period of the rotation policy changes, 
+/// after renaming the closed one after its period. The sink buffering it tells it where the period ends 
+/// in the records (see [`PeriodEnd`]), a buffer holding the end of a period and the start of the next.
+/// 
+struct Rotating {
+    path: PathBuf,
+    policy: RotationPolicy,
+    naming: RotatedNaming,
+    backups: Backups,
+    file: Synced<File>,
+    /// The period of the records in the file.
+    period: String,
+    /// When the next period starts: until then, the clock is only compared with it.
+    next_period: SystemTime,
+    /// The end of the period, shared with the sink.
+    period_end: Arc<PeriodEnd>,
+    /// The header written at the start of each new file, with its framing.
+    header: Vec<u8>,
+    /// The symlink pointed at each new file, if any.
+    latest_link: Option<PathBuf>,
+    /// The permissions of each new file, None for the default ones.
+    file_mode: Option<u32>,
+    /// Take the advisory lock of each new file.
+    exclusive: bool,
+    /// Where the failures processing the backups are pushed.
+    notices: Arc<Notices>,
+}
+
+impl Rotating {
+    fn new(path: PathBuf, policy: RotationPolicy, naming: RotatedNaming, backups: Backups, file: Synced<File>, header: Vec<u8>) -> Rotating {
+        let (period, next_period) = policy.period(SystemTime::now());
+        let period_end = Arc::new(PeriodEnd::default());
+        period_end.set(next_period);
+        Rotating { path, policy, naming, backups, file, period, next_period, period_end, header, latest_link: None, file_mode: None, exclusive: false,
+            notices: Arc::default() }
+    }
+
+    fn with_notices(mut self, notices: Arc<Notices>) -> Rotating {
+        self.notices = notices;
+        self
+    }
+
+    fn with_exclusive_lock(mut self, exclusive: bool) -> Rotating {
+        self.exclusive = exclusive;
+        self
+    }
+
+    fn with_file_mode(mut self, file_mode: Option<u32>) -> Rotating {
+        self.file_mode = file_mode;
+        self
+    }
+
+    fn with_latest_link(mut self, latest_link: Option<PathBuf>) -> Rotating {
+        self.latest_link = latest_link;
+        self
+    }
+
+    ///
+    /// Closes the file and starts a new one if a new period started at `now`, the time the file is closed.
+    /// A clock going backwards doesn't rotate: a period is never reopened.
+    /// 
+    fn rotate_if_needed(&mut self, now: SystemTime) -> io::Result<()> {
+        if now < self.next_period {
+            return Ok(());
+        }
+        let (period, next_period) = self.policy.period(now);
+        self.next_period = next_period;
+        self.period_end.set(next_period);
+        if period <= self.period {
+            return Ok(());
+        }
+
+        self.file.flush()?;
+        let rotated = rotated_path(&self.path, &self.naming.label(&self.period, now));
+        let file = if self.exclusive {
+            // Locked before the closed file is renamed: on failure, the records go on in the closed 
+            // file, still locked and under its name.
+            let next = with_suffix(&self.path, ".next");
+            let file = open_options(OpenMode::Truncate, self.file_mode).open(&next)?;
+            if let Err(err) = lock_exclusive(&file, &self.path).and_then(|()| fs::rename(&self.path, &rotated)) {
+                let _ = fs::remove_file(&next);
+                return Err(err);
+            }
+            if let Err(err) = fs::rename(&next, &self.path) {
+                let _ = fs::remove_file(&next);
+                fs::rename(&rotated, &self.path)?;
+                return Err(err);
+            }
+            file
+        } else {
+            fs::rename(&self.path, &rotated)?;
+            open_options(OpenMode::Append, self.file_mode).open(&self.path)?
+        };
+        self.file.inner = file;
+        self.period = period;
+        if let Some(link) = &self.latest_link
+            && let Err(err) = update_link(link, &self.path) {
+            eprintln!("Unable to update the link {}: {err}", link.display());
+        }
+        self.backups.process(&self.path, &self.notices);
+        self.file.write_all(&self.header)
+    }
+}
+
+impl Write for Rotating {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // The records are kept rather than lost: they go on in the current file.
+        if self.period_end.reached.swap(false, Ordering::Relaxed)
+            && let Err(err) = self.rotate_if_needed(SystemTime::now()) {
+            eprintln!("Unable to rotate {}: {err}", self.path.display());
+        }
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+///
+/// Returns the path of the file closed with `label`: `app.log` becomes `app.2024-06-03.log`,
+/// or `app.2024-06-03.1.log` and so on if it already exists, compressed or not.
+/// 
+fn rotated_path(path: &Path, label: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path.extension().map(|extension| format!(".{}", extension.to_string_lossy())).unwrap_or_default();
+    let mut rotated = path.with_file_name(format!("{stem}.{label}{extension}"));
+    let mut index = 1;
+    while rotated.exists() || with_suffix(&rotated, ".gz").exists() {
+        rotated = path.with_file_name(format!("{stem}.{label}.{index}{extension}"));
+        index += 1;
+    }
+    rotated
+}
+
+///
+/// Renames the file left by a previous run if it was last written in a previous period, 
+/// so the records of the current period start a new file. With the Timestamp naming, the file is named
+/// after its last write, when it would have been closed if the process had gone on.
+/// 
+fn rotate_stale(path: &Path, policy: RotationPolicy, naming: RotatedNaming) -> io::Result<()> {
+    let metadata = match fs::metadata(path) {
+        Ok(metadata) if metadata.len() > 0 => metadata,
+        _ => return Ok(()),
+    };
+    let modified = metadata.modified()?;
+    let (period, _) = policy.period(modified);
+    if period < policy.period(SystemTime::now()).0 {
+        fs::rename(path, rotated_path(path, &naming.label(&period, modified)))?;
+    }
+    Ok(())
+}
+
+///
+/// Where a writer puts the records: a buffer, or the target itself when the capacity is 0.
+/// 
+struct Sink {
+    buffer: SinkBuffer,
+    /// The end of the period of a rotating file target: the records of the ended period are flushed
+    /// before the first record of the next one is buffered, so each file holds exactly its period.
+    period_end: Option<Arc<PeriodEnd>>,
+}
+
+///
+/// How a sink buffers the records.
+/// 
+enum SinkBuffer {
+    Buffered(BufWriter<Box<dyn Write + Send + Sync>>),
+    /// As Buffered, flushed at the end of every `every` records.
+    EveryN {
+        buffer: BufWriter<Box<dyn Write + Send + Sync>>,
+        every: usize,
+        /// Records ended since the last flush.
+        unflushed: usize,
+    },
+    /// The record being written, sent to the target with a single write and flushed at its end.
+    Unbuffered {
+        target: Box<dyn Write + Send + Sync>,
+        record: Vec<u8>,
+    },
+}
+
+impl Sink {
+    fn new(capacity: usize, policy: FlushPolicy, target: impl Write + Send + Sync + 'static) -> Sink {
+        let target: Box<dyn Write + Send + Sync> = Box::new(target);
+        let every = match policy {
+            _ if capacity == 0 => return Sink::from(SinkBuffer::Unbuffered { target, record: Vec::new() }),
+            FlushPolicy::Buffered => return Sink::from(SinkBuffer::Buffered(BufWriter::with_capacity(capacity, target))),
+            FlushPolicy::EveryRecord => 1,
+            FlushPolicy::EveryN(every) => every.max(1),
+        };
+        Sink::from(SinkBuffer::EveryN { buffer: BufWriter::with_capacity(capacity, target), every, unflushed: 0 })
+    }
+
+    fn with_period_end(mut self, period_end: Option<Arc<PeriodEnd>>) -> Sink {
+        self.period_end = period_end;
+        self
+    }
+
+    ///
+    /// Returns the bytes written in the sink and not yet on the target.
+    /// 
+    fn buffered_len(&self) -> usize {
+        match &self.buffer {
+            SinkBuffer::Buffered(buffer) | SinkBuffer::EveryN { buffer, .. } => buffer.buffer().len(),
+            SinkBuffer::Unbuffered { record, .. } => record.len(),
+        }
+    }
+
+    ///
+    /// Marks the end of a record: an unbuffered sink writes it on the target and flushes it, 
+    /// a sink flushed every N records counts it.
+    /// 
+    fn end_record(&mut self) -> io::Result<()> {
+        match &mut self.buffer {
+            SinkBuffer::Buffered(_) => Ok(()),
+            SinkBuffer::EveryN { every, unflushed, .. } => {
+                *unflushed += 1;
+                if unflushed < every { Ok(()) } else { self.flush() }
+            }
+            SinkBuffer::Unbuffered { .. } => self.flush(),
+        }
+    }
+
+    ///
+    /// Writes a whole record made of `parts` with a single write: copied at once into the buffer, which is flushed first
+    /// if the record doesn't fit in what's left of it, or written at once on the target if it's larger than the buffer.
+    /// A record is never split between two writes on the target, then ends the record.
+    /// 
+    fn write_record(&mut self, parts: [&[u8]; 3]) -> io::Result<()> {
+        if let Some(period_end) = &self.period_end
+            && period_end.passed() {
+            // The target rotates before the next write, which starts with this record.
+            let period_end = Arc::clone(period_end);
+            if self.buffered_len() > 0 {
+                self.flush()?;
+            }
+            period_end.reached.store(true, Ordering::Relaxed);
+        }
+
+        let length: usize = parts.iter().map(|part| part.len()).sum();
+        match &mut self.buffer {
+            SinkBuffer::Buffered(buffer) | SinkBuffer::EveryN { buffer, .. } => {
+                if buffer.capacity() - buffer.buffer().len() < length && !buffer.buffer().is_empty() {
+                    buffer.flush()?;
+                }
+                if length < buffer.capacity() {
+                    // Fits in the buffer, so copied whole: the remainder is only there to honor the Write contract.
+                    let written = buffer.write_vectored(&parts.map(IoSlice::new))?;
+                    if written < length {
+                        buffer.write_all(&parts.concat()[written..])?;
+                    }
+                } else {
+                    buffer.get_mut().write_all(&parts.concat())?;
+                }
+            }
+            SinkBuffer::Unbuffered { record, .. } => parts.iter().for_each(|part| record.extend_from_slice(part)),
+        }
+        self.end_record()
+    }
+}
+
+impl From<SinkBuffer> for Sink {
+    fn from(buffer: SinkBuffer) -> Sink {
+        Sink { buffer, period_end: None }
+    }
+}
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.buffer {
+            SinkBuffer::Buffered(buffer) | SinkBuffer::EveryN { buffer, .. } => buffer.write(buf),
+            SinkBuffer::Unbuffered { record, .. } => {
+                record.extend_from_slice(buf);
+                Ok(buf.len())
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.buffer {
+            SinkBuffer::Buffered(buffer) => buffer.flush(),
+            SinkBuffer::EveryN { buffer, unflushed, .. } => {
+                *unflushed = 0;
+                buffer.flush()
+            }
+            SinkBuffer::Unbuffered { target, record } => {
+                // Cleared even on error, so a failing target doesn't accumulate the records.
+                let result = target.write_all(record);
+                record.clear();
+                result.and_then(|()| target.flush())
+            }
+        }
+    }
+}
+
+///
+/// Stdout writing each buffer whole under the stdout lock, and flushing it before releasing the lock,
+/// so the prints of the other threads can't land in the middle of a record.
+/// 
+struct LockedStdout;
+
+impl Write for LockedStdout {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_all(buf).map(|()| buf.len())
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(buf)?;
+        stdout.flush()
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stdout().flush()
+    }
+}
+
+enum WriteMode {
+    ThisThread,
+    SeparateThread,
+    /// As SeparateThread, on a task of the current tokio runtime writing through a tokio file.
+    #[cfg(feature = "async-tokio")]
+    TokioTask,
+    /// As SeparateThread, on a thread shared with other writers.
+    Shared,
+}
+
+///
+/// The sending side of the queue to the separate thread, bounded or not.
+/// 
+enum QueueSender {
+    Unbounded(Sender<MsgType>),
+    Bounded(SyncSender<MsgType>),
+    /// The unbounded queue of a shared thread, with the slot of the writer.
+    Shared(Sender<(usize, MsgType)>, usize),
+    /// The queue of a tokio task, bounded or not.
+    #[cfg(feature = "async-tokio")]
+    Tokio(tokio::sync::mpsc::Sender<MsgType>),
+    #[cfg(feature = "async-tokio")]
+    TokioUnbounded(tokio::sync::mpsc::UnboundedSender<MsgType>),
+}
+
+impl QueueSender {
+    ///
+    /// Creates the queue, bounded to `capacity` messages if Some.
+    /// 
+    fn new(capacity: Option<usize>) -> (QueueSender, Receiver<MsgType>) {
+        match capacity {
+            Some(capacity) => {
+                let (sender, receiver) = sync_channel(capacity);
+                (QueueSender::Bounded(sender), receiver)
+            }
+            None => {
+                let (sender, receiver) = channel();
+                (QueueSender::Unbounded(sender), receiver)
+            }
+        }
+    }
+
+    ///
+    /// Sends the message, waiting for room if the queue is bounded and full.
+    /// 
+    fn send(&self, message: MsgType) -> Result<(), SendError<MsgType>> {
+        match self {
+            QueueSender::Unbounded(sender) => sender.send(message),
+            QueueSender::Bounded(sender) => sender.send(message),
+            QueueSender::Shared(sender, slot) => sender.send((*slot, message)).map_err(|SendError((_, message))| SendError(message)),
+            // Never blocks on the channel, which would panic on a thread of the runtime: retries until there's room.
+            #[cfg(feature = "async-tokio")]
+            QueueSender::Tokio(sender) => {
+                let mut message = message;
+                loop {
+                    match sender.try_send(message) {
+                        Ok(()) => return Ok(()),
+                        Err(tokio::sync::mpsc::error::TrySendError::Closed(message)) => return Err(SendError(message)),
+                        Err(tokio::sync::mpsc::error::TrySendError::Full(full)) => {
+                            message = full;
+                            block_in_runtime(|| thread::sleep(Duration::from_millis(1)));
+                        }
+                    }
+                }
+            }
+            #[cfg(feature = "async-tokio")]
+            QueueSender::TokioUnbounded(sender) => sender.send(message).map_err(|error| SendError(error.0)),
+        }
+    }
+
+    ///
+    /// Sends the message, giving it back if the queue is bounded and full.
+    /// 
+    fn try_send(&self, message: MsgType) -> Result<(), TrySendError<MsgType>> {
+        match self {
+            QueueSender::Unbounded(sender) => sender.send(message).map_err(|SendError(message)| TrySendError::Disconnected(message)),
+            QueueSender::Bounded(sender) => sender.try_send(message),
+            QueueSender::Shared(..) => self.send(message).map_err(|SendError(message)| TrySendError::Disconnected(message)),
+            #[cfg(feature = "async-tokio")]
+            QueueSender::Tokio(sender) => sender.try_send(message).map_err(|error| match error {
+                tokio::sync::mpsc::error::TrySendError::Full(message) => TrySendError::Full(message),
+                tokio::sync::mpsc::error::TrySendError::Closed(message) => TrySendError::Disconnected(message),
+            }),
+            #[cfg(feature = "async-tokio")]
+            QueueSender::TokioUnbounded(..) => self.send(message).map_err(|SendError(message)| TrySendError::Disconnected(message)),
+        }
+    }
+}
+
+///
+/// The receiving side of the queue of a tokio task, bounded or not.
+/// 
+#[cfg(feature = "async-tokio")]
+enum TokioReceiver {
+    Bounded(tokio::sync::mpsc::Receiver<MsgType>),
+    Unbounded(tokio::sync::mpsc::UnboundedReceiver<MsgType>),
+}
+
+#[cfg(feature = "async-tokio")]
+impl TokioReceiver {
+    ///
+    /// Creates the queue of a tokio task, bounded to `capacity` messages if Some.
+    /// 
+    fn channel(capacity: Option<usize>) -> (QueueSender, TokioReceiver) {
+        match capacity {
+            Some(capacity) => {
+                let (sender, receiver) = tokio::sync::mpsc::channel(capacity.max(1));
+                (QueueSender::Tokio(sender), TokioReceiver::Bounded(receiver))
+            }
+            None => {
+                let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+                (QueueSender::TokioUnbounded(sender), TokioReceiver::Unbounded(receiver))
+            }
+        }
+    }
+
+    ///
+    /// Waits for the next message, None once all the senders are dropped.
+    /// 
+    async fn recv(&mut self) -> Option<MsgType> {
+        match self {
+            TokioReceiver::Bounded(receiver) => receiver.recv().await,
+            TokioReceiver::Unbounded(receiver) => receiver.recv().await,
+        }
+    }
+
+    ///
+    /// Returns the next message if one is queued.
+    /// 
+    fn try_recv(&mut self) -> Option<MsgType> {
+        match self {
+            TokioReceiver::Bounded(receiver) => receiver.try_recv().ok(),
+            TokioReceiver::Unbounded(receiver) => receiver.try_recv().ok(),
+        }
+    }
+}
+
+///
+/// Runs `f`, which blocks, handing the other tasks of the current worker of a multi-thread tokio runtime 
+/// to another thread meanwhile, so the writer tasks keep running.
+/// 
+#[cfg(feature = "async-tokio")]
+fn block_in_runtime<R>(f: impl FnOnce() -> R) -> R {
+    match tokio::runtime::Handle::try_current() {
+        Ok(runtime) if runtime.runtime_flavor() == tokio::runtime::RuntimeFlavor::MultiThread => tokio::task::block_in_place(f),
+        _ => f(),
+    }
+}
+
+enum MsgType {
+    Msg(Arc<str>),
+    /// A record to lay out on the separate thread.
+    Deferred(Arc<DeferredRecord>),
+    Flush,
+    FlushAndStop,
+}
+
+pub struct BufferedWriter {
+
+    ///
+    /// The target to write to
+    /// 
+    target: WriteTarget,
+
+    ///
+    /// Whether to write on the caller thread or on a separate thread
+    /// 
+    mode: WriteMode,
+
+    ///
+    /// The file path to write to.
+    /// Only meaningful if writing on a file 
+    /// 
+    file_path: PathBuf,
+
+    ///
+    /// The handle receiving the lines.
+    /// Only meaningful if writing on a capture
+    /// 
+    capture: Option<CaptureHandle>,
+
+    ///
+    /// The target given by the user, moved into the BufWriter at init.
+    /// Only meaningful if writing on a custom target
+    /// 
+    custom: Option<Box<dyn Write + Send + Sync>>,
+
+    ///
+    /// The file opened by the user (e.g. an inherited file descriptor), moved into the BufWriter at init.
+    /// Only meaningful if writing on an open file
+    /// 
+    open_file: Option<File>,
+
+    ///
+    /// Whether the parent directories of the file are created at init.
+    /// Only meaningful if writing on a file
+    /// 
+    create_dirs: bool,
+
+    ///
+    /// The permissions set on the directories created at init, whatever the umask (Unix only).
+    /// 
+    dir_mode: Option<u32>,
+
+    ///
+    /// A symlink kept pointing at the file, across the rotations (Unix only).
+    /// Only meaningful if writing on a file
+    /// 
+    latest_link: Option<PathBuf>,
+
+    ///
+    /// A line written at init, before any record, if the file is empty.
+    /// Only meaningful if writing on a file
+    /// 
+    header: Option<String>,
+
+    ///
+    /// If set, the file is written in whole blocks of this size, except on explicit flushes.
+    /// Only meaningful if writing on a file
+    /// 
+    block_size: Option<usize>,
+
+    ///
+    /// When the file is synced on the disk.
+    /// Only meaningful if writing on a file
+    /// 
+    sync: SyncPolicy,
+
+    ///
+    /// How many times opening the file is retried at init, and the delay before the first retry.
+    /// Only meaningful if writing on a file
+    /// 
+    open_retries: (u32, Duration),
+
+    ///
+    /// Whether the content of an existing file is kept or discarded at init.
+    /// Only meaningful if writing on a file
+    /// 
+    open_mode: OpenMode,
+
+    ///
+    /// Write each record whole under the stdout lock, see with_stdout_lock_per_line().
+    /// Only meaningful if writing on stdout
+    /// 
+    stdout_lock_per_line: bool,
+
+    ///
+    /// Take the advisory lock of the file, so that another process can't write on it.
+    /// Only meaningful if writing on a file
+    /// 
+    exclusive: bool,
+
+    ///
+    /// The permissions of the files created, the default ones if None (Unix only).
+    /// Only meaningful if writing on a file
+    /// 
+    file_mode: Option<u32>,
+
+    ///
+    /// When the file is closed and a new one started.
+    /// Only meaningful if writing on a file
+    /// 
+    rotation: RotationPolicy,
+
+    ///
+    /// How the rotated files are named, only meaningful if the file is rotated.
+    /// 
+    rotated_naming: RotatedNaming,
+
+    ///
+    /// What is done with the rotated files, only meaningful if the file is rotated.
+    /// 
+    backups: Backups,
+
+    ///
+    /// The capacity of the buffer.
+    /// If set to 0, it will write and flush on the target record by record, without a buffer. 
+    /// Otherwise it will buffer and then flush.
+    /// 
+    buffer_capacity: usize,
+
+    ///
+    /// If set, the buffer is flushed when no record arrives for this long after a write.
+    /// Only meaningful if the mode is SeparateThread.
+    /// 
+    idle_flush: Option<Duration>,
+
+    ///
+    /// When the buffer is flushed, None for the default of the target.
+    /// 
+    flush_policy: Option<FlushPolicy>,
+
+    ///
+    /// The flush policy for each level, indexed by `Level as usize - 1`.
+    /// 
+    level_flush: [LevelFlush; 5],
+
+    ///
+    /// Whether the lines written on this writer are colored.
+    /// If true, a final color reset is written at cleanup.
+    /// 
+    colors: bool,
+
+    ///
+    /// Whether the Error records written on this writer are drawn in a box.
+    /// 
+    boxed_errors: bool,
+
+    ///
+    /// The bytes written before and after each record. 
+    /// By default no prefix and a `\n` suffix.
+    /// 
+    framing: RecordFraming,
+
+    ///
+    /// The BufWriters on the target Stdout.
+    /// - Option because it's only initialized at init()
+    /// - Box so I can use the dynamic features
+    /// - RwLock because we need async interior mutability (It's needed for integration with log crate)
+    /// 
+    buf_writer: Option<Box<RwLock<Sink>>>,
+
+    ///
+    /// Set when the reader of the target went away (e.g. stdout piped into `head`).
+    /// From then on, everything written on this writer is discarded.
+    /// Shared with the separate thread, if any.
+    /// 
+    io_state: Arc<IoState>,
+
+    ///
+    /// The handler of the separate thread, 
+    /// only meaningful if the mode is SeparateThread.
+    /// 
+    thread_handler: Option<JoinHandle<()>>,
+
+    ///
+    /// The sender to send messages on the separate thread. 
+    /// only meaningful if the mode is SeparateThread.
+    /// 
+    sender: Option<QueueSender>,
+
+    ///
+    /// The max number of messages queued for the separate thread, None for unbounded.
+    /// 
+    queue_capacity: Option<usize>,
+
+    ///
+    /// What happens to a record when the bounded queue is full.
+    /// 
+    overflow_policy: OverflowPolicy,
+
+    ///
+    /// The records waiting for room in the queue, only used with DropOldest.
+    /// 
+    overflow: Mutex<VecDeque<MsgType>>,
+
+    ///
+    /// The records queued, written and dropped. Shared with the separate thread, if any.
+    /// 
+    counters: Arc<Counters>,
+
+    ///
+    /// The high and low watermarks of the queue, if reported.
+    /// 
+    watermarks: Option<(usize, usize)>,
+
+    ///
+    /// Set when the queue reached the high watermark, until it goes back down to the low one.
+    /// 
+    above_watermark: AtomicBool,
+
+    ///
+    /// Set when the separate thread is found dead (it panicked), so the warning is reported once.
+    /// 
+    thread_dead: AtomicBool,
+
+    ///
+    /// The options ignored at init because they don't apply to the target, see init_warnings().
+    /// 
+    init_warnings: Vec<String>,
+
+    ///
+    /// The failures away from the records, see take_notices(). Shared with the threads processing the rotated files.
+    /// 
+    notices: Arc<Notices>,
+
+    ///
+    /// The name of the separate thread, instead of one derived from the target.
+    /// 
+    thread_name: Option<String>,
+
+    ///
+    /// Whether the separate thread lowers its scheduling priority.
+    /// 
+    background_priority: bool,
+
+    ///
+    /// The thread to register on at init, only meaningful if the mode is SharedThread.
+    /// 
+    shared_thread: Option<Arc<SharedThread>>,
+
+    ///
+    /// Signaled when the tokio task or the slot on the shared thread stops cleanly, 
+    /// disconnected if it panics. Only meaningful if the mode is TokioTask or SharedThread.
+    /// 
+    /// In a Mutex as the receiver is not Sync.
+    task_done: Option<Mutex<Receiver<()>>>,
+
+    ///
+    /// The file opened at init, with true if it was empty, handed to the tokio task. Only meaningful if the mode is TokioTask.
+    /// 
+    #[cfg(feature = "async-tokio")]
+    async_file: Option<(File, bool)>,
+}
+
+
+impl BufferedWriter {
+
+    ///
+    /// Initializes the Writer with default mode as ThisThread.
+    /// 
+    pub fn new() -> BufferedWriter {
+        BufferedWriter { 
+            target: WriteTarget::StdOut,
+            mode: WriteMode::ThisThread, 
+            file_path: PathBuf::default(), 
+            capture: None,
+            custom: None,
+            open_file: None,
+            create_dirs: true,
+            dir_mode: None,
+            latest_link: None,
+            header: None,
+            block_size: None,
+            sync: SyncPolicy::Never,
+            open_retries: (0, Duration::ZERO),
+            open_mode: OpenMode::Append,
+            stdout_lock_per_line: false,
+            exclusive: false,
+            file_mode: None,
+            rotation: RotationPolicy::Never,
+            rotated_naming: RotatedNaming::Period,
+            backups: Backups::default(),
+            buffer_capacity: DEFAULT_BUFFER_CAPACITY, 
+            idle_flush: None,
+            flush_policy: None,
+            level_flush: [LevelFlush::Buffered; 5],
+            colors: false,
+            boxed_errors: false,
+            framing: RecordFraming::default(),
+            buf_writer: None,
+            io_state: Arc::default(),
+            thread_handler: None, 
+            thread_name: None,
+            background_priority: false,
+            shared_thread: None,
+            task_done: None,
+            #[cfg(feature = "async-tokio")]
+            async_file: None,
+            sender: None,
+            queue_capacity: None,
+            overflow_policy: OverflowPolicy::Block,
+            overflow: Mutex::new(VecDeque::new()),
+            counters: Arc::default(),
+            watermarks: None,
+            above_watermark: AtomicBool::new(false),
+            thread_dead: AtomicBool::new(false),
+            init_warnings: Vec::new(),
+            notices: Arc::default(),
+        }
+    }
+
+    pub fn on_stdout(mut self) -> BufferedWriter {
+        self.target = WriteTarget::StdOut;
+        self
+    }
+
+    pub fn on_file(mut self, file_path: PathBuf) -> BufferedWriter {
+        self.target = WriteTarget::File;
+        self.file_path = file_path;
+        self
+    }
+
+    ///
+    /// Writes on a file already open for writing (e.g. a file descriptor inherited from the parent process, 
+    /// a temporary file or a memfd), without opening it by path. The header, the block alignment and the sync policy
+    /// apply as for the file writers, but not the rotation nor the exclusive lock.
+    /// 
+    pub fn on_open_file(mut self, file: File) -> BufferedWriter {
+        self.target = WriteTarget::OpenFile;
+        self.open_file = Some(file);
+        self
+    }
+
+    ///
+    /// Sets a line written at init before any record (e.g. the header of a CSV file). 
+    /// The header is only written if the file is empty, so appending to an existing file doesn't repeat it.
+    /// 
+    pub fn with_header(mut self, header: String) -> BufferedWriter {
+        self.header = Some(header);
+        self
+    }
+
+    ///
+    /// Writes the file in whole blocks of `block_size` bytes (e.g. 4096), which is faster on some storage.
+    /// The buffer capacity is rounded up to a multiple of the block size, and the bytes after the 
+    /// last whole block are only written on explicit flushes (flush(), flush policies) and at cleanup.
+    /// # Panics 
+    /// If `block_size` is 0.
+    /// 
+    pub fn with_block_alignment(mut self, block_size: usize) -> BufferedWriter {
+        assert!(block_size > 0, "The block size must be greater than 0");
+        self.block_size = Some(block_size);
+        self
+    }
+
+    ///
+    /// Sets when the file is synced on the disk with `File::sync_data()`, so the records survive a power loss.
+    /// [`SyncPolicy::EveryRecord`] also flushes the buffer after each record, whatever the flush policy.
+    /// Only applies to the file writers: on the other targets the policy is ignored with a warning (see init_warnings()).
+    /// 
+    pub fn with_sync(mut self, policy: SyncPolicy) -> BufferedWriter {
+        self.sync = policy;
+        self
+    }
+
+    ///
+    /// Audit mode: syncs the file and its metadata on the disk with `File::sync_all()` after each flush,
+    /// as `with_sync(SyncPolicy::FullOnFlush)`. Only applies to the file writers.
+    /// 
+    pub fn with_fsync(self) -> BufferedWriter {
+        self.with_sync(SyncPolicy::FullOnFlush)
+    }
+
+    ///
+    /// Retries opening the file at init up to `count` times before giving up, waiting `delay` before
+    /// the first retry and doubling it before each next one, e.g. for a mounted volume not ready yet.
+    /// The error of the last attempt is returned if they all fail.
+    /// 
+    pub fn with_open_retries(mut self, count: u32, delay: Duration) -> BufferedWriter {
+        self.open_retries = (count, delay);
+        self
+    }
+
+    ///
+    /// Sets whether the content of an existing file is kept (default) or discarded at init.
+    /// [`OpenMode::Truncate`] with a rotation policy makes init() fail.
+    /// 
+    pub fn with_open_mode(mut self, mode: OpenMode) -> BufferedWriter {
+        self.open_mode = mode;
+        self
+    }
+
+    ///
+    /// With true, takes an advisory lock on the file at init (and on the next ones after each rotation, 
+    /// before the closed file is renamed: if it can't be taken, the records go on in the closed file), 
+    /// so that two processes never write on the same file: init() fails if another process holds the lock. 
+    /// The lock is released when the writer is dropped. Only the processes taking the lock are kept out.
+    /// 
+    pub fn with_exclusive_lock(mut self, exclusive: bool) -> BufferedWriter {
+        self.exclusive = exclusive;
+        self
+    }
+
+    ///
+    /// Creates the file, and the next ones after each rotation, with the permissions `mode` (e.g. `0o600`) 
+    /// instead of the default ones, minus the umask of the process. The existing files are left as they are.
+    /// No-op on non-Unix platforms.
+    /// 
+    pub fn with_file_mode(mut self, mode: u32) -> BufferedWriter {
+        self.file_mode = Some(mode);
+        self
+    }
+
+    ///
+    /// Sets when the file is closed and a new one started, the closed one being renamed after its period.
+    /// The period is checked on each record, so a file is closed with the first record of the next period, after the records
+    /// of its period still in the buffer are written on it: each file holds exactly the records written in its period.
+    /// At init, a file last written in a previous period (e.g. before a restart) is rotated immediately.
+    /// Only applies to the file writers: on the other targets the policy is ignored with a warning (see init_warnings()).
+    /// 
+    pub fn with_rotation(mut self, policy: RotationPolicy) -> BufferedWriter {
+        self.rotation = policy;
+        self
+    }
+
+    ///
+    /// Sets how the rotated files are named: after their period (default) or after the time they were closed.
+    /// The max backups and the compression handle the files of both namings.
+    /// 
+    pub fn with_rotated_naming(mut self, naming: RotatedNaming) -> BufferedWriter {
+        self.rotated_naming = naming;
+        self
+    }
+
+    ///
+    /// Keeps only the `max_backups` newest rotated files, deleting the older ones after each rotation and at init.
+    /// Only the files named as rotated by this writer are deleted.
+    /// 
+    pub fn with_max_backups(mut self, max_backups: usize) -> BufferedWriter {
+        self.backups.max_backups = Some(max_backups);
+        self
+    }
+
+    ///
+    /// Deletes the rotated files last written more than `max_age` ago, after each rotation and at init.
+    /// Only the files named as rotated by this writer are deleted.
+    /// 
+    pub fn with_max_age(mut self, max_age: Duration) -> BufferedWriter {
+        self.backups.max_age = Some(max_age);
+        self
+    }
+
+    ///
+    /// Gzips the rotated files on a helper thread, `app.2024-06-03.log` becoming `app.2024-06-03.log.gz`.
+    /// The files left uncompressed (e.g. by a process ending during a compression) are compressed 
+    /// at the next rotation or init. The compressed files count as rotated files for the max backups.
+    /// 
+    pub fn with_compression(mut self) -> BufferedWriter {
+        self.backups.compress = true;
+        self
+    }
+
+    ///
+    /// Discards everything written, while going through the same buffering and threading as 
+    /// the other targets. Useful to measure the formatting overhead without IO.
+    /// 
+    pub fn on_null(mut self) -> BufferedWriter {
+        self.target = WriteTarget::Null;
+        self
+    }
+
+    ///
+    /// Sets whether the parent directories of the log file are created at init (default true).
+    /// With false, the file is opened in the existing directory, useful when directory creation is forbidden.
+    /// 
+    pub fn with_create_dirs(mut self, create_dirs: bool) -> BufferedWriter {
+        self.create_dirs = create_dirs;
+        self
+    }
+
+    ///
+    /// Sets the permissions of the directories created at init to `mode` (e.g. `0o750`), 
+    /// whatever the umask of the process. The existing directories are left as they are. No-op on non-Unix platforms.
+    /// 
+    pub fn with_dir_mode(mut self, mode: u32) -> BufferedWriter {
+        self.dir_mode = Some(mode);
+        self
+    }
+
+    ///
+    /// Keeps the symlink `link` pointing at the log file, e.g. for `tail -F`: it's created at init 
+    /// and replaced atomically after each rotation. A relative `link` is in the directory of the file.
+    /// No-op on non-Unix platforms.
+    /// 
+    pub fn with_latest_link(mut self, link: impl Into<PathBuf>) -> BufferedWriter {
+        self.latest_link = Some(link.into());
+        self
+    }
+
+    ///
+    /// Writes on the given capture handle. 
+    /// The capture writer is never buffered: every line is visible as soon as it's written.
+    /// 
+    pub fn on_capture(mut self, capture: CaptureHandle) -> BufferedWriter {
+        self.target = WriteTarget::Capture;
+        self.capture = Some(capture);
+        self
+    }
+
+    ///
+    /// Returns the capture handle the writer writes on, if any, to receive the records as well.
+    ///
+    pub(crate) fn capture(&self) -> Option<&CaptureHandle> {
+        self.capture.as_ref().filter(|_| self.target == WriteTarget::Capture)
+    }
+
+    ///
+    /// Writes on the given target, e.g. a socket, with the same buffering, threading and flushes as the other targets.
+    /// 
+    pub fn on_custom(mut self, target: impl Write + Send + Sync + 'static) -> BufferedWriter {
+        self.target = WriteTarget::Custom;
+        self.custom = Some(Box::new(target));
+        self
+    }
+
+    /// 
+    /// Sets the write mode to ThisThread (default). 
+    /// With this mode, the logging operations will happen on the thread which is calling the write().
+    /// 
+    #[must_use = "You must call init() to initialize the writer"]
+    #[allow(dead_code)]
+    pub fn with_this_thread(mut self) -> BufferedWriter {
+        self.mode = WriteMode::ThisThread;
+        self
+    }
+
+    /// 
+    /// With this mode, the logging operations will happen of a dedicated separate thread.
+    /// 
+    pub fn with_separate_thread(mut self) -> BufferedWriter {
+        self.mode = WriteMode::SeparateThread;
+        self
+    }
+
+    /// 
+    /// Names the separate thread, instead of a name derived from the target like `rslogger-stdout` 
+    /// or `rslog:app.log`. Linux only shows the first 15 bytes.
+    /// 
+    pub fn with_thread_name(mut self, name: String) -> BufferedWriter {
+        self.thread_name = Some(name);
+        self
+    }
+
+    /// 
+    /// Lowers the scheduling priority of the separate thread, so it loses the CPU to the other threads
+    /// of the process. Does nothing on the tokio blocking pool, whose threads are shared.
+    /// A failure is reported on stderr, as the IO errors.
+    /// 
+    pub fn with_background_priority(mut self) -> BufferedWriter {
+        self.background_priority = true;
+        self
+    }
+
+    /// 
+    /// With this mode, the logging operations will happen on the given thread, shared with other writers.
+    /// The queue capacity and the overflow policy don't apply: the queue of the shared thread is unbounded.
+    /// 
+    pub fn with_shared_thread(mut self, shared_thread: Arc<SharedThread>) -> BufferedWriter {
+        self.mode = WriteMode::Shared;
+        self.shared_thread = Some(shared_thread);
+        self
+    }
+
+    /// 
+    /// Bounds the queue of the separate thread to `capacity` records, so a logging loop faster 
+    /// than the sink can't grow the memory without limit. When the queue is full, logging waits for room.
+    /// Only applies to the separate thread modes. The queue is unbounded by default.
+    /// Independent of the buffer capacity (see with_buffer_capacity), which sizes the writes of the separate thread.
+    /// 
+    pub fn with_queue_capacity(mut self, capacity: usize) -> BufferedWriter {
+        self.queue_capacity = Some(capacity);
+        self
+    }
+
+    /// 
+    /// Sets what happens to a record when the bounded queue is full (default Block).
+    /// Without `with_queue_capacity` the queue is never full and the policy has no effect.
+    /// 
+    pub fn with_overflow_policy(mut self, policy: OverflowPolicy) -> BufferedWriter {
+        self.overflow_policy = policy;
+        self
+    }
+
+    /// 
+    /// Reports the queue reaching `high` records, then going back down to `low` (see queue_watermark()).
+    /// Only applies to the separate thread modes.
+    /// 
+    pub fn with_queue_watermarks(mut self, high: usize, low: usize) -> BufferedWriter {
+        self.watermarks = Some((high, low));
+        self
+    }
+
+    /// 
+    /// Flushes the buffer when no record arrives for `idle` after a write, so that the records appear
+    /// quickly when the traffic is light, while the buffer still fills up under load.
+    /// Only applies to the separate thread modes.
+    /// 
+    pub fn with_idle_flush(mut self, idle: Duration) -> BufferedWriter {
+        self.idle_flush = Some(idle);
+        self
+    }
+
+    ///
+    /// Sets when the buffer is flushed. By default the stdout writers flush after each record,
+    /// so the lines show up as they are logged, while the other writers flush when the buffer is full.
+    /// 
+    pub fn with_flush_policy(mut self, policy: FlushPolicy) -> BufferedWriter {
+        self.flush_policy = Some(policy);
+        self
+    }
+
+    ///
+    /// Returns when the buffer is flushed, see with_flush_policy().
+    /// 
+    pub fn flush_policy(&self) -> FlushPolicy {
+        if self.sync == SyncPolicy::EveryRecord && matches!(self.target, WriteTarget::File | WriteTarget::OpenFile) {
+            return FlushPolicy::EveryRecord;
+        }
+        self.flush_policy.unwrap_or(match self.target {
+            WriteTarget::StdOut => FlushPolicy::EveryRecord,
+            _ => FlushPolicy::Buffered,
+        })
+    }
+
+    /// 
+    /// With this mode, the records are written by a task of the current tokio runtime through a tokio file,
+    /// instead of a dedicated thread. Only for the file writers, without rotation, sync policy or block alignment.
+    /// init() must be called within a multi-thread tokio runtime: the flushes wait for the task synchronously.
+    /// 
+    #[cfg(feature = "async-tokio")]
+    pub fn with_tokio_task(mut self) -> BufferedWriter {
+        self.mode = WriteMode::TokioTask;
+        self
+    }
+
+    ///
+    /// Sets the capcity of the buffer, in bytes (a plain `usize`) or in records. 
+    /// Not calling this function will use the default capacity. 
+    /// Calling this function with capacity = 0, will write and flush log by log on the target, without any buffer.
+    /// A capacity in records sets the flush policy to [`FlushPolicy::EveryN`].
+    /// 
+    pub fn with_buffer_capacity(mut self, capacity: impl Into<BufferCapacity>) -> BufferedWriter {
+        match capacity.into() {
+            BufferCapacity::Bytes(bytes) => self.buffer_capacity = bytes,
+            BufferCapacity::Records(records) => {
+                self.buffer_capacity = RECORDS_BUFFER_CAPACITY;
+                self.flush_policy = Some(FlushPolicy::EveryN(records));
+            }
+        }
+        self
+    }
+
+    ///
+    /// Sets what happens after writing a record of the given level.
+    /// By default all the levels are buffered.
+    /// 
+    pub fn with_level_flush(mut self, level: Level, policy: LevelFlush) -> BufferedWriter {
+        self.level_flush[level as usize - 1] = policy;
+        self
+    }
+
+    ///
+    /// Returns the flush policy configured for the given level.
+    /// 
+    pub fn level_flush(&self, level: Level) -> LevelFlush {
+        self.level_flush[level as usize - 1]
+    }
+
+    ///
+    /// Marks the writer as receiving colored lines. 
+    /// The writer will emit a final color reset at cleanup, so the terminal is never left colored.
+    /// 
+    pub fn with_colors(mut self) -> BufferedWriter {
+        self.colors = true;
+        self
+    }
+
+    ///
+    /// Writes each record on stdout with a single write under the stdout lock, including its terminator, 
+    /// so the lines can't be torn by the prints of the other threads. The records are not buffered: 
+    /// each one is written and flushed on its own, whatever the buffer capacity.
+    /// 
+    pub fn with_stdout_lock_per_line(mut self) -> BufferedWriter {
+        self.stdout_lock_per_line = true;
+        self
+    }
+
+    ///
+    /// Writes each record as a binary frame instead of a line: the level byte (1 for Error to 5 for Trace), 
+    /// the length of the text as an unsigned LEB128 varint, then the text. The messages written must start with
+    /// the level byte, followed by the text. The framing and the header are ignored.
+    /// 
+    pub fn with_binary_frames(mut self) -> BufferedWriter {
+        self.framing.binary = true;
+        self.header = None;
+        self
+    }
+
+    ///
+    /// Returns true if the writer writes binary frames, see with_binary_frames().
+    /// 
+    pub fn binary_frames(&self) -> bool {
+        self.framing.binary
+    }
+
+    ///
+    /// Sets the bytes written before and after each record, replacing the default `\n` terminator.
+    /// E.g. a `\x1e` prefix and a `\n` suffix for JSON text sequences, or a binary length prefix.
+    /// 
+    pub fn with_record_framing(mut self, prefix: Vec<u8>, suffix: Vec<u8>) -> BufferedWriter {
+        self.framing.prefix = prefix;
+        self.framing.suffix = suffix;
+        self
+    }
+
+    ///
+    /// Holds back the suffix of each record (by default the `\n` terminator) until the next record,
+    /// so the output never ends with it: the suffix becomes a separator between the records.
+    /// Appending to a non-empty file starts with a suffix, to separate the new records from the previous ones.
+    /// 
+    pub fn without_final_suffix(mut self) -> BufferedWriter {
+        self.framing.final_suffix = false;
+        self
+    }
+
+    ///
+    /// Returns true if the writer receives colored lines.
+    /// 
+    pub fn colors(&self) -> bool {
+        self.colors
+    }
+
+    ///
+    /// Marks the writer as receiving the Error records drawn in a box.
+    /// 
+    pub fn with_boxed_errors(mut self) -> BufferedWriter {
+        self.boxed_errors = true;
+        self
+    }
+
+    ///
+    /// Returns true if the writer receives the Error records drawn in a box.
+    /// 
+    pub fn boxed_errors(&self) -> bool {
+        self.boxed_errors
+    }
+
+    ///
+    /// Initializes the BufferedWriter. To be necessarily called before any write. 
+    /// In case of failures returns an error with the description of the error
+    /// 
+    pub fn init(self) -> Result<BufferedWriter, Error> {
+        match self.init_writers() {
+            Ok(moved_self) => {
+                match &moved_self.mode {
+                    WriteMode::SeparateThread => moved_self.init_separate_thread(),
+                    #[cfg(feature = "async-tokio")]
+                    WriteMode::TokioTask => moved_self.init_tokio_task(),
+                    WriteMode::Shared => moved_self.init_shared_thread(),
+                    _ => Ok(moved_self),
+                }
+            },
+            Err(error) => Err(error),
+        }
+    }
+
+    ///
+    /// Writes the message on the target using the configured mode. 
+    /// The message is shared with the separate thread without being copied.
+    /// # Panics 
+    /// If called before init()
+    /// 
+    pub fn write(&self, message: &Arc<str>) {
+        if self.io_state.broken() {
+            return;
+        }
+
+        match &self.mode {
+            WriteMode::ThisThread => {
+                if BufferedWriter::write_on_this_thread(message, &self.framing, self.buf_writer.as_ref().unwrap(), &self.io_state) {
+                    self.counters.written(1);
+                }
+            }
+            WriteMode::SeparateThread => self.send_record(MsgType::Msg(Arc::clone(message))),
+            #[cfg(feature = "async-tokio")]
+            WriteMode::TokioTask => self.send_record(MsgType::Msg(Arc::clone(message))),
+            WriteMode::Shared => self.send_record(MsgType::Msg(Arc::clone(message))),
+        }
+    }
+
+    ///
+    /// Sends the record to be laid out on the separate thread.
+    /// Writers not running on a separate thread lay it out immediately.
+    /// # Panics 
+    /// If called before init()
+    /// 
+    pub fn write_deferred(&self, record: &Arc<DeferredRecord>) {
+        if self.io_state.broken() {
+            return;
+        }
+
+        if self.is_separate_thread() {
+            self.send_record(MsgType::Deferred(Arc::clone(record)));
+        } else {
+            self.write(&record.line(self.colors).into());
+        }
+    }
+
+    ///
+    /// Returns true if both writers write on stdout, or on the same file (even through different paths).
+    /// `candidate` is a writer not initialized yet: its path is expanded as init() does, unlike the one of this writer.
+    /// 
+    pub fn same_destination(&self, candidate: &BufferedWriter) -> bool {
+        match (&self.target, &candidate.target) {
+            (WriteTarget::StdOut, WriteTarget::StdOut) => true,
+            (WriteTarget::File, WriteTarget::File) => {
+                // An invalid path is reported by init().
+                let Ok(candidate_path) = expand_path(&candidate.file_path) else {
+                    return false;
+                };
+                match (fs::canonicalize(&self.file_path), fs::canonicalize(&candidate_path)) {
+                    (Ok(path), Ok(other_path)) => path == other_path,
+                    _ => self.file_path == candidate_path,
+                }
+            }
+            _ => false,
+        }
+    }
+
+    ///
+    /// Returns the bytes buffered by the writer and not yet written on its target, e.g. to understand why 
+    /// some lines haven't appeared yet. For the separate thread modes, it's the buffer after the last message
+    /// handled by the thread, and the records still in the queue are not counted.
+    /// # Panics 
+    /// If called before init()
+    /// 
+    pub fn buffered_len(&self) -> usize {
+        match &self.mode {
+            WriteMode::ThisThread => self.buf_writer.as_ref().unwrap().read().unwrap_or_else(PoisonError::into_inner).buffered_len(),
+            _ => self.counters.buffered.load(Ordering::Relaxed),
+        }
+    }
+
+    ///
+    /// Returns true if the writer writes on a separate thread (or task).
+    /// 
+    pub fn is_separate_thread(&self) -> bool {
+        !matches!(self.mode, WriteMode::ThisThread)
+    }
+
+    ///
+    /// Returns the number of records dropped because the bounded queue was full,
+    /// or because the separate thread was dead.
+    /// 
+    pub fn dropped(&self) -> u64 {
+        self.counters.dropped.load(Ordering::Relaxed)
+    }
+
+    ///
+    /// Returns the options ignored at init because they don't apply to the target or the mode,
+    /// e.g. a sync policy on stdout. The logger reports them as notices (see [`crate::Logger`]).
+    /// 
+    pub fn init_warnings(&self) -> &[String] {
+        &self.init_warnings
+    }
+
+    ///
+    /// Returns the failures since the last call that are not about writing the records, e.g. a rotated file 
+    /// that can't be deleted. The logger reports them as notices, as init_warnings().
+    /// 
+    pub fn take_notices(&self) -> Vec<String> {
+        self.notices.take()
+    }
+
+    ///
+    /// Returns the crossing of the queue watermarks since the last call, if any.
+    /// The high watermark is reported once, then nothing until the queue goes back down to the low watermark.
+    /// 
+    pub fn queue_watermark(&self) -> Option<QueueWatermark> {
+        let (high, low) = self.watermarks?;
+        let queued = self.counters.queued.load(Ordering::Relaxed);
+        if queued >= high && !self.above_watermark.swap(true, Ordering::Relaxed) {
+            return Some(QueueWatermark::High(queued));
+        }
+        if queued <= low && self.above_watermark.swap(false, Ordering::Relaxed) {
+            return Some(QueueWatermark::Low(queued));
+        }
+        None
+    }
+
+    ///
+    /// Immediately flushes the buffer. 
+    /// Records waiting for room in the queue are written first, whatever the overflow policy.
+    /// # Panics 
+    /// If called before init()
+    /// 
+    pub fn flush(&self) {
+        match &self.mode {
+            WriteMode::ThisThread => 
+                BufferedWriter::flush_on_this_thread(self.buf_writer.as_ref().unwrap(), &self.io_state),
+            WriteMode::SeparateThread => self.send_control(MsgType::Flush),
+            #[cfg(feature = "async-tokio")]
+            WriteMode::TokioTask => self.send_control(MsgType::Flush),
+            WriteMode::Shared => self.send_control(MsgType::Flush),
+        }
+    }
+
+    /// 
+    /// Flushes the buffer and clean up the writer. 
+    /// If the mode is SeparateThread, the thread will be stopped and it's no longer possible
+    /// to write anything else. 
+    /// After calling this method you can only restart to write if you call the init method again.
+    /// Use this method before dropping the BufferedWriter to ensure all the data are flushed.
+    /// Returns what happened on the writer.
+    /// 
+    pub fn flush_and_cleanup(&mut self) -> FlushStats {
+        let start = Instant::now();
+        let mut joined = true;
+        match &self.mode {
+            WriteMode::ThisThread => {
+                if self.colors {
+                    BufferedWriter::reset_colors_on_this_thread(self.buf_writer.as_ref().unwrap(), &self.io_state);
+                }
+                BufferedWriter::flush_on_this_thread(self.buf_writer.as_ref().unwrap(), &self.io_state)
+            },
+            WriteMode::SeparateThread => {
+                self.send_control(MsgType::FlushAndStop);
+                self.sender.take();
+                self.buf_writer.take();
+                joined = self.thread_handler.take().unwrap().join().is_ok();
+            }
+            #[cfg(feature = "async-tokio")]
+            WriteMode::TokioTask => joined = self.stop_task(),
+            WriteMode::Shared => joined = self.stop_task(),
+        }
+
+        FlushStats {
+            written: self.counters.written.load(Ordering::Relaxed),
+            dropped: self.counters.dropped.load(Ordering::Relaxed),
+            duration: start.elapsed(),
+            joined,
+        }
+    }
+
+
+    // ------------------------------------- Private ------------------------------- //
+
+    ///
+    /// Stops the tokio task or the slot on the shared thread, returning false if it panicked.
+    /// A tokio task is waited for up to TASK_STOP_TIMEOUT, as the runtime may not run it soon
+    /// (or never, if it's shutting down): the flush must not hang the synchronous caller.
+    /// 
+    fn stop_task(&mut self) -> bool {
+        self.send_control(MsgType::FlushAndStop);
+        self.sender.take();
+        self.buf_writer.take();
+        // Returns when the task or the slot ends: the signal is sent at the end, or the sender is dropped by a panic.
+        let Ok(task_done) = self.task_done.take().unwrap().into_inner() else {
+            return false;
+        };
+        match self.mode {
+            #[cfg(feature = "async-tokio")]
+            WriteMode::TokioTask => match block_in_runtime(|| task_done.recv_timeout(TASK_STOP_TIMEOUT)) {
+                Ok(()) => true,
+                Err(RecvTimeoutError::Timeout) => {
+                    eprintln!("The writer task of {} did not stop within {TASK_STOP_TIMEOUT:?}, its last records may be lost", self.file_path.display());
+                    false
+                }
+                Err(RecvTimeoutError::Disconnected) => false,
+            },
+            _ => task_done.recv().is_ok(),
+        }
+    }
+
+    ///
+    /// Queues a record for the separate thread, applying the overflow policy if the queue is full.
+    /// 
+    fn send_record(&self, message: MsgType) {
+        match self.overflow_policy {
+            OverflowPolicy::Block => {
+                let _ = self.enqueue(message, true);
+            }
+            OverflowPolicy::DropNewest => {
+                if let Err(TrySendError::Full(_)) = self.enqueue(message, false) {
+                    self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            OverflowPolicy::DropOldest => {
+                let mut overflow = self.overflow.lock().unwrap_or_else(PoisonError::into_inner);
+                // The waiting records are older, they are queued first to keep the order.
+                while let Some(waiting) = overflow.pop_front() {
+                    if let Err(TrySendError::Full(waiting)) = self.enqueue(waiting, false) {
+                        overflow.push_front(waiting);
+                        break;
+                    }
+                }
+
+                let message = if overflow.is_empty() {
+                    match self.enqueue(message, false) {
+                        Err(TrySendError::Full(message)) => message,
+                        _ => return,
+                    }
+                } else {
+                    message
+                };
+
+                if overflow.len() >= self.queue_capacity.unwrap_or_default().max(1) {
+                    overflow.pop_front();
+                    self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+                overflow.push_back(message);
+            }
+        }
+    }
+
+    ///
+    /// Sends a control message to the separate thread after the records waiting for room, if any.
+    /// Always waits for room, so a flush is never dropped.
+    /// 
+    fn send_control(&self, message: MsgType) {
+        let mut overflow = self.overflow.lock().unwrap_or_else(PoisonError::into_inner);
+        for waiting in overflow.drain(..) {
+            let _ = self.enqueue(waiting, true);
+        }
+        if self.sender.as_ref().unwrap().send(message).is_err() {
+            self.report_dead_thread();
+        }
+    }
+
+    ///
+    /// Sends a record to the separate thread, waiting for room if `wait`, and counts it as queued.
+    /// 
+    fn enqueue(&self, message: MsgType, wait: bool) -> Result<(), TrySendError<MsgType>> {
+        let sender = self.sender.as_ref().unwrap();
+        // Counted before sending, so the separate thread never receives a record not counted yet.
+        self.counters.queued.fetch_add(1, Ordering::Relaxed);
+        let result = if wait {
+            sender.send(message).map_err(|SendError(message)| TrySendError::Disconnected(message))
+        } else {
+            sender.try_send(message)
+        };
+        if result.is_err() {
+            self.counters.queued.fetch_sub(1, Ordering::Relaxed);
+        }
+        if let Err(TrySendError::Disconnected(_)) = result {
+            self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+            self.report_dead_thread();
+        }
+        result
+    }
+
+    ///
+    /// Reports on stderr, the first time only, that the separate thread stopped receiving: 
+    /// it panicked, and every record sent to it from now on is dropped.
+    /// 
+    fn report_dead_thread(&self) {
+        if !self.thread_dead.swap(true, Ordering::Relaxed) {
+            eprintln!("The writer thread {} is dead, the records sent to it are dropped", self.thread_name());
+        }
+    }
+
+    ///
+    /// Initializes the writers depending on the target.
+    /// This routine is common to Single and Multi Thread.
+    /// Can panic if the writers are initialized before.
+    /// 
+    fn init_writers(mut self) -> Result<BufferedWriter, Error> {
+
+        // Check if data is not corrupted
+        if self.buf_writer.is_some() {
+            panic!("The BufWriter should be None at this point");
+        }
+
+        if self.sync != SyncPolicy::Never && !matches!(self.target, WriteTarget::File | WriteTarget::OpenFile) {
+            self.init_warnings.push(format!("the sync policy {:?} only applies to the file writers, it's ignored", self.sync));
+        }
+
+        if self.rotation != RotationPolicy::Never && self.target != WriteTarget::File {
+            self.init_warnings.push(format!("the rotation policy {:?} only applies to the file writers, it's ignored", self.rotation));
+        }
+
+        if self.exclusive && self.target != WriteTarget::File {
+            self.init_warnings.push("the exclusive lock only applies to the file writers, it's ignored".to_string());
+        }
+
+        #[cfg(feature = "async-tokio")]
+        if matches!(self.mode, WriteMode::TokioTask) {
+            if self.target != WriteTarget::File {
+                return Err(Error::InvalidConfig("the tokio writer task only writes on files".to_string()));
+            }
+            if self.rotation != RotationPolicy::Never || self.sync != SyncPolicy::Never || self.block_size.is_some() {
+                self.init_warnings.push("the rotation, the sync policy and the block alignment don't apply to the tokio writer task, they're ignored".to_string());
+                self.rotation = RotationPolicy::Never;
+                self.sync = SyncPolicy::Never;
+                self.block_size = None;
+            }
+        }
+
+        match self.target {
+            // Init for stdout
+            WriteTarget::StdOut => {
+                // The stdout lock is taken by the BufWriter flushes only. A StdoutLock can't be kept 
+                // instead: it's not Send and it would block the prints of the other threads.
+                let sink = if self.stdout_lock_per_line {
+                    // Unbuffered, so each record reaches the target whole.
+                    Sink::new(0, self.flush_policy(), LockedStdout)
+                } else {
+                    Sink::new(self.buffer_capacity, self.flush_policy(), std::io::stdout())
+                };
+                self.buf_writer = Some(Box::new(RwLock::new(sink)));
+                Ok(self)
+            }
+            // Init for file
+            WriteTarget::File => {
+                self.file_path = expand_path(&self.file_path)
+                    .map_err(|err| Error::InvalidConfig(format!("invalid log file path: {err}")))?;
+
+                if self.open_mode == OpenMode::Truncate && self.rotation != RotationPolicy::Never {
+                    return Err(Error::InvalidConfig(format!("the Truncate open mode can't be combined with the rotation policy {:?}: \
+                        the file would be discarded at each start while the rotated ones are kept", self.rotation)));
+                }
+
+                // Create the folder if it doesn't exists
+                if self.create_dirs
+                    && let Some(dir) = &self.file_path.parent()
+                    && let Err(err) = create_dirs(dir, self.dir_mode) {
+                    return Err(Error::Io { path: dir.to_path_buf(), source: err });
+                }
+
+                // Checked before the file is rotated or truncated, which would clobber the file of the other process.
+                if self.exclusive
+                    && let Ok(file) = File::open(&self.file_path)
+                    && let Err(err) = lock_exclusive(&file, &self.file_path) {
+                    return Err(Error::Io { path: self.file_path.clone(), source: err });
+                }
+
+                if self.rotation != RotationPolicy::Never {
+                    if let Err(err) = rotate_stale(&self.file_path, self.rotation, self.rotated_naming) {
+                        eprintln!("Unable to rotate {}: {err}", self.file_path.display());
+                    }
+                    self.backups.process(&self.file_path, &self.notices);
+                }
+
+                // Open the file
+                let (retries, delay) = self.open_retries;
+                match open_with_retries(&self.file_path, &open_options(self.open_mode, self.file_mode), retries, delay) {
+                        Err(err) if retries > 0 => {
+                            let source = io::Error::new(err.kind(), format!("{err} (after {} attempts)", retries + 1));
+                            Err(Error::Io { path: self.file_path.clone(), source })
+                        }
+                        Err(err) => {
+                            Err(Error::Io { path: self.file_path.clone(), source: err })
+                        }
+
+                        // Ok, initialize bufwriter
+                        Ok(file_handler) => {
+                            if self.exclusive
+                                && let Err(err) = lock_exclusive(&file_handler, &self.file_path) {
+                                return Err(Error::Io { path: self.file_path.clone(), source: err });
+                            }
+                            let latest_link = self.latest_link.as_ref().map(|link| match self.file_path.parent() {
+                                Some(dir) => dir.join(link),
+                                None => link.clone(),
+                            });
+                            if let Some(link) = &latest_link
+                                && let Err(err) = update_link(link, &self.file_path) {
+                                eprintln!("Unable to update the link {}: {err}", link.display());
+                            }
+                            let is_empty = file_handler.metadata().is_ok_and(|metadata| metadata.len() == 0);
+                            self.framing.suffix_pending.store(!is_empty, Ordering::Relaxed);
+                            #[cfg(feature = "async-tokio")]
+                            if matches!(self.mode, WriteMode::TokioTask) {
+                                self.async_file = Some((file_handler, is_empty));
+                                return Ok(self);
+                            }
+                            let file_handler = Synced { inner: file_handler, policy: self.sync };
+                            let (file_handler, period_end): (Box<dyn Write + Send + Sync>, _) = match self.rotation {
+                                RotationPolicy::Never => (Box::new(file_handler), None),
+                                policy => {
+                                    let rotating = Rotating::new(self.file_path.clone(), policy, self.rotated_naming, self.backups, file_handler, self.framed_header())
+                                        .with_latest_link(latest_link)
+                                        .with_file_mode(self.file_mode)
+                                        .with_exclusive_lock(self.exclusive)
+                                        .with_notices(Arc::clone(&self.notices));
+                                    let period_end = Arc::clone(&rotating.period_end);
+                                    (Box::new(rotating), Some(period_end))
+                                }
+                            };
+                            self.init_file_sink(file_handler, is_empty, period_end);
+                            Ok(self)
+                        }
+                    }
+            }
+            // Init for a file opened by the user
+            WriteTarget::OpenFile => {
+                let file_handler = self.open_file.take().expect("The open file should be set at this point");
+                let is_empty = file_handler.metadata().is_ok_and(|metadata| metadata.len() == 0);
+                self.framing.suffix_pending.store(!is_empty, Ordering::Relaxed);
+                self.init_file_sink(Box::new(Synced { inner: file_handler, policy: self.sync }), is_empty, None);
+                Ok(self)
+            }
+            // Init for null
+            WriteTarget::Null => {
+                self.buf_writer = Some(Box::new(
+                    RwLock::new(
+                        Sink::new(self.buffer_capacity, self.flush_policy(), std::io::sink())
+                    )
+                ));
+                Ok(self)
+            }
+            // Init for capture, always unbuffered
+            WriteTarget::Capture => {
+                let capture = self.capture.clone().expect("The capture handle should be set at this point");
+                self.buf_writer = Some(Box::new(
+                    RwLock::new(
+                        Sink::new(0, FlushPolicy::Buffered, capture)
+                    )
+                ));
+                Ok(self)
+            }
+            // Init for custom
+            WriteTarget::Custom => {
+                let custom = self.custom.take().expect("The custom target should be set at this point");
+                self.buf_writer = Some(Box::new(
+                    RwLock::new(
+                        Sink::new(self.buffer_capacity, self.flush_policy(), custom)
+                    )
+                ));
+                Ok(self)
+            }
+        }
+    }
+
+    ///
+    /// Initializes the BufWriter on an open file, aligned on blocks if set, and writes the header if the file is empty.
+    /// `period_end` is the one of a rotating file.
+    /// 
+    fn init_file_sink(&mut self, file_handler: Box<dyn Write + Send + Sync>, is_empty: bool, period_end: Option<Arc<PeriodEnd>>) {
+        self.buf_writer = Some(match self.block_size {
+            Some(block_size) => Box::new(
+                RwLock::new(
+                    Sink::new(
+                        self.buffer_capacity.div_ceil(block_size).max(1) * block_size,
+                        self.flush_policy(),
+                        BlockAligned { inner: file_handler, block_size, pending: Vec::new() }
+                    ).with_period_end(period_end)
+                )
+            ),
+            None => Box::new(
+                RwLock::new(
+                    Sink::new(self.buffer_capacity, self.flush_policy(), file_handler).with_period_end(period_end)
+                )
+            ),
+        });
+
+        if is_empty && let Some(header) = &self.header {
+            BufferedWriter::write_on_this_thread(header, &self.framing, self.buf_writer.as_ref().unwrap(), &self.io_state);
+        }
+    }
+
+    ///
+    /// Initializes the separate thread for writing in SeparateThread Mode
+    /// Can panic if the data structure is corrupted here
+    /// 
+    fn init_separate_thread(mut self) -> Result<BufferedWriter, Error> {
+        // Check for data structure consistency
+        if self.thread_handler.is_some() {
+            panic!("Thread handler should be None at this point");
+        }
+
+        if self.sender.is_some() {
+            panic!("Sender should be None at this point");
+        }
+
+        if self.buf_writer.is_none() {
+            panic!("BufWriter should be initialized at this point");
+        }
+
+        let (sender, receiver) = QueueSender::new(self.queue_capacity);
+        self.sender = Some(sender); 
+
+        // Note that after the init, the bufwriter cannot be used anymore because it was moved to the other thread.
+        let buf_writer_to_move: Box<RwLock<Sink>> = self.buf_writer.take().unwrap();
+
+        let colors = self.colors;
+        let framing = self.framing.clone();
+        let io_state = Arc::clone(&self.io_state);
+        let idle_flush = self.idle_flush;
+        let counters = Arc::clone(&self.counters);
+        let background_priority = self.background_priority;
+
+        match thread::Builder::new().name(self.thread_name()).spawn(move | | {
+            let _pipeline = PipelineGuard::enter();
+            if background_priority {
+                BufferedWriter::check_io(priority::lower_current_thread(), &io_state, "Unable to lower the priority of the writer thread");
+            }
+            BufferedWriter::receive_on_this_thread(receiver, &buf_writer_to_move, colors, &framing, idle_flush, &io_state, &counters);
+        }) {
+            Err(err) => return Err(Error::ThreadSpawn(err)),
+            Ok(handler) => self.thread_handler = Some(handler),
+        }
+        
+        Ok(self)
+    }
+
+    ///
+    /// Spawns the task writing the records on the file opened by init_writers(), on the current tokio runtime.
+    /// The runtime must be multi-thread, so the task runs while a flush waits for it.
+    /// 
+    #[cfg(feature = "async-tokio")]
+    fn init_tokio_task(mut self) -> Result<BufferedWriter, Error> {
+        let runtime = tokio::runtime::Handle::try_current()
+            .map_err(|err| Error::InvalidConfig(format!("the async writers need a tokio runtime: {err}")))?;
+        if runtime.runtime_flavor() != tokio::runtime::RuntimeFlavor::MultiThread {
+            return Err(Error::InvalidConfig("the async writers need a multi-thread tokio runtime, \
+                the flushes can't wait for the writer task on a current-thread one".to_string()));
+        }
+
+        let (file, is_empty) = self.async_file.take().expect("The file should be opened at this point");
+        let (sender, receiver) = TokioReceiver::channel(self.queue_capacity);
+        let (done_sender, done_receiver) = channel::<()>();
+        self.sender = Some(sender);
+        self.task_done = Some(Mutex::new(done_receiver));
+
+        let header = self.header.clone().filter(|_| is_empty);
+        let framing = self.framing.clone();
+        let io_state = Arc::clone(&self.io_state);
+        let counters = Arc::clone(&self.counters);
+
+        runtime.spawn(async move {
+            let file = tokio::fs::File::from_std(file);
+            BufferedWriter::receive_on_task(receiver, file, header, &framing, &io_state, &counters).await;
+            let _ = done_sender.send(());
+        });
+
+        Ok(self)
+    }
+
+    ///
+    /// Writes the messages received until FlushAndStop is received or all the senders are dropped, 
+    /// in batches of up to BATCH_BYTES bytes. The file is written and flushed whenever the queue is empty.
+    /// 
+    #[cfg(feature = "async-tokio")]
+    async fn receive_on_task(mut receiver: TokioReceiver, mut file: tokio::fs::File, header: Option<String>, framing: &RecordFraming, 
+        io_state: &IoState, counters: &Counters) {
+        let mut batch = TaskBatch::default();
+        if let Some(header) = header {
+            batch.push(&header, framing);
+            batch.records = 0;
+        }
+
+        loop {
+            let message = match receiver.try_recv() {
+                Some(message) => Some(message),
+                None => {
+                    batch.write(&mut file, io_state, counters).await;
+                    receiver.recv().await
+                }
+            };
+
+            match message {
+                Some(MsgType::Msg(msg)) => {
+                    counters.received();
+                    batch.push(&msg, framing);
+                }
+                Some(MsgType::Deferred(record)) => {
+                    counters.received();
+                    batch.push(&record.line(false), framing);
+                }
+                Some(MsgType::Flush) => batch.write(&mut file, io_state, counters).await,
+                Some(MsgType::FlushAndStop) | None => {
+                    batch.write(&mut file, io_state, counters).await;
+                    break;
+                }
+            }
+            if batch.bytes.len() >= BATCH_BYTES {
+                batch.write(&mut file, io_state, counters).await;
+            }
+            counters.buffered.store(batch.bytes.len(), Ordering::Relaxed);
+        }
+    }
+
+    ///
+    /// Returns the header as write_framed() writes it, empty if there's none. Without final suffix, 
+    /// its suffix is the one written before the next record.
+    /// 
+    fn framed_header(&self) -> Vec<u8> {
+        let Some(header) = &self.header else {
+            return Vec::new();
+        };
+        let mut framed = [self.framing.prefix.as_slice(), header.as_bytes()].concat();
+        if self.framing.final_suffix {
+            framed.extend_from_slice(&self.framing.suffix);
+        }
+        framed
+    }
+
+    ///
+    /// Returns the name of the separate thread: the configured one, or one showing the target 
+    /// within the length kept by Linux.
+    /// 
+    fn thread_name(&self) -> String {
+        if let Some(name) = &self.thread_name {
+            return name.clone();
+        }
+
+        let mut name = match self.target {
+            WriteTarget::StdOut => "rslogger-stdout".to_string(),
+            WriteTarget::File => format!("rslog:{}", self.file_path.file_name().unwrap_or_default().to_string_lossy()),
+            WriteTarget::Capture => "rslogger-capture".to_string(),
+            WriteTarget::Null => "rslogger-null".to_string(),
+            WriteTarget::Custom => "rslogger-custom".to_string(),
+            WriteTarget::OpenFile => "rslogger-open-file".to_string(),
+        };
+        let mut len = name.len().min(THREAD_NAME_LEN);
+        while !name.is_char_boundary(len) {
+            len -= 1;
+        }
+        name.truncate(len);
+        name
+    }
+
+    ///
+    /// Hands the target to the shared thread, which writes the records of this writer from now on.
+    /// 
+    fn init_shared_thread(mut self) -> Result<BufferedWriter, Error> {
+        let shared_thread = self.shared_thread.take().expect("The shared thread should be set at this point");
+        let (done_sender, done_receiver) = channel::<()>();
+
+        self.sender = Some(shared_thread.register(SharedSlot {
+            buf_writer: self.buf_writer.take().unwrap(),
+            colors: self.colors,
+            framing: self.framing.clone(),
+            idle_flush: self.idle_flush,
+            io_state: Arc::clone(&self.io_state),
+            counters: Arc::clone(&self.counters),
+            done: done_sender,
+            panicked: false,
+            written_at: None,
+        }));
+        self.task_done = Some(Mutex::new(done_receiver));
+        Ok(self)
+    }
+
+    ///
+    /// Writes the messages received until FlushAndStop is received or all the senders are dropped. 
+    /// 
+    fn receive_on_this_thread(receiver: Receiver<MsgType>, buf_writer: &RwLock<Sink>, colors: bool, framing: &RecordFraming, idle_flush: Option<Duration>, io_state: &IoState, counters: &Counters) {
+        // A message received while draining a batch, not handled yet.
+        let mut pending = None;
+        // Records were written since the last flush.
+        let mut written = false;
+
+        loop {
+            let new_message = match (pending.take(), idle_flush) {
+                (Some(message), _) => message,
+                (None, Some(idle)) if written => match receiver.recv_timeout(idle) {
+                    Ok(message) => message,
+                    Err(RecvTimeoutError::Timeout) => {
+                        BufferedWriter::flush_on_this_thread(buf_writer, io_state);
+                        counters.update_buffered(buf_writer);
+                        written = false;
+                        continue;
+                    }
+                    Err(RecvTimeoutError::Disconnected) => break,
+                },
+                (None, _) => match receiver.recv() {
+                    Ok(message) => message,
+                    Err(_) => break,
+                },
+            };
+
+            written = matches!(new_message, MsgType::Msg(_) | MsgType::Deferred(_));
+            if written {
+                counters.received();
+            }
+
+            match new_message {
+                MsgType::Msg(msg) => pending = BufferedWriter::write_batch(&msg, &receiver, colors, framing, buf_writer, io_state, counters),
+                MsgType::Deferred(record) => 
+                    pending = BufferedWriter::write_batch(&record.line(colors), &receiver, colors, framing, buf_writer, io_state, counters),
+                MsgType::Flush => BufferedWriter::flush_on_this_thread(buf_writer, io_state),
+                MsgType::FlushAndStop => {
+                    if colors {
+                        BufferedWriter::reset_colors_on_this_thread(buf_writer, io_state);
+                    }
+                    BufferedWriter::flush_on_this_thread(buf_writer, io_state);
+                    break;
+                }
+            }
+            counters.update_buffered(buf_writer);
+        }
+    }
+
+
+    ///
+    /// Writes on this thread using the buf_writer passed, returning true if the message was written without error.
+    /// Used to avoid moving of self problem when initializing the separate thread.
+    /// # Panics
+    /// If the RWLock of the BufWriter is poisoned and cannot be taken for writing.
+    /// 
+    fn write_on_this_thread(message: &str, framing: &RecordFraming, buf_writer: &RwLock<Sink>, io_state: &IoState) -> bool {
+        if io_state.broken() {
+            return false;
+        }
+
+        if let Ok(mut writer_mut) = buf_writer.write() {
+            let result = BufferedWriter::write_framed(&mut writer_mut, message, framing);
+            BufferedWriter::check_io(result, io_state, "Unable to write")
+        } else {
+            panic!("Cannot get writer as mutable. RWLock is poisoned!");
+        }
+    }
+
+    ///
+    /// Writes `first` and the records already queued behind it (laying out the deferred ones) under a single lock of the BufWriter,
+    /// up to BATCH_RECORDS records or BATCH_BYTES bytes so that a flush doesn't wait too long behind a burst.
+    /// Returns the first message received that is not a record, to be handled by the caller.
+    /// # Panics
+    /// If the RWLock of the BufWriter is poisoned and cannot be taken for writing.
+    /// 
+    fn write_batch(first: &str, receiver: &Receiver<MsgType>, colors: bool, framing: &RecordFraming, buf_writer: &RwLock<Sink>, io_state: &IoState, counters: &Counters) -> Option<MsgType> {
+        if io_state.broken() {
+            return None;
+        }
+
+        let Ok(mut writer_mut) = buf_writer.write() else {
+            panic!("Cannot get writer as mutable. RWLock is poisoned!");
+        };
+
+        let mut result = BufferedWriter::write_framed(&mut writer_mut, first, framing);
+        let (mut records, mut bytes) = (1, first.len());
+        let mut pending = None;
+
+        while result.is_ok() && records < BATCH_RECORDS && bytes < BATCH_BYTES {
+            match receiver.try_recv() {
+                Ok(MsgType::Msg(msg)) => {
+                    counters.received();
+                    result = BufferedWriter::write_framed(&mut writer_mut, &msg, framing);
+                    records += 1;
+                    bytes += msg.len();
+                }
+                Ok(MsgType::Deferred(record)) => {
+                    counters.received();
+                    let line = record.line(colors);
+                    result = BufferedWriter::write_framed(&mut writer_mut, &line, framing);
+                    records += 1;
+                    bytes += line.len();
+                }
+                Ok(other) => {
+                    pending = Some(other);
+                    break;
+                }
+                Err(_) => break,
+            }
+        }
+
+        // The batch stops at the first failure: the records before it were written.
+        counters.written(if result.is_ok() { records } else { records - 1 } as u64);
+        BufferedWriter::check_io(result, io_state, "Unable to write");
+        pending
+    }
+
+    ///
+    /// Writes the record with its framing straight into the buffer with a single write, without assembling the framed record first.
+    /// 
+    fn write_framed(writer: &mut Sink, message: &str, framing: &RecordFraming) -> io::Result<()> {
+        BufferedWriter::with_frame(message, framing, |parts| writer.write_record(parts))
+    }
+
+    ///
+    /// Calls `write` with the parts of the framed record, in order.
+    /// 
+    fn with_frame<R>(message: &str, framing: &RecordFraming, write: impl FnOnce([&[u8]; 3]) -> R) -> R {
+        if framing.binary {
+            let (level, text) = message.split_at(1);
+            write([level.as_bytes(), &varint(text.len()), text.as_bytes()])
+        } else if framing.final_suffix {
+            write([&framing.prefix, message.as_bytes(), &framing.suffix])
+        } else {
+            // The suffix of the previous record, held back in case it was the last one.
+            let previous_suffix: &[u8] = if framing.suffix_pending.swap(true, Ordering::Relaxed) { &framing.suffix } else { &[] };
+            write([previous_suffix, &framing.prefix, message.as_bytes()])
+        }
+    }
+
+    ///
+    /// Writes the color reset sequence, without any line terminator.
+    /// # Panics
+    /// If the RWLock of the BufWriter is poisoned and cannot be taken for writing.
+    /// 
+    fn reset_colors_on_this_thread(buf_writer: &RwLock<Sink>, io_state: &IoState) {
+        if io_state.broken() {
+            return;
+        }
+
+        if let Ok(mut writer_mut) = buf_writer.write() {
+            let result = writer_mut.write_all(ANSI_RESET.as_bytes());
+            BufferedWriter::check_io(result, io_state, "Unable to write");
+        } else {
+            panic!("Cannot get writer as mutable. RWLock is poisoned!");
+        }
+    }
+
+    ///
+    /// Flushes the buf_writer passed on this thread.
+    /// Used to avoid moving of self problems when initializing the separate thread.
+    /// # Panics 
+    /// If the RWLock of the BufWriter is poisoned and cannot be taken for writing.
+    /// 
+    fn flush_on_this_thread(buf_writer: &RwLock<Sink>, io_state: &IoState) {
+        if io_state.broken() {
+            return;
+        }
+
+        if let Ok(mut writer_mut) = buf_writer.write() {
+            let result = writer_mut.flush();
+            BufferedWriter::check_io(result, io_state, "Unable to flush");
+        } else {
+            panic!("Cannot get writer as mutable. RWLock is poisoned!");
+        }
+    }
+
+    ///
+    /// Marks the pipe as broken if the reader went away (e.g. `| head`), so the writer stops writing
+    /// silently instead of panicking.
+    /// Any other IO error (e.g. disk full) is reported on stderr with the given message, see IoState::report():
+    /// the records being written are lost, but logging never makes the program panic.
+    /// 
+    fn check_io(result: io::Result<()>, io_state: &IoState, message: &str) -> bool {
+        let succeeded = result.is_ok();
+        if let Some(report) = io_state.report(result, message, Instant::now()) {
+            eprintln!("{report}");
+        }
+        succeeded
+    }
+}
+
+///
+/// The framed records waiting to be written by a tokio task.
+/// 
+#[cfg(feature = "async-tokio")]
+#[derive(Default)]
+struct TaskBatch {
+    bytes: Vec<u8>,
+    records: u64,
+}
+
+#[cfg(feature = "async-tokio")]
+impl TaskBatch {
+    fn push(&mut self, message: &str, framing: &RecordFraming) {
+        BufferedWriter::with_frame(message, framing, |parts| parts.iter().for_each(|part| self.bytes.extend_from_slice(part)));
+        self.records += 1;
+    }
+
+    ///
+    /// Writes the batch on the file of the tokio task and waits for the write to complete.
+    /// The records are counted as written only if the write succeeds.
+    /// 
+    async fn write(&mut self, file: &mut tokio::fs::File, io_state: &IoState, counters: &Counters) {
+        use tokio::io::AsyncWriteExt;
+
+        if self.bytes.is_empty() {
+            return;
+        }
+        if !io_state.broken() {
+            // A tokio file only hands the bytes to the blocking pool: they're written once flushed.
+            let result = match file.write_all(&self.bytes).await {
+                Ok(()) => file.flush().await,
+                Err(err) => Err(err),
+            };
+            if BufferedWriter::check_io(result, io_state, "Unable to write") {
+                counters.written(self.records);
+            }
+        }
+        self.bytes.clear();
+        self.records = 0;
+    }
+}
+
+///
+/// The state of a writer on the shared thread.
+/// 
+struct SharedSlot {
+    buf_writer: Box<RwLock<Sink>>,
+    colors: bool,
+    framing: RecordFraming,
+    idle_flush: Option<Duration>,
+    io_state: Arc<IoState>,
+    counters: Arc<Counters>,
+    /// Signaled when the writer is stopped, dropped instead if its target panicked.
+    done: Sender<()>,
+    /// Whether the target panicked, its records are dropped from then on.
+    panicked: bool,
+    /// When the last record was written, if not flushed since.
+    written_at: Option<Instant>,
+}
+
+impl SharedSlot {
+    fn write(&mut self, message: &str) {
+        self.counters.received();
+        if BufferedWriter::write_on_this_thread(message, &self.framing, &self.buf_writer, &self.io_state) {
+            self.counters.written(1);
+        }
+        self.counters.update_buffered(&self.buf_writer);
+        if self.idle_flush.is_some() {
+            self.written_at = Some(Instant::now());
+        }
+    }
+
+    fn flush(&mut self) {
+        BufferedWriter::flush_on_this_thread(&self.buf_writer, &self.io_state);
+        self.counters.update_buffered(&self.buf_writer);
+        self.written_at = None;
+    }
+
+    ///
+    /// Returns when the idle flush is due, if any.
+    /// 
+    fn idle_deadline(&self) -> Option<Instant> {
+        Some(self.written_at? + self.idle_flush?)
+    }
+}
+
+///
+/// A thread writing the records of several writers (see [`BufferedWriter::with_shared_thread`]),
+/// received on a single queue with the slot of their writer.
+/// The thread ends when all its writers are cleaned up and this is dropped.
+/// 
+pub struct SharedThread {
+    sender: Option<Sender<(usize, MsgType)>>,
+    /// The writers registered, None once cleaned up.
+    slots: Arc<Mutex<Vec<Option<SharedSlot>>>>,
+    thread_handler: Option<JoinHandle<()>>,
+}
+
+impl SharedThread {
+
+    ///
+    /// Starts the shared thread, with a lowered scheduling priority if `background_priority`. 
+    /// In case of failures returns the error of the thread spawn.
+    /// 
+    pub fn new(background_priority: bool) -> io::Result<SharedThread> {
+        let (sender, receiver) = channel();
+        let slots: Arc<Mutex<Vec<Option<SharedSlot>>>> = Arc::default();
+        let slots_to_move = Arc::clone(&slots);
+
+        match thread::Builder::new().name("rslogger-shared".to_string()).spawn(move || {
+            let _pipeline = PipelineGuard::enter();
+            if background_priority {
+                // The shared thread has no target of its own to break.
+                BufferedWriter::check_io(priority::lower_current_thread(), &IoState::default(), "Unable to lower the priority of the writer thread");
+            }
+            SharedThread::receive(receiver, &slots_to_move)
+        }) {
+            Err(err) => Err(err),
+            Ok(handler) => Ok(SharedThread { sender: Some(sender), slots, thread_handler: Some(handler) }),
+        }
+    }
+
+    ///
+    /// Adds a writer, returning the sender of its records.
+    /// 
+    fn register(&self, slot: SharedSlot) -> QueueSender {
+        let mut slots = self.slots.lock().unwrap_or_else(PoisonError::into_inner);
+        slots.push(Some(slot));
+        QueueSender::Shared(self.sender.clone().unwrap(), slots.len() - 1)
+    }
+
+    ///
+    /// Writes the records received on the slot of their writer, until all the senders are dropped.
+    /// 
+    fn receive(receiver: Receiver<(usize, MsgType)>, slots: &Mutex<Vec<Option<SharedSlot>>>) {
+        loop {
+            let deadline = slots.lock().unwrap_or_else(PoisonError::into_inner)
+                .iter().flatten()
+                .filter_map(SharedSlot::idle_deadline)
+                .min();
+
+            let (index, message) = match deadline {
+                Some(deadline) => match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                    Ok(received) => received,
+                    Err(RecvTimeoutError::Timeout) => {
+                        let now = Instant::now();
+                        let mut slots = slots.lock().unwrap_or_else(PoisonError::into_inner);
+                        for slot in slots.iter_mut().flatten() {
+                            if slot.idle_deadline().is_some_and(|deadline| deadline <= now) {
+                                slot.flush();
+                            }
+                        }
+                        continue;
+                    }
+                    Err(RecvTimeoutError::Disconnected) => break,
+                },
+                None => match receiver.recv() {
+                    Ok(received) => received,
+                    Err(_) => break,
+                },
+            };
+
+            let mut slots = slots.lock().unwrap_or_else(PoisonError::into_inner);
+            let Some(slot) = slots.get_mut(index).and_then(Option::as_mut) else {
+                continue;
+            };
+            let is_record = matches!(message, MsgType::Msg(_) | MsgType::Deferred(_));
+            let stop = matches!(message, MsgType::FlushAndStop);
+
+            if slot.panicked {
+                // The target of this writer panicked: its records are dropped, the other writers go on.
+                if is_record {
+                    slot.counters.received();
+                    slot.counters.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+                if stop {
+                    SharedThread::remove(&mut slots, index);
+                }
+                continue;
+            }
+
+            let result = panic::catch_unwind(AssertUnwindSafe(|| match message {
+                MsgType::Msg(msg) => slot.write(&msg),
+                MsgType::Deferred(record) => slot.write(&record.line(slot.colors)),
+                MsgType::Flush => slot.flush(),
+                MsgType::FlushAndStop => {
+                    if slot.colors {
+                        BufferedWriter::reset_colors_on_this_thread(&slot.buf_writer, &slot.io_state);
+                    }
+                    slot.flush();
+                }
+            }));
+            if result.is_err() {
+                slot.panicked = true;
+                if is_record {
+                    slot.counters.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            if stop {
+                // Signaled once the target is closed.
+                let done = SharedThread::remove(&mut slots, index);
+                if let Some(done) = done {
+                    let _ = done.send(());
+                }
+            }
+        }
+    }
+
+    ///
+    /// Removes the slot, closing its target, and returns its done sender unless the target panicked.
+    /// Otherwise the sender is dropped, telling the writer that its target was not closed cleanly.
+    /// 
+    fn remove(slots: &mut [Option<SharedSlot>], index: usize) -> Option<Sender<()>> {
+        let slot = slots[index].take()?;
+        let (done, panicked) = (slot.done.clone(), slot.panicked);
+        // A panicking target may panic again on close.
+        let closed = panic::catch_unwind(AssertUnwindSafe(|| drop(slot))).is_ok();
+        (closed && !panicked).then_some(done)
+    }
+}
+
+impl Drop for SharedThread {
+    fn drop(&mut self) {
+        self.sender.take();
+        if let Some(handler) = self.thread_handler.take() {
+            let _ = handler.join();
+        }
+    }
+}
+
+impl Drop for BufferedWriter {
+    fn drop(&mut self) {
+        // Nothing to flush if the writer was never initialized (e.g. init failed) or was already cleaned up.
+        if self.buf_writer.is_some() || self.sender.is_some() {
+            self.flush_and_cleanup();
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A sink whose reader went away.
+    struct ClosedPipe;
+
+    impl Write for ClosedPipe {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::Error::from(ErrorKind::BrokenPipe))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Err(io::Error::from(ErrorKind::BrokenPipe))
+        }
+    }
+
+    /// A sink accepting at most 3 bytes per write.
+    struct ShortWrites(Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl Write for ShortWrites {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let written = buf.len().min(3);
+            self.0.lock().unwrap().extend_from_slice(&buf[..written]);
+            Ok(written)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// A sink failing every operation.
+    struct Failing;
+
+    impl Write for Failing {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::Error::other("disk full"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Err(io::Error::other("disk full"))
+        }
+    }
+
+    #[test]
+    fn test_short_writes_are_completed() {
+        let written = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let buf_writer: Box<RwLock<Sink>> = Box::new(RwLock::new(Sink::new(0, FlushPolicy::Buffered, ShortWrites(Arc::clone(&written)))));
+        let io_state = IoState::default();
+
+        BufferedWriter::write_on_this_thread("a line longer than three bytes", &RecordFraming::default(), &buf_writer, &io_state);
+        BufferedWriter::flush_on_this_thread(&buf_writer, &io_state);
+
+        assert_eq!(*written.lock().unwrap(), b"a line longer than three bytes\n");
+    }
+
+    /// A sink recording each write separately.
+    struct RecordedWrites(Arc<std::sync::Mutex<Vec<Vec<u8>>>>);
+
+    impl Write for RecordedWrites {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().push(buf.to_vec());
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_framed_record_written_at_once() {
+        let writes = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut sink = Sink::new(16, FlushPolicy::Buffered, RecordedWrites(Arc::clone(&writes)));
+        let framing = RecordFraming { prefix: b"<".to_vec(), suffix: b">\n".to_vec(), ..RecordFraming::default() };
+
+        for message in ["abcdef", "ghijkl", "longer than the buffer"] {
+            BufferedWriter::write_framed(&mut sink, message, &framing).unwrap();
+        }
+        sink.flush().unwrap();
+
+        // The buffer is flushed before a record that doesn't fit, never in the middle of it.
+        assert_eq!(*writes.lock().unwrap(), [b"<abcdef>\n".to_vec(), b"<ghijkl>\n".to_vec(), b"<longer than the buffer>\n".to_vec()]);
+    }
+
+    #[test]
+    fn test_io_error_does_not_panic() {
+        let buf_writer: Box<RwLock<Sink>> = Box::new(RwLock::new(Sink::new(0, FlushPolicy::Buffered, Failing)));
+        let io_state = IoState::default();
+
+        BufferedWriter::write_on_this_thread("lost", &RecordFraming::default(), &buf_writer, &io_state);
+        BufferedWriter::flush_on_this_thread(&buf_writer, &io_state);
+        assert!(!io_state.broken());
+    }
+
+    #[test]
+    fn test_failed_writes_not_counted() {
+        for writer in [BufferedWriter::new().with_this_thread(), BufferedWriter::new().with_separate_thread()] {
+            let mut writer = writer.on_custom(Failing).with_buffer_capacity(0).init().unwrap();
+            writer.write(&Arc::from("lost"));
+            assert_eq!(writer.flush_and_cleanup().written, 0);
+        }
+
+        let mut writer = BufferedWriter::new().with_separate_thread().on_null().init().unwrap();
+        writer.write(&Arc::from("kept"));
+        assert_eq!(writer.flush_and_cleanup().written, 1);
+    }
+
+    /// A sink blocking the writes until opened.
+    #[derive(Clone, Default)]
+    struct GatedSink {
+        gate: Arc<(Mutex<bool>, std::sync::Condvar)>,
+        /// Set when a write is waiting for the gate.
+        waiting: Arc<AtomicBool>,
+        written: Arc<Mutex<Vec<u8>>>,
+    }
+
+    impl GatedSink {
+        fn open(&self) {
+            *self.gate.0.lock().unwrap() = true;
+            self.gate.1.notify_all();
+        }
+
+        fn wait_for_writer(&self) {
+            while !self.waiting.load(Ordering::Relaxed) {
+                thread::yield_now();
+            }
+        }
+
+        fn written(&self) -> String {
+            String::from_utf8(self.written.lock().unwrap().clone()).unwrap()
+        }
+
+        /// Starts a separate thread writer on the sink, with a queue of 2 records.
+        fn writer(&self, policy: OverflowPolicy) -> BufferedWriter {
+            let mut writer = BufferedWriter::new().with_separate_thread().with_queue_capacity(2).with_overflow_policy(policy);
+            writer.buf_writer = Some(Box::new(RwLock::new(Sink::new(0, FlushPolicy::Buffered, self.clone()))));
+            writer.init_separate_thread().unwrap()
+        }
+    }
+
+    impl Write for GatedSink {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.waiting.store(true, Ordering::Relaxed);
+            let (open, opened) = &*self.gate;
+            let _open = opened.wait_while(open.lock().unwrap(), |open| !*open).unwrap();
+            self.written.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_overflow_block() {
+        let sink = GatedSink::default();
+        let writer = sink.writer(OverflowPolicy::Block);
+        // The first record is stuck in the sink, the next two fill the queue.
+        writer.write(&"1".into());
+        sink.wait_for_writer();
+
+        let fourth_sent = AtomicBool::new(false);
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                for message in ["2", "3", "4"] {
+                    writer.write(&message.into());
+                }
+                fourth_sent.store(true, Ordering::Relaxed);
+            });
+
+            thread::sleep(Duration::from_millis(100));
+            assert!(!fourth_sent.load(Ordering::Relaxed));
+            sink.open();
+        });
+
+        assert_eq!(writer.dropped(), 0);
+        drop(writer);
+        assert_eq!(sink.written(), "1\n2\n3\n4\n");
+    }
+
+    /// A sink panicking on the first write, as a bug in a target would.
+    struct Panicking;
+
+    impl Write for Panicking {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            panic!("broken target");
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_dead_thread_drops_records() {
+        let mut writer = BufferedWriter::new().with_separate_thread();
+        writer.buf_writer = Some(Box::new(RwLock::new(Sink::new(0, FlushPolicy::Buffered, Panicking))));
+        let mut writer = writer.init_separate_thread().unwrap();
+
+        writer.write(&"kills the thread".into());
+        let start = Instant::now();
+        while writer.dropped() == 0 && start.elapsed() < Duration::from_secs(5) {
+            writer.write(&"dropped".into());
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert!(writer.dropped() > 0);
+
+        let stats = writer.flush_and_cleanup();
+        assert!(!stats.joined);
+    }
+
+    #[test]
+    fn test_shared_thread_survives_panicking_target() {
+        let shared_thread = Arc::new(SharedThread::new(false).unwrap());
+        let mut broken = BufferedWriter::new().with_shared_thread(Arc::clone(&shared_thread));
+        broken.buf_writer = Some(Box::new(RwLock::new(Sink::new(0, FlushPolicy::Buffered, Panicking))));
+        let mut broken = broken.init_shared_thread().unwrap();
+        let writes = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut healthy = BufferedWriter::new().with_shared_thread(Arc::clone(&shared_thread));
+        healthy.buf_writer = Some(Box::new(RwLock::new(Sink::new(0, FlushPolicy::Buffered, RecordedWrites(Arc::clone(&writes))))));
+        let mut healthy = healthy.init_shared_thread().unwrap();
+
+        broken.write(&"kills the target".into());
+        broken.write(&"dropped".into());
+        healthy.write(&"still written".into());
+
+        let stats = broken.flush_and_cleanup();
+        assert_eq!((stats.written, stats.dropped, stats.joined), (0, 2, false));
+        let stats = healthy.flush_and_cleanup();
+        assert_eq!((stats.written, stats.dropped, stats.joined), (1, 0, true));
+        assert_eq!(writes.lock().unwrap().concat(), b"still written\n");
+    }
+
+    #[test]
+    fn test_overflow_drop_newest() {
+        let sink = GatedSink::default();
+        let mut writer = sink.writer(OverflowPolicy::DropNewest);
+        writer.write(&"1".into());
+        sink.wait_for_writer();
+
+        for message in ["2", "3", "4", "5", "6"] {
+            writer.write(&message.into());
+        }
+        assert_eq!(writer.dropped(), 3);
+
+        sink.open();
+        let stats = writer.flush_and_cleanup();
+        assert_eq!((stats.written, stats.dropped, stats.joined), (3, 3, true));
+        assert_eq!(sink.written(), "1\n2\n3\n");
+    }
+
+    #[test]
+    fn test_overflow_drop_oldest() {
+        let sink = GatedSink::default();
+        let writer = sink.writer(OverflowPolicy::DropOldest);
+        writer.write(&"1".into());
+        sink.wait_for_writer();
+
+        // 2 and 3 are queued, 4 and 5 wait in the ring and are pushed out by 6 and 7.
+        for message in ["2", "3", "4", "5", "6", "7"] {
+            writer.write(&message.into());
+        }
+        assert_eq!(writer.dropped(), 2);
+
+        sink.open();
+        // The flush drains the ring.
+        drop(writer);
+        assert_eq!(sink.written(), "1\n2\n3\n6\n7\n");
+    }
+
+    #[test]
+    fn test_queue_watermarks() {
+        let writer = BufferedWriter::new().with_queue_watermarks(3, 1);
+
+        writer.counters.queued.store(4, Ordering::Relaxed);
+        assert_eq!(writer.queue_watermark(), Some(QueueWatermark::High(4)));
+        writer.counters.queued.store(5, Ordering::Relaxed);
+        assert_eq!(writer.queue_watermark(), None);
+        writer.counters.queued.store(2, Ordering::Relaxed);
+        assert_eq!(writer.queue_watermark(), None);
+        writer.counters.queued.store(1, Ordering::Relaxed);
+        assert_eq!(writer.queue_watermark(), Some(QueueWatermark::Low(1)));
+        writer.counters.queued.store(0, Ordering::Relaxed);
+        assert_eq!(writer.queue_watermark(), None);
+    }
+
+    #[test]
+    fn test_without_final_suffix() {
+        let written = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut buf_writer: Box<Sink> = Box::new(Sink::from(SinkBuffer::Buffered(BufWriter::new(Box::new(ShortWrites(Arc::clone(&written)))))));
+        let framing = RecordFraming { final_suffix: false, ..RecordFraming::default() };
+
+        for message in ["first", "second", "third"] {
+            BufferedWriter::write_framed(&mut buf_writer, message, &framing).unwrap();
+        }
+        buf_writer.flush().unwrap();
+        assert_eq!(*written.lock().unwrap(), b"first\nsecond\nthird");
+    }
+
+    /// A sink recording the name of the thread writing on it.
+    struct ThreadNameSink(Arc<Mutex<Option<String>>>);
+
+    impl Write for ThreadNameSink {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            *self.0.lock().unwrap() = thread::current().name().map(str::to_string);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_thread_names() {
+        let name = Arc::new(Mutex::new(None));
+        let mut writer = BufferedWriter::new().on_stdout().with_separate_thread().with_buffer_capacity(0);
+        writer.buf_writer = Some(Box::new(RwLock::new(Sink::new(0, FlushPolicy::Buffered, ThreadNameSink(Arc::clone(&name))))));
+        let mut writer = writer.init_separate_thread().unwrap();
+        writer.write(&"record".into());
+        writer.flush_and_cleanup();
+        assert_eq!(name.lock().unwrap().as_deref(), Some("rslogger-stdout"));
+
+        let file = BufferedWriter::new().on_file(PathBuf::from("logs/application.log"));
+        assert_eq!(file.thread_name(), "rslog:applicati");
+        let accented = BufferedWriter::new().on_file(PathBuf::from("logs/résumé.log"));
+        assert_eq!(accented.thread_name(), "rslog:résumé.");
+        assert_eq!(file.with_thread_name("custom".to_string()).thread_name(), "custom");
+    }
+
+    #[test]
+    fn test_batch_stops_at_control_message() {
+        let written = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let buf_writer: Box<RwLock<Sink>> = Box::new(RwLock::new(Sink::from(SinkBuffer::Buffered(BufWriter::new(Box::new(ShortWrites(Arc::clone(&written))))))));
+        let io_state = IoState::default();
+        let (sender, receiver) = channel();
+
+        sender.send(MsgType::Msg("second".into())).unwrap();
+        sender.send(MsgType::Flush).unwrap();
+        sender.send(MsgType::Msg("third".into())).unwrap();
+
+        // The first record was received by the caller, the second one is still counted as queued.
+        let counters = Counters { queued: AtomicUsize::new(1), ..Counters::default() };
+        let pending = BufferedWriter::write_batch("first", &receiver, false, &RecordFraming::default(), &buf_writer, &io_state, &counters);
+        assert!(matches!(pending, Some(MsgType::Flush)));
+        assert_eq!(counters.queued.load(Ordering::Relaxed), 0);
+        assert_eq!(counters.written.load(Ordering::Relaxed), 2);
+
+        BufferedWriter::flush_on_this_thread(&buf_writer, &io_state);
+        assert_eq!(*written.lock().unwrap(), b"first\nsecond\n");
+    }
+
+    #[test]
+    fn test_buffer_records() {
+        let path = std::env::temp_dir().join(format!("rslogger-buffer-records-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let writer = BufferedWriter::new()
+            .on_file(path.clone())
+            .with_buffer_capacity(BufferCapacity::Records(3))
+            .init()
+            .unwrap();
+        let lines = || std::fs::read_to_string(&path).unwrap().lines().count();
+
+        writer.write(&Arc::from("first"));
+        writer.write(&Arc::from("second"));
+        assert_eq!(lines(), 0);
+        writer.write(&Arc::from("third"));
+        assert_eq!(lines(), 3);
+        writer.write(&Arc::from("fourth"));
+        assert_eq!(lines(), 3);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_flush_every_record() {
+        assert_eq!(BufferedWriter::new().on_stdout().flush_policy(), FlushPolicy::EveryRecord);
+        assert_eq!(BufferedWriter::new().on_file(PathBuf::from("app.log")).flush_policy(), FlushPolicy::Buffered);
+
+        let written = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let buf_writer = RwLock::new(Sink::new(1024, FlushPolicy::EveryRecord, ShortWrites(Arc::clone(&written))));
+        let io_state = IoState::default();
+
+        BufferedWriter::write_on_this_thread("first", &RecordFraming::default(), &buf_writer, &io_state);
+        assert_eq!(*written.lock().unwrap(), b"first\n");
+        BufferedWriter::write_on_this_thread("second", &RecordFraming::default(), &buf_writer, &io_state);
+        assert_eq!(*written.lock().unwrap(), b"first\nsecond\n");
+    }
+
+    #[test]
+    fn test_flush_every_n_records() {
+        for separate_thread in [false, true] {
+            let path = std::env::temp_dir().join(format!("rslogger-every-n-{separate_thread}-{}.log", std::process::id()));
+            let _ = std::fs::remove_file(&path);
+            let mut writer = BufferedWriter::new()
+                .on_file(path.clone())
+                .with_buffer_capacity(10000)
+                .with_flush_policy(FlushPolicy::EveryN(3));
+            if separate_thread {
+                writer = writer.with_separate_thread();
+            }
+            let mut writer = writer.init().unwrap();
+            // Waits for the separate thread to write the records already sent.
+            let tail = |expected: usize| {
+                let start = Instant::now();
+                let mut lines = std::fs::read_to_string(&path).unwrap().lines().count();
+                while lines != expected && start.elapsed() < Duration::from_secs(5) {
+                    thread::sleep(Duration::from_millis(1));
+                    lines = std::fs::read_to_string(&path).unwrap().lines().count();
+                }
+                lines
+            };
+
+            for (record, visible) in [(1, 0), (2, 0), (3, 3), (4, 3), (5, 3), (6, 6), (7, 6)] {
+                writer.write(&format!("record {record}").into());
+                assert_eq!(tail(visible), visible);
+            }
+            writer.flush_and_cleanup();
+            let _ = std::fs::remove_file(&path);
+        }
+    }
+
+    /// A sink counting the data syncs and the full syncs.
+    #[derive(Default)]
+    struct CountingSync(Arc<AtomicUsize>, Arc<AtomicUsize>);
+
+    impl Write for CountingSync {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SyncData for CountingSync {
+        fn sync_data(&self) -> io::Result<()> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            Ok(())
+        }
+
+        fn sync_all(&self) -> io::Result<()> {
+            self.1.fetch_add(1, Ordering::Relaxed);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_sync_policies() {
+        let io_state = IoState::default();
+        for (sync, flush, expected) in [
+            (SyncPolicy::Never, FlushPolicy::Buffered, (0, 0)),
+            (SyncPolicy::OnFlush, FlushPolicy::Buffered, (1, 0)),
+            (SyncPolicy::FullOnFlush, FlushPolicy::Buffered, (0, 1)),
+            (SyncPolicy::EveryRecord, FlushPolicy::EveryRecord, (3, 0)),
+        ] {
+            let writer = BufferedWriter::new().on_file(PathBuf::from("audit.log")).with_sync(sync);
+            assert_eq!(writer.flush_policy(), flush);
+
+            let (data_syncs, full_syncs) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+            let synced = Synced { inner: CountingSync(Arc::clone(&data_syncs), Arc::clone(&full_syncs)), policy: sync };
+            let buf_writer = RwLock::new(Sink::new(1024, writer.flush_policy(), synced));
+            for message in ["first", "second"] {
+                BufferedWriter::write_on_this_thread(message, &RecordFraming::default(), &buf_writer, &io_state);
+            }
+            BufferedWriter::flush_on_this_thread(&buf_writer, &io_state);
+            assert_eq!((data_syncs.load(Ordering::Relaxed), full_syncs.load(Ordering::Relaxed)), expected, "{sync:?}");
+        }
+    }
+
+    #[test]
+    fn test_sync_ignored_on_stdout() {
+        let writer = BufferedWriter::new().on_stdout().with_buffer_capacity(16).with_fsync().init().unwrap();
+        assert_eq!(writer.init_warnings(), ["the sync policy FullOnFlush only applies to the file writers, it's ignored"]);
+
+        let writer = BufferedWriter::new().on_file(PathBuf::from("audit.log")).with_fsync();
+        assert_eq!(writer.sync, SyncPolicy::FullOnFlush);
+    }
+
+    #[test]
+    fn test_expand_placeholders() {
+        let expand = |path: &str| expand_path(Path::new(path)).map(|path| path.to_str().unwrap().to_string());
+        assert_eq!(expand("logs/app.log").unwrap(), "logs/app.log");
+        assert_eq!(expand("logs/app-{pid}.log").unwrap(), format!("logs/app-{}.log", std::process::id()));
+        assert_eq!(expand("{hostname}.log").unwrap(), format!("{}.log", hostname()));
+        assert!(!hostname().is_empty());
+        assert_eq!(expand("{{pid}}-{{}}.log").unwrap(), "{pid}-{}.log");
+
+        // Digits separated by dashes: `2024-06-03` and `14-00-00`.
+        let shape = |text: &str| text.chars().map(|c| if c.is_ascii_digit() { '0' } else { c }).collect::<String>();
+        assert_eq!(shape(&expand("{date}").unwrap()), "0000-00-00");
+        assert_eq!(shape(&expand("{time}").unwrap()), "00-00-00");
+
+        assert_eq!(expand("app-{user}.log").unwrap_err(), 
+            "unknown placeholder `{user}` in `app-{user}.log`, expected {pid}, {hostname}, {date} or {time}");
+        for unmatched in ["app-{pid.log", "app}.log", "app-{.log"] {
+            assert!(expand(unmatched).unwrap_err().starts_with("unmatched brace"), "{unmatched}");
+        }
+
+        let dir = rotation_dir("placeholders");
+        let mut writer = BufferedWriter::new().on_file(dir.join("app-{pid}.log")).init().unwrap();
+        writer.flush_and_cleanup();
+        assert!(dir.join(format!("app-{}.log", std::process::id())).exists());
+        assert!(BufferedWriter::new().on_file(dir.join("app-{user}.log")).init().is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_expand_variables() {
+        let expand = |path: &str| expand_path(Path::new(path)).map(|path| path.to_str().unwrap().to_string());
+        let home = std::env::var(if cfg!(windows) { "USERPROFILE" } else { "HOME" }).unwrap();
+        let path = std::env::var("PATH").unwrap();
+
+        assert_eq!(expand("~/logs/app.log").unwrap(), format!("{home}/logs/app.log"));
+        assert_eq!(expand("~").unwrap(), home);
+        assert_eq!(expand("logs/~/app~.log").unwrap(), "logs/~/app~.log");
+        assert_eq!(expand("$PATH/app.log").unwrap(), format!("{path}/app.log"));
+        assert_eq!(expand("${PATH}_{pid}.log").unwrap(), format!("{path}_{}.log", std::process::id()));
+        assert_eq!(expand("app$$.log").unwrap(), "app$.log");
+
+        assert_eq!(expand("$RSLOGGER_UNSET_VARIABLE/app.log").unwrap_err(),
+            "the environment variable `RSLOGGER_UNSET_VARIABLE` of `$RSLOGGER_UNSET_VARIABLE/app.log` is not set");
+        assert!(expand("${PATH/app.log").unwrap_err().starts_with("unmatched brace"));
+        assert!(expand("app-$.log").unwrap_err().starts_with("missing variable name"));
+        assert!(BufferedWriter::new().on_file(PathBuf::from("$RSLOGGER_UNSET_VARIABLE/app.log")).init().is_err());
+    }
+
+    #[test]
+    fn test_same_destination_expanded() {
+        let home = PathBuf::from(std::env::var(if cfg!(windows) { "USERPROFILE" } else { "HOME" }).unwrap());
+        let existing = BufferedWriter::new().on_file(home.join("rslogger-missing").join("app.log"));
+        assert!(existing.same_destination(&BufferedWriter::new().on_file(PathBuf::from("~/rslogger-missing/app.log"))));
+        assert!(!existing.same_destination(&BufferedWriter::new().on_file(PathBuf::from("~/rslogger-missing/other.log"))));
+        assert!(!existing.same_destination(&BufferedWriter::new().on_file(PathBuf::from("$RSLOGGER_UNSET_VARIABLE/app.log"))));
+    }
+
+    #[test]
+    fn test_exclusive_lock() {
+        let dir = rotation_dir("exclusive");
+        let path = dir.join("app.log");
+        let exclusive = || BufferedWriter::new().on_file(path.clone()).with_exclusive_lock(true).with_open_mode(OpenMode::Truncate).init();
+        let mut writer = exclusive().unwrap();
+        writer.write(&"first process".into());
+        writer.flush();
+
+        // Not truncated by the second writer.
+        let err = exclusive().err().unwrap();
+        assert!(matches!(&err, Error::Io { path: err_path, source } if *err_path == path && source.kind() == ErrorKind::WouldBlock), "{err}");
+        assert!(err.to_string().contains("is in use by another process"), "{err}");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "first process\n");
+
+        // Released on drop.
+        writer.flush_and_cleanup();
+        drop(writer);
+        exclusive().unwrap().flush_and_cleanup();
+
+        // Taken again on the file started by the rotation.
+        let file = File::options().append(true).open(&path).unwrap();
+        file.try_lock().unwrap();
+        let mut rotating = Rotating::new(path.clone(), RotationPolicy::Daily, RotatedNaming::Period, Backups::default(), Synced { inner: file, policy: SyncPolicy::Never }, Vec::new())
+            .with_exclusive_lock(true);
+        end_period(&mut rotating, "2000-01-01");
+        rotating.write_all(b"record\n").unwrap();
+        assert!(matches!(File::open(&path).unwrap().try_lock(), Err(fs::TryLockError::WouldBlock)));
+        assert!(File::open(dir.join("app.2000-01-01.log")).unwrap().try_lock().is_ok());
+
+        // The closed file is not renamed when the new one can't be locked.
+        let next = File::create(with_suffix(&path, ".next")).unwrap();
+        next.try_lock().unwrap();
+        end_period(&mut rotating, "2000-01-02");
+        rotating.write_all(b"kept\n").unwrap();
+        rotating.flush().unwrap();
+        assert!(!dir.join("app.2000-01-02.log").exists());
+        assert!(!with_suffix(&path, ".next").exists());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "record\nkept\n");
+        assert!(matches!(File::open(&path).unwrap().try_lock(), Err(fs::TryLockError::WouldBlock)));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_stdout_lock_per_line() {
+        let mut writer = BufferedWriter::new().on_stdout().with_buffer_capacity(4096).with_stdout_lock_per_line().init().unwrap();
+        assert!(matches!(writer.buf_writer.as_ref().unwrap().read().unwrap().buffer, SinkBuffer::Unbuffered { .. }));
+        writer.flush_and_cleanup();
+    }
+
+    /// Ends the period of the file, `period` being the one of its records, as the sink does at the first record of the next period.
+    fn end_period(rotating: &mut Rotating, period: &str) {
+        rotating.period = period.to_string();
+        rotating.next_period = SystemTime::UNIX_EPOCH;
+        rotating.period_end.reached.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns an empty directory for the files of a rotation test.
+    fn rotation_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rslogger-rotation-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_rotation() {
+        let dir = rotation_dir("period");
+        let path = dir.join("app.log");
+        let file = Synced { inner: File::create(&path).unwrap(), policy: SyncPolicy::Never };
+        let mut rotating = Rotating::new(path.clone(), RotationPolicy::Daily, RotatedNaming::Period, Backups::default(), file, b"header\n".to_vec());
+        rotating.write_all(b"first\n").unwrap();
+
+        // The next period started.
+        end_period(&mut rotating, "2000-01-01");
+        rotating.write_all(b"second\n").unwrap();
+        assert_eq!(fs::read_to_string(dir.join("app.2000-01-01.log")).unwrap(), "first\n");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "header\nsecond\n");
+        assert!(rotating.next_period > SystemTime::now());
+
+        // The clock went backwards.
+        end_period(&mut rotating, "2999-01-01");
+        rotating.write_all(b"third\n").unwrap();
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "header\nsecond\nthird\n");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_rotation_at_record_boundary() {
+        let dir = rotation_dir("boundary");
+        let path = dir.join("app.log");
+        let file = Synced { inner: File::create(&path).unwrap(), policy: SyncPolicy::Never };
+        let mut rotating = Rotating::new(path.clone(), RotationPolicy::Daily, RotatedNaming::Period, Backups::default(), file, Vec::new());
+        rotating.period = "2000-01-01".to_string();
+        rotating.next_period = SystemTime::UNIX_EPOCH;
+        let period_end = Arc::clone(&rotating.period_end);
+        let mut sink = Sink::new(1024, FlushPolicy::Buffered, rotating).with_period_end(Some(Arc::clone(&period_end)));
+        sink.write_record([b"", b"first", b"\n"]).unwrap();
+
+        // The next period starts with the second record, buffered with the first.
+        period_end.set(SystemTime::UNIX_EPOCH);
+        sink.write_record([b"", b"second", b"\n"]).unwrap();
+        sink.flush().unwrap();
+        assert_eq!(fs::read_to_string(dir.join("app.2000-01-01.log")).unwrap(), "first\n");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second\n");
+        assert!(!period_end.passed());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_latest_link() {
+        let dir = rotation_dir("latest");
+        let path = dir.join("app.log");
+        let link = dir.join("latest.log");
+        let mut writer = BufferedWriter::new().on_file(path.clone()).with_latest_link("latest.log").init().unwrap();
+        writer.write(&"first".into());
+        writer.flush_and_cleanup();
+        assert_eq!(fs::read_link(&link).unwrap(), PathBuf::from("app.log"));
+        assert_eq!(fs::read_to_string(&link).unwrap(), "first\n");
+
+        let file = Synced { inner: fs::OpenOptions::new().append(true).open(&path).unwrap(), policy: SyncPolicy::Never };
+        let mut rotating = Rotating::new(path.clone(), RotationPolicy::Daily, RotatedNaming::Period, Backups::default(), file, Vec::new())
+            .with_latest_link(Some(link.clone()));
+        for (period, record) in [("2000-01-01", "second\n"), ("2000-01-02", "third\n")] {
+            end_period(&mut rotating, period);
+            rotating.write_all(record.as_bytes()).unwrap();
+            rotating.flush().unwrap();
+            assert_eq!(fs::canonicalize(&link).unwrap(), fs::canonicalize(&path).unwrap());
+            assert_eq!(fs::read_to_string(&link).unwrap(), record);
+        }
+        assert!(!dir.join("latest.log.tmp").exists());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_timestamp_naming() {
+        let dir = rotation_dir("timestamp");
+        let path = dir.join("app.log");
+        let file = Synced { inner: File::create(&path).unwrap(), policy: SyncPolicy::Never };
+        let mut rotating = Rotating::new(path.clone(), RotationPolicy::Hourly, RotatedNaming::Timestamp, Backups::default(), file, Vec::new());
+
+        // Rotated twice within the same second: the second file gets a counter.
+        let cut = SystemTime::UNIX_EPOCH + Duration::from_secs(1_717_423_200);
+        for record in ["first\n", "second\n"] {
+            rotating.write_all(record.as_bytes()).unwrap();
+            rotating.period = "2000-01-01-00".to_string();
+            rotating.next_period = SystemTime::UNIX_EPOCH;
+            rotating.rotate_if_needed(cut).unwrap();
+        }
+        rotating.write_all(b"third\n").unwrap();
+
+        let label = RotatedNaming::Timestamp.label("", cut);
+        let backups = list_backups(&path).unwrap();
+        // The newest first.
+        assert_eq!(backups.iter().map(|backup| backup.2.clone()).collect::<Vec<_>>(), [
+            dir.join(format!("app.{label}.1.log")), dir.join(format!("app.{label}.log")),
+        ]);
+        assert_eq!(fs::read_to_string(&backups[0].2).unwrap(), "second\n");
+        assert_eq!(fs::read_to_string(&backups[1].2).unwrap(), "first\n");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "third\n");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_rotation_at_init() {
+        let dir = rotation_dir("init");
+        let path = dir.join("app.log");
+        let yesterday = SystemTime::now() - Duration::from_secs(24 * 60 * 60);
+        fs::write(&path, "yesterday\n").unwrap();
+        File::options().append(true).open(&path).unwrap().set_modified(yesterday).unwrap();
+
+        let mut writer = BufferedWriter::new().on_file(path.clone()).with_rotation(RotationPolicy::Daily).init().unwrap();
+        writer.write(&"today".into());
+        writer.flush_and_cleanup();
+
+        let rotated = dir.join(format!("app.{}.log", RotationPolicy::Daily.period(yesterday).0));
+        assert_eq!(fs::read_to_string(rotated).unwrap(), "yesterday\n");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "today\n");
+
+        // Named after its last write with the Timestamp naming.
+        File::options().append(true).open(&path).unwrap().set_modified(yesterday).unwrap();
+        rotate_stale(&path, RotationPolicy::Daily, RotatedNaming::Timestamp).unwrap();
+        let rotated = dir.join(format!("app.{}.log", RotatedNaming::Timestamp.label("", yesterday)));
+        assert_eq!(fs::read_to_string(rotated).unwrap(), "today\n");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_max_backups() {
+        let dir = rotation_dir("backups");
+        let path = dir.join("app.log");
+        fs::write(dir.join("other.2000-01-01.log"), "").unwrap();
+        fs::write(dir.join("app.notes.log"), "").unwrap();
+        let file = Synced { inner: File::create(&path).unwrap(), policy: SyncPolicy::Never };
+        let backups = Backups { max_backups: Some(2), ..Backups::default() };
+        let mut rotating = Rotating::new(path.clone(), RotationPolicy::Hourly, RotatedNaming::Period, backups, file, Vec::new());
+
+        for hour in 0..5 {
+            end_period(&mut rotating, &format!("2000-01-01-0{hour}"));
+            rotating.write_all(b"record\n").unwrap();
+            // The log file, the backups and the 2 unrelated files.
+            assert!(fs::read_dir(&dir).unwrap().count() <= 2 + 1 + 2);
+        }
+        let mut names: Vec<_> = fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().file_name().into_string().unwrap()).collect();
+        names.sort();
+        assert_eq!(names, ["app.2000-01-01-03.log", "app.2000-01-01-04.log", "app.log", "app.notes.log", "other.2000-01-01.log"]);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_max_age() {
+        let dir = rotation_dir("age");
+        let path = dir.join("app.log");
+        for (name, age) in [("app.2000-01-01.log", 3), ("app.2000-01-02.log", 1)] {
+            fs::write(dir.join(name), "").unwrap();
+            let modified = SystemTime::now() - Duration::from_secs(age * 24 * 60 * 60);
+            File::options().append(true).open(dir.join(name)).unwrap().set_modified(modified).unwrap();
+        }
+
+        Backups { max_age: Some(Duration::from_secs(2 * 24 * 60 * 60)), ..Backups::default() }.prune(&path, &Notices::default());
+        assert!(!dir.join("app.2000-01-01.log").exists());
+        assert!(dir.join("app.2000-01-02.log").exists());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_prune_failures_notices() {
+        let dir = rotation_dir("prune-failures");
+        let path = dir.join("app.log");
+        // A directory can't be deleted as a file.
+        fs::create_dir(dir.join("app.2000-01-01.log")).unwrap();
+        fs::write(dir.join("app.2000-01-02.log"), "").unwrap();
+        let notices = Notices::default();
+
+        let backups = Backups { max_backups: Some(0), ..Backups::default() };
+        backups.prune(&path, &notices);
+        assert!(!dir.join("app.2000-01-02.log").exists());
+        let taken = notices.take();
+        assert_eq!(taken.len(), 1);
+        assert!(taken[0].starts_with(&format!("unable to delete the rotated file {}: ", dir.join("app.2000-01-01.log").display())), "{}", taken[0]);
+
+        backups.prune(&dir.join("missing").join("app.log"), &notices);
+        let taken = notices.take();
+        assert_eq!(taken.len(), 1);
+        assert!(taken[0].starts_with(&format!("unable to list the rotated files in {}: ", dir.join("missing").display())), "{}", taken[0]);
+        assert!(notices.take().is_empty());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_compression() {
+        let dir = rotation_dir("gzip");
+        let path = dir.join("app.log");
+        // Left by a previous run, as well as a compression interrupted before its end.
+        fs::write(dir.join("app.2000-01-01.log"), "first day\n").unwrap();
+        fs::write(dir.join("app.2000-01-01.log.gz.partial"), "").unwrap();
+        let file = Synced { inner: File::create(&path).unwrap(), policy: SyncPolicy::Never };
+        let backups = Backups { max_backups: Some(2), compress: true, ..Backups::default() };
+        let mut rotating = Rotating::new(path.clone(), RotationPolicy::Daily, RotatedNaming::Period, backups, file, Vec::new());
+
+        for day in 2..5 {
+            end_period(&mut rotating, &format!("2000-01-0{day}"));
+            rotating.write_all(b"record\n").unwrap();
+        }
+        let names = || {
+            let mut names: Vec<_> = fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().file_name().into_string().unwrap()).collect();
+            names.sort();
+            names
+        };
+        let expected = ["app.2000-01-03.log.gz", "app.2000-01-04.log.gz", "app.log"];
+        let start = Instant::now();
+        while names() != expected && start.elapsed() < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(names(), expected);
+        assert_eq!(fs::read(dir.join("app.2000-01-04.log.gz")).unwrap()[..2], [0x1F, 0x8B]);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_open_mode() {
+        let dir = rotation_dir("open-mode");
+        let path = dir.join("app.log");
+        for (mode, expected) in [(OpenMode::Append, "previous run\nrecord\n"), (OpenMode::Truncate, "record\n")] {
+            fs::write(&path, "previous run\n").unwrap();
+            let mut writer = BufferedWriter::new().on_file(path.clone()).with_open_mode(mode).init().unwrap();
+            writer.write(&"record".into());
+            writer.flush_and_cleanup();
+            assert_eq!(fs::read_to_string(&path).unwrap(), expected);
+        }
+
+        let err = BufferedWriter::new().on_file(path.clone())
+            .with_open_mode(OpenMode::Truncate)
+            .with_rotation(RotationPolicy::Daily)
+            .init().err().unwrap();
+        assert!(matches!(&err, Error::InvalidConfig(reason) if reason.contains("can't be combined with the rotation policy Daily")), "{err}");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "record\n");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_varint() {
+        assert_eq!(varint(0), [0]);
+        assert_eq!(varint(127), [0x7F]);
+        assert_eq!(varint(128), [0x80, 0x01]);
+        assert_eq!(varint(300), [0xAC, 0x02]);
+        assert_eq!(varint(1 << 21), [0x80, 0x80, 0x80, 0x01]);
+    }
+
+    #[test]
+    fn test_open_retries() {
+        let root = rotation_dir("open-retries");
+        let dir = root.join("mounted");
+        let path = dir.join("app.log");
+        assert!(BufferedWriter::new().on_file(path.clone()).with_create_dirs(false).init().is_err());
+        let err = BufferedWriter::new().on_file(path.clone()).with_create_dirs(false)
+            .with_open_retries(2, Duration::from_millis(1))
+            .init().err().unwrap();
+        assert!(err.to_string().contains("after 3 attempts"), "{err}");
+        assert!(matches!(&err, Error::Io { source, .. } if source.kind() == ErrorKind::NotFound), "{err}");
+
+        // The directory shows up while retrying.
+        let mount = std::thread::spawn({
+            let dir = dir.clone();
+            move || {
+                std::thread::sleep(Duration::from_millis(30));
+                fs::create_dir(dir).unwrap();
+            }
+        });
+        let mut writer = BufferedWriter::new().on_file(path.clone()).with_create_dirs(false)
+            .with_open_retries(8, Duration::from_millis(10))
+            .init().unwrap();
+        mount.join().unwrap();
+        writer.write(&"written".into());
+        writer.flush_and_cleanup();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "written\n");
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_file_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = rotation_dir("file-mode");
+        let path = dir.join("app.log");
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        let mut writer = BufferedWriter::new().on_file(path.clone()).with_file_mode(0o600).init().unwrap();
+        writer.flush_and_cleanup();
+        assert_eq!(mode(&path), 0o600);
+
+        let file = Synced { inner: fs::OpenOptions::new().append(true).open(&path).unwrap(), policy: SyncPolicy::Never };
+        let mut rotating = Rotating::new(path.clone(), RotationPolicy::Daily, RotatedNaming::Period, Backups::default(), file, Vec::new())
+            .with_file_mode(Some(0o600));
+        end_period(&mut rotating, "2000-01-01");
+        rotating.write_all(b"record\n").unwrap();
+        assert_eq!(mode(&path), 0o600);
+        assert_eq!(mode(&dir.join("app.2000-01-01.log")), 0o600);
+
+        gzip_file(&dir.join("app.2000-01-01.log")).unwrap();
+        assert_eq!(mode(&dir.join("app.2000-01-01.log.gz")), 0o600);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_dir_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = rotation_dir("dir-mode");
+        let dir = root.join("group").join("logs");
+        let mut writer = BufferedWriter::new().on_file(dir.join("app.log")).with_dir_mode(0o750).init().unwrap();
+        writer.flush_and_cleanup();
+
+        for dir in [root.join("group"), dir] {
+            assert_eq!(fs::metadata(dir).unwrap().permissions().mode() & 0o777, 0o750);
+        }
+        assert_ne!(fs::metadata(&root).unwrap().permissions().mode() & 0o777, 0o750);
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_rotated_period() {
+        let path = Path::new("logs/app.log");
+        assert_eq!(rotated_period(path, "app.2024-06-03.log"), Some(("2024-06-03".to_string(), 0)));
+        assert_eq!(rotated_period(path, "app.2024-06-03-14.2.log"), Some(("2024-06-03-14".to_string(), 2)));
+        assert_eq!(rotated_period(path, "app.2024-06-03.log.gz"), Some(("2024-06-03".to_string(), 0)));
+        assert_eq!(rotated_period(path, "app.2024-06-03T14-00-00.1.log.gz"), Some(("2024-06-03T14-00-00".to_string(), 1)));
+        for name in ["app.log", "app.2024-06-03.txt", "app.2024-6-3.log", "app.2024-06-03.+1.log", "other.2024-06-03.log", 
+            "app.2024-06-03.log.gz.partial", "app.2024-06-03T14:00:00.log", "app.2024-06-03-14-00-00.log"] {
+            assert_eq!(rotated_period(path, name), None, "{name}");
+        }
+    }
+
+    #[test]
+    fn test_rotated_path() {
+        let dir = rotation_dir("path");
+        assert_eq!(rotated_path(&dir.join("app.log"), "2024-06-03-14"), dir.join("app.2024-06-03-14.log"));
+        assert_eq!(rotated_path(&dir.join("app"), "2024-06-03"), dir.join("app.2024-06-03"));
+
+        fs::write(dir.join("app.2024-06-03.log"), "").unwrap();
+        assert_eq!(rotated_path(&dir.join("app.log"), "2024-06-03"), dir.join("app.2024-06-03.1.log"));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_buffered_len() {
+        for separate_thread in [false, true] {
+            let mut writer = BufferedWriter::new().on_null().with_buffer_capacity(1024).with_flush_policy(FlushPolicy::Buffered);
+            if separate_thread {
+                writer = writer.with_separate_thread();
+            }
+            let mut writer = writer.init().unwrap();
+            // Waits for the separate thread to handle the messages already sent.
+            let buffered_len = |expected: usize| {
+                let start = Instant::now();
+                while writer.buffered_len() != expected && start.elapsed() < Duration::from_secs(5) {
+                    thread::sleep(Duration::from_millis(1));
+                }
+                writer.buffered_len()
+            };
+
+            writer.write(&"record".into());
+            assert_eq!(buffered_len(7), 7);
+            writer.flush();
+            assert_eq!(buffered_len(0), 0);
+            writer.flush_and_cleanup();
+        }
+    }
+
+    #[test]
+    fn test_block_aligned_writes() {
+        let mut aligned = BlockAligned { inner: Vec::new(), block_size: 4, pending: Vec::new() };
+
+        aligned.write_all(b"abc").unwrap();
+        assert!(aligned.inner.is_empty());
+
+        aligned.write_all(b"defghi").unwrap();
+        assert_eq!(aligned.inner, b"abcdefgh");
+
+        aligned.flush().unwrap();
+        assert_eq!(aligned.inner, b"abcdefghi");
+    }
+
+    #[test]
+    fn test_io_failures_reported_once_per_interval() {
+        let io_state = IoState::default();
+        let start = Instant::now();
+        let failure = || Err(io::Error::other("disk full"));
+
+        assert_eq!(io_state.report(Ok(()), "Unable to write", start), None);
+        assert_eq!(io_state.report(failure(), "Unable to write", start).as_deref(), Some("Unable to write: disk full"));
+        assert_eq!(io_state.report(failure(), "Unable to write", start + Duration::from_secs(1)), None);
+        assert_eq!(io_state.report(failure(), "Unable to flush", start + Duration::from_secs(2)), None);
+        assert_eq!(io_state.report(failure(), "Unable to write", start + IO_REPORT_INTERVAL).as_deref(), 
+            Some("Unable to write: disk full (2 more failures not reported)"));
+
+        assert_eq!(io_state.report(Ok(()), "Unable to write", start + IO_REPORT_INTERVAL).as_deref(), 
+            Some("The writer works again, after 4 failed operations"));
+        assert_eq!(io_state.report(Ok(()), "Unable to write", start + IO_REPORT_INTERVAL), None);
+        // A new failure is reported at once.
+        assert!(io_state.report(failure(), "Unable to write", start + IO_REPORT_INTERVAL).is_some());
+        assert!(!io_state.broken());
+    }
+
+    #[test]
+    fn test_broken_pipe_stops_writing() {
+        let buf_writer: Box<RwLock<Sink>> = Box::new(RwLock::new(Sink::new(0, FlushPolicy::Buffered, ClosedPipe)));
+        let io_state = IoState::default();
+
+        BufferedWriter::write_on_this_thread("first", &RecordFraming::default(), &buf_writer, &io_state);
+        assert!(io_state.broken());
+
+        BufferedWriter::write_on_this_thread("second", &RecordFraming::default(), &buf_writer, &io_state);
+        BufferedWriter::flush_on_this_thread(&buf_writer, &io_state);
+    }
+}